@@ -2,14 +2,9 @@
 
 uniform mat4 camera;
 
-// The camera's viewing direction.
-uniform int orbit;
-
-// The direction the model is oriented in world space.
-uniform int direction;
-
-// The origin of the model in world space.
-uniform vec3 origin;
+// Places the model in world space: turns it to face the camera, then moves it to its
+// origin. Built by model::model_transform.
+uniform mat4 model;
 
 // The position of the vertex relative to the model's origin.
 in vec3 position;
@@ -19,30 +14,7 @@ in vec2 uv;
 
 out vec2 vUV;
 
-float pi = 3.1415926536;
-float pi_4 = pi / 4;
-
-/*
-Z rotation matrix:
-
-cos t | -sin t | 0
-sin t |  cos t | 0
-0     |  1     | 1
-
-45 deg = pi / 4
-*/
-
-void main() {  
-  /* Each step in the model's direction is 45 degrees. Each step in the camera's
-  orbit is 90 degrees. We have to ensure the model is always facing the camera. */
-  float theta = pi_4 * ((orbit * 2) - (direction % 2));
-  direction;
-  
-  mat4 rotateZ = mat4( cos(theta), -1 * sin(theta), 0, 0,
-                       sin(theta), cos(theta),      0, 0,
-                       0,          0,               1, 0,
-                       0,          0,               0, 1);
-  gl_Position = camera * ((rotateZ * vec4(position, 1.0)) + vec4(origin, 1.0));
-  
+void main() {
+  gl_Position = camera * model * vec4(position, 1.0);
   vUV = uv;
 }
\ No newline at end of file
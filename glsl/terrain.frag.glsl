@@ -2,7 +2,6 @@
 
 // Configure various constants.
 const float texScale          = 0.05;   // Smaller numbers make the texture appear larger.
-const float waterHeight       = 5;      // Below this Z coord, we use the underwater texture.
 const float waterTransition   = 2.5;    // Size of transition zone from underwater to land texture.
 const float plainMinLevelness = 0.98;   // Threshold dividing plain from slope.
 const float plainTransition   = 0.25;   // Size of transition zone from plain to slope.
@@ -19,8 +18,11 @@ uniform sampler2D plain;      // Texture for plain land.
 uniform sampler2D slope;      // Texture for sloping land. (But not as sloped as cliffs.)
 uniform sampler2D cliff;      // Texture for the most extreme slopes.
 
+uniform float waterHeight;    // Below this Z coord, we use the underwater texture.
+
 uniform uint mouseIn;
 uniform vec3 mousePosition;
+uniform float cursorRadius;   // Radius of the ring drawn around mousePosition.
 
 in vec3 vPosition;
 in vec3 vNormal;
@@ -134,8 +136,8 @@ void main() {
   float cursorMix;
   if (mouseIn == 1u) {
     // Distance from this fragment to the boundary of the circle. The circle is centered
-    // on the cursor. The first parameter of abs is the radius.
-    float d = abs(2.0 - length(vPosition - mousePosition));
+    // on the cursor.
+    float d = abs(cursorRadius - length(vPosition - mousePosition));
     cursorMix = smoothstep(0.5, 0.0, d);
   } else {
     cursorMix = 0.0;
@@ -1,3 +1,7 @@
+# Writes saves/test.city in the current save file format (version 2). See
+# doc/save-file-format.md. The Rust tests check that loading and saving it gives back
+# the same bytes, so keep the two in sync.
+#
 # Usage:
 # python py/make_test_save_file.py
 
@@ -5,17 +9,24 @@ from struct import *
 
 f = open('saves/test.city', 'wb')
 
-name = "Test Save File"
-terrain_path = 'height/test-height-128x128.png'
-meta_things = ['test']
-things = [{'name': 'test', 'direction': 0, 'x': 10, 'y': 5, 'z': 0}]
+version = 2
+name = b'river-128x128'
+water_height = 5.0
+terrain_path = b'assets/height/river-128x128.png'
+vertical_scale = 0.1
+meta_things = [b'jarrett-test']
+# One of each direction, in a row.
+things = [
+  {'name': b'jarrett-test', 'direction': d, 'x': 5 + 3 * d, 'y': 5, 'z': 45}
+  for d in range(8)
+]
 
 # Header size.
 header_size = 2 + 2 + 2 + len(name)
 f.write(pack('!H', header_size))
 
 # File format version.
-f.write(pack('!H', 0))
+f.write(pack('!H', version))
 
 # Name length.
 f.write(pack('!H', len(name)))
@@ -24,9 +35,12 @@ f.write(pack('!H', len(name)))
 f.write(name)
 
 # Terrain section size.
-terrain_size = 4 + 1 + 2 + len(terrain_path)
+terrain_size = 4 + 4 + 1 + 2 + len(terrain_path) + 4
 f.write(pack('!L', terrain_size))
 
+# Water height.
+f.write(pack('!f', water_height))
+
 # Terrain storage strategy.
 f.write(pack('B', 0))
 
@@ -36,6 +50,9 @@ f.write(pack('!H', len(terrain_path)))
 # Terrain path.
 f.write(terrain_path)
 
+# Vertical scale.
+f.write(pack('!f', vertical_scale))
+
 # Meta Things table size.
 meta_things_table_size = 4 + 4
 for meta_thing in meta_things:
@@ -50,9 +67,8 @@ for meta_thing in meta_things:
   f.write(pack('!H', len(meta_thing)))
   f.write(meta_thing)
 
-
 # Thing section size.
-thing_section_size = 4 + 4 + (len(things) * (4 + 1 + 4 + 4 + 4))
+thing_section_size = 4 + 4 + (len(things) * (4 + 1 + 4 + 4 + 4 + 4))
 f.write(pack('!L', thing_section_size))
 
 # Number of Things.
@@ -63,6 +79,8 @@ for thing in things:
   idx = meta_things.index(thing['name'])
   f.write(pack('!L', idx))
   f.write(pack('B', thing['direction']))
-  f.write(pack('fff', thing['x'], thing['y'], thing['z']))
+  f.write(pack('!fff', thing['x'], thing['y'], thing['z']))
+  # Reserved section size. No extension records.
+  f.write(pack('!L', 0))
 
-f.close()
\ No newline at end of file
+f.close()
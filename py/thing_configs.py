@@ -19,11 +19,22 @@ KEYS = [
   "thing_has_pylon",
   "thing_pylon_model",
   "thing_pylon_align",
-  "thing_pylon_repeat"
+  "thing_pylon_repeat",
+  "thing_align_front",
+  "thing_align_back",
+  "thing_align_left",
+  "thing_align_right",
+  "thing_foundation_max_height"
 ]
 
 if __name__ == "__main__":
-  rust_file = open("src/thing_configs.rs", "w")
+  rust_file = open("src/thing/keys.rs", "w")
+  rust_file.write("// Generated by py/thing_configs.py. Do not edit.\n\n")
   for idx, key in enumerate(KEYS):
-    rust_file.write("static %s: u8 = %d;\n" % (key.upper(), idx))
+    rust_file.write("pub const %s: u8 = %d;\n" % (key.upper(), idx))
+  rust_file.write("\n// Names used for each key in .thing.json files, indexed by key.\n")
+  rust_file.write("pub const NAMES: [&'static str; %d] = [\n" % len(KEYS))
+  for key in KEYS:
+    rust_file.write("  \"%s\",\n" % key)
+  rust_file.write("];\n")
   rust_file.close()
\ No newline at end of file
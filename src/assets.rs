@@ -0,0 +1,270 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use errors::GameError;
+use model::{MetaModel, Images, DIRECTIONS};
+use opengl::{Texture2d, TextureConfig};
+use thing::MetaThing;
+
+// Every MetaModel and MetaThing the game knows about, indexed by qualified name, e.g.
+// "jarrett-test". Built once at startup by scanning the models and things folders,
+// including any subfolders.
+pub struct AssetRegistry {
+  models: Vec<ModelEntry>,
+  things: Vec<ThingEntry>,
+  model_ids: HashMap<String, usize>,
+  thing_ids: HashMap<String, usize>
+}
+
+struct ModelEntry {
+  meta_model: MetaModel,
+
+  // Folder containing the .model file, where external images live.
+  folder: PathBuf,
+
+  // One slot per direction, filled in the first time that direction is drawn. Loading
+  // every texture at startup would waste video memory on models that are never placed.
+  textures: RefCell<Vec<Option<Rc<Texture2d>>>>
+}
+
+struct ThingEntry {
+  meta_thing: MetaThing,
+
+  // IDs of the MetaModels in meta_thing.models, in the same order.
+  model_ids: Vec<usize>
+}
+
+impl AssetRegistry {
+  // Loads every .model file under assets/models and every .thing file under assets/things, then
+  // checks that each Thing's models exist. Rather than stopping at the first bad asset,
+  // we report all missing, duplicate, and unreadable assets in one error.
+  pub fn load(assets: &Path) -> Result<AssetRegistry, GameError> {
+    let mut registry = AssetRegistry {
+      models: Vec::new(), things: Vec::new(), model_ids: HashMap::new(), thing_ids: HashMap::new()
+    };
+    let mut problems: Vec<String> = Vec::new();
+
+    let models_folder = assets.join("models");
+    for path in list_assets(&models_folder, "model", &mut problems) {
+      match MetaModel::from_file(&path) {
+        Ok(meta_model) => {
+          let name = meta_model.qualified_name();
+          if !check_file_name(&path, &name, "model", &mut problems) {
+            continue;
+          }
+          if registry.model_ids.contains_key(&name) {
+            problems.push(format!("Duplicate model {} in {}", name, path.display()));
+            continue;
+          }
+          registry.model_ids.insert(name, registry.models.len());
+          registry.models.push(ModelEntry {
+            meta_model: meta_model,
+            folder: path.parent().unwrap_or(&models_folder).to_path_buf(),
+            textures: RefCell::new(Vec::new())
+          });
+        },
+        Err(err) => problems.push(format!("{}: {}", path.display(), err))
+      }
+    }
+
+    for path in list_assets(&assets.join("things"), "thing", &mut problems) {
+      match MetaThing::from_file(&path) {
+        Ok(meta_thing) => {
+          let name = meta_thing.qualified_name();
+          if !check_file_name(&path, &name, "thing", &mut problems) {
+            continue;
+          }
+          if registry.thing_ids.contains_key(&name) {
+            problems.push(format!("Duplicate thing {} in {}", name, path.display()));
+            continue;
+          }
+          let mut model_ids: Vec<usize> = Vec::with_capacity(meta_thing.models.len());
+          for model in meta_thing.models.iter() {
+            match registry.model_ids.get(&model.qualified_name()) {
+              Some(&id) => model_ids.push(id),
+              None => problems.push(format!(
+                "Thing {} uses model {}, which doesn't exist", name, model.qualified_name()
+              ))
+            }
+          }
+          let config = &meta_thing.config;
+          for model in [&config.main_model, &config.footer_model, &config.pylon_model].iter() {
+            if let Some(ref model) = **model {
+              if !registry.model_ids.contains_key(model) {
+                problems.push(format!(
+                  "Thing {} is configured with model {}, which doesn't exist", name, model
+                ));
+              }
+            }
+          }
+          registry.thing_ids.insert(name, registry.things.len());
+          registry.things.push(ThingEntry {meta_thing: meta_thing, model_ids: model_ids});
+        },
+        Err(err) => problems.push(format!("{}: {}", path.display(), err))
+      }
+    }
+
+    if problems.is_empty() {
+      Ok(registry)
+    } else {
+      Err(GameError::Invalid(format!(
+        "Found {} problems with the assets in {}:\n  {}",
+        problems.len(), assets.display(), problems.join("\n  ")
+      )))
+    }
+  }
+
+  pub fn model_id(&self, name: &str) -> Option<usize> {
+    self.model_ids.get(name).cloned()
+  }
+
+  // Model IDs run from 0 up to this.
+  pub fn model_count(&self) -> usize {
+    self.models.len()
+  }
+
+  pub fn model(&self, id: usize) -> &MetaModel {
+    &self.models[id].meta_model
+  }
+
+  pub fn thing(&self, name: &str) -> Option<&MetaThing> {
+    self.thing_ids.get(name).map(|&id| &self.things[id].meta_thing)
+  }
+
+  // IDs of the MetaModels the named MetaThing is drawn with, in the same order as its
+  // models field. These were resolved when the registry was loaded, so they're always
+  // valid.
+  pub fn thing_model_ids(&self, name: &str) -> Option<&[usize]> {
+    self.thing_ids.get(name).map(|&id| &self.things[id].model_ids[..])
+  }
+
+  // Returns the texture for one direction of a model, loading it on first use. 2d models
+  // only have direction 0. Must be called on the thread that owns the OpenGL context.
+  pub fn texture(&self, model_id: usize, direction: usize) -> Result<Rc<Texture2d>, GameError> {
+    let entry = &self.models[model_id];
+    let count = if entry.meta_model.is_3d() { DIRECTIONS } else { 1 };
+    if direction >= count {
+      return Err(GameError::Invalid(format!(
+        "Model {} has no direction {}", entry.meta_model.qualified_name(), direction
+      )));
+    }
+    let mut textures = entry.textures.borrow_mut();
+    if textures.is_empty() {
+      textures.resize(count, None);
+    }
+    if let Some(ref texture) = textures[direction] {
+      return Ok(texture.clone());
+    }
+    let config = TextureConfig::default();
+    let texture = Rc::new(match entry.meta_model.images {
+      Images::Embedded(ref images) => try!(Texture2d::from_memory(&images[direction], &config)),
+      Images::External => try!(Texture2d::from_file(
+        &entry.meta_model.external_image_path(&entry.folder, direction), &config
+      ))
+    });
+    textures[direction] = Some(texture.clone());
+    Ok(texture)
+  }
+}
+
+// Lists the files under folder with the given extension, sorted so that duplicates are
+// always reported the same way.
+// Like files_with_extension, but reports a missing or unreadable folder as a problem so
+// loading can carry on with the other folder.
+fn list_assets(folder: &Path, extension: &str, problems: &mut Vec<String>) -> Vec<PathBuf> {
+  match files_with_extension(folder, extension) {
+    Ok(paths) => paths,
+    Err(err) => {
+      problems.push(format!("{}: {}", folder.display(), err));
+      Vec::new()
+    }
+  }
+}
+
+fn files_with_extension(folder: &Path, extension: &str) -> Result<Vec<PathBuf>, GameError> {
+  let mut paths: Vec<PathBuf> = Vec::new();
+  for entry in try!(fs::read_dir(folder)) {
+    let path = try!(entry).path();
+    if path.is_dir() {
+      paths.extend(try!(files_with_extension(&path, extension)));
+    } else if path.extension().and_then(|e| e.to_str()) == Some(extension) {
+      paths.push(path);
+    }
+  }
+  paths.sort();
+  Ok(paths)
+}
+
+// Files must be named after the asset they contain, e.g. jarrett-test.model, because
+// that's how the compiler and the inspector find them.
+fn check_file_name(path: &Path, name: &str, extension: &str, problems: &mut Vec<String>) -> bool {
+  let expected = format!("{}.{}", name, extension);
+  if path.file_name().and_then(|n| n.to_str()) == Some(&expected) {
+    true
+  } else {
+    problems.push(format!("{} contains {}, so it should be named {}", path.display(), name, expected));
+    false
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use std::path::Path;
+  use errors::GameError;
+  use testing;
+  use super::*;
+
+  #[test]
+  fn load_assets() {
+    let registry = AssetRegistry::load(&Path::new("assets")).unwrap();
+    let model_id = registry.model_id("jarrett-test").unwrap();
+    assert_eq!("jarrett-test", registry.model(model_id).qualified_name());
+    assert!(registry.thing("jarrett-test").is_some());
+    assert_eq!(Some(&[model_id][..]), registry.thing_model_ids("jarrett-test"));
+    assert!(registry.thing("nobody-nothing").is_none());
+  }
+
+  #[test]
+  fn problems_reported_together() {
+    let dir = testing::scratch_dir("asset-registry-problems");
+    let folder = dir.as_path();
+    fs::create_dir_all(folder.join("models")).unwrap();
+    fs::create_dir_all(folder.join("things/more")).unwrap();
+    // A thing whose model is missing, a copy of it under the wrong name, and a duplicate.
+    let thing = "assets/things/jarrett-test.thing";
+    fs::copy(thing, folder.join("things/jarrett-test.thing")).unwrap();
+    fs::copy(thing, folder.join("things/jarrett-copy.thing")).unwrap();
+    fs::copy(thing, folder.join("things/more/jarrett-test.thing")).unwrap();
+    match AssetRegistry::load(folder) {
+      Err(GameError::Invalid(msg)) => {
+        assert!(msg.starts_with("Found 3 problems"));
+        assert!(msg.contains("Duplicate thing jarrett-test"));
+        assert!(msg.contains("should be named jarrett-test.thing"));
+        assert!(msg.contains("uses model jarrett-test, which doesn't exist"));
+      },
+      _ => panic!("Expected asset problems")
+    }
+    fs::remove_dir_all(folder).unwrap();
+  }
+
+  #[test]
+  fn missing_folders() {
+    let dir = testing::scratch_dir("asset-registry-missing");
+    fs::create_dir_all(dir.join("things")).unwrap();
+    fs::copy("assets/things/jarrett-test.thing", dir.join("things/jarrett-test.thing")).unwrap();
+    // The missing models folder is reported along with the thing that needs it.
+    match AssetRegistry::load(&dir) {
+      Err(GameError::Invalid(msg)) => {
+        assert!(msg.starts_with("Found 2 problems"));
+        assert!(msg.contains(&dir.join("models").display().to_string()));
+        assert!(msg.contains("uses model jarrett-test, which doesn't exist"));
+      },
+      _ => panic!("Expected asset problems")
+    }
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,253 @@
+extern crate cities;
+
+use std::env;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+
+use cities::compiler;
+use cities::errors::GameError;
+use cities::save;
+use cities::terrain::{ByteOrder, Dem, DemImport, Erosion, Generator};
+use cities::world::{World, TerrainStorage};
+
+const USAGE: &'static str = "\
+Usage:
+  cities-assets model <folder> <author> <name>
+  cities-assets thing <folder> <author> <name>
+  cities-assets slope-map <save file> <output png> [full scale slope]
+  cities-assets generate <seed> <output .city or .png> [options]
+  cities-assets erode <save file> <output save file> [options]
+  cities-assets import-dem <.asc, .png or .raw DEM> <output save file> [options]
+
+Options for generate:
+  --size <vertices>      Vertices along each side. Default 257.
+  --max-height <z>       Height of the tallest possible peak. Default 40.
+  --sea-level <z>        Z coord of the water surface. Default 8.
+  --mountains <amount>   From 0 (hills only) to 1. Default 0.5.
+  --rivers <count>       Number of rivers to carve. Default 3.
+
+Options for erode:
+  --seed <seed>          Default 0.
+  --thermal <passes>     Passes of thermal weathering. Default 20.
+  --droplets <count>     Raindrops for hydraulic erosion. Default 50000.
+
+Options for import-dem:
+  --size <x>x<y>         Vertices to resample to. Default is the DEM's own size.
+  --min-elevation <e>    Elevation at Z = 0. Default is the lowest in the DEM.
+  --max-elevation <e>    Elevation at the max height. Default is the highest in the DEM.
+  --max-height <z>       Z coord of the max elevation. Default 40.
+  --sea-level <e>        Elevation of the water surface. Default 0.
+  --raw-size <x>x<y>     Samples in a .raw DEM. Required for .raw files.
+  --byte-order <order>   little or big, for .raw DEMs. Default little.
+
+Examples:
+  cities-assets model assets/models jarrett test
+  cities-assets slope-map saves/test.city slope.png 2.0
+  cities-assets generate 1234 assets/height/island-1234.png --mountains 0.8
+  cities-assets erode saves/test.city saves/test-eroded.city --droplets 200000
+  cities-assets import-dem bay.asc saves/bay.city --size 257x257 --max-elevation 400";
+
+// Compiles asset sources into the binary formats the game loads, and exports data about
+// the terrain in save files. See compiler/mod.rs.
+//
+// generate writes either a save file with the heights stored inline, or a 16-bit
+// heightmap PNG. Load the PNG with a vertical scale of max height / 255. erode always
+// stores the eroded heights inline, even if the original save used a heightmap image.
+// import-dem stores the imported heights inline too.
+fn main() {
+  let args: Vec<String> = env::args().collect();
+  let command = args.get(1).map_or("", |c| c.as_ref());
+  let result = match (command, args.len()) {
+    ("model", 5) => compiler::compile_model(&Path::new(&args[2]), &args[3], &args[4]),
+    ("thing", 5) => compiler::compile_thing(&Path::new(&args[2]), &args[3], &args[4]),
+    ("slope-map", 4) | ("slope-map", 5) => {
+      let full_scale: f32 = match args.get(4).map_or(Ok(1.0), |s| s.parse()) {
+        Ok(full_scale) => full_scale,
+        Err(_) => usage()
+      };
+      let out = Path::new(&args[3]);
+      save::load(&Path::new(&args[2]))
+        .and_then(|world| world.terrain.slope_grid().write_image(&out, full_scale))
+        .map(|_| out.to_path_buf())
+    },
+    ("generate", n) if n >= 4 => {
+      let generator = parse_generator(&args[2], &args[4..]);
+      let out = Path::new(&args[3]);
+      write_generated(&generator, &out).map(|_| out.to_path_buf())
+    },
+    ("erode", n) if n >= 4 => {
+      let erosion = parse_erosion(&args[4..]);
+      let out = Path::new(&args[3]);
+      erode(&erosion, &Path::new(&args[2]), &out).map(|_| out.to_path_buf())
+    },
+    ("import-dem", n) if n >= 4 => {
+      let out = Path::new(&args[3]);
+      import_dem(&Path::new(&args[2]), &out, &args[4..]).map(|_| out.to_path_buf())
+    },
+    _ => usage()
+  };
+  match result {
+    Ok(path) => println!("Wrote {}", path.display()),
+    Err(err) => {
+      eprintln!("{}", err);
+      process::exit(1);
+    }
+  }
+}
+
+fn usage() -> ! {
+  eprintln!("{}", USAGE);
+  process::exit(2);
+}
+
+fn parse_generator(seed: &str, options: &[String]) -> Generator {
+  let mut generator = Generator::default();
+  generator.seed = seed.parse().unwrap_or_else(|_| usage());
+  for pair in options.chunks(2) {
+    let value: &str = match pair.get(1) {
+      Some(value) => value,
+      None => usage()
+    };
+    match pair[0].as_ref() {
+      "--size" => {
+        let size = value.parse().unwrap_or_else(|_| usage());
+        generator.x_verts = size;
+        generator.y_verts = size;
+      },
+      "--max-height" => generator.max_height = value.parse().unwrap_or_else(|_| usage()),
+      "--sea-level" => generator.sea_level = value.parse().unwrap_or_else(|_| usage()),
+      "--mountains" => generator.mountains = value.parse().unwrap_or_else(|_| usage()),
+      "--rivers" => generator.rivers = value.parse().unwrap_or_else(|_| usage()),
+      _ => usage()
+    }
+  }
+  if generator.x_verts < 2 {
+    usage();
+  }
+  generator
+}
+
+// Writes a heightmap PNG if out ends in .png, and a save file otherwise.
+fn write_generated(generator: &Generator, out: &Path) -> Result<(), GameError> {
+  let terrain = generator.generate();
+  let is_png = out.extension().and_then(|e| e.to_str()).map_or(false, |e|
+    e.eq_ignore_ascii_case("png")
+  );
+  if is_png {
+    terrain.write_image(out, generator.max_height / 255.0)
+  } else {
+    let world = World {
+      name: format!("Generated {}", generator.seed),
+      terrain: terrain, terrain_storage: TerrainStorage::Inline,
+      water_height: generator.sea_level, things: Vec::new()
+    };
+    save::write(out, &world)
+  }
+}
+
+fn parse_erosion(options: &[String]) -> Erosion {
+  let mut erosion = Erosion::default();
+  for pair in options.chunks(2) {
+    let value: &str = match pair.get(1) {
+      Some(value) => value,
+      None => usage()
+    };
+    match pair[0].as_ref() {
+      "--seed" => erosion.seed = value.parse().unwrap_or_else(|_| usage()),
+      "--thermal" => erosion.thermal_iterations = value.parse().unwrap_or_else(|_| usage()),
+      "--droplets" => erosion.droplets = value.parse().unwrap_or_else(|_| usage()),
+      _ => usage()
+    }
+  }
+  erosion
+}
+
+// Erodes the terrain in a save file, printing progress as it goes.
+fn erode(erosion: &Erosion, input: &Path, out: &Path) -> Result<(), GameError> {
+  let mut world = try!(save::load(input));
+  let mut reported = 0;
+  erosion.run(&mut world.terrain, &mut |fraction| {
+    let percent = (fraction * 100.0) as u32;
+    if percent > reported {
+      eprint!("\rEroding: {}%", percent);
+      let _ = io::stderr().flush();
+      reported = percent;
+    }
+    true
+  });
+  eprintln!();
+  world.terrain_storage = TerrainStorage::Inline;
+  save::write(out, &world)
+}
+
+// Reads a DEM in whichever format its extension says, resamples it and writes it to a
+// save file, with the water at the sea level.
+fn import_dem(input: &Path, out: &Path, options: &[String]) -> Result<(), GameError> {
+  let mut size: Option<(usize, usize)> = None;
+  let mut min_elevation: Option<f32> = None;
+  let mut max_elevation: Option<f32> = None;
+  let mut max_height = 40.0;
+  let mut sea_level = 0.0;
+  let mut raw_size: Option<(usize, usize)> = None;
+  let mut order = ByteOrder::Little;
+  for pair in options.chunks(2) {
+    let value: &str = match pair.get(1) {
+      Some(value) => value,
+      None => usage()
+    };
+    match pair[0].as_ref() {
+      "--size" => size = Some(parse_size(value)),
+      "--min-elevation" => min_elevation = Some(value.parse().unwrap_or_else(|_| usage())),
+      "--max-elevation" => max_elevation = Some(value.parse().unwrap_or_else(|_| usage())),
+      "--max-height" => max_height = value.parse().unwrap_or_else(|_| usage()),
+      "--sea-level" => sea_level = value.parse().unwrap_or_else(|_| usage()),
+      "--raw-size" => raw_size = Some(parse_size(value)),
+      "--byte-order" => order = match value {
+        "little" => ByteOrder::Little,
+        "big" => ByteOrder::Big,
+        _ => usage()
+      },
+      _ => usage()
+    }
+  }
+
+  let extension = input.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+  let dem = try!(match extension.as_ref() {
+    "asc" => Dem::from_asc(input),
+    "png" => Dem::from_png(input),
+    "raw" => match raw_size {
+      Some((columns, rows)) => Dem::from_raw(input, columns, rows, order),
+      None => usage()
+    },
+    _ => usage()
+  });
+
+  let mut import = DemImport::for_dem(&dem, max_height);
+  if let Some((x_verts, y_verts)) = size {
+    import.x_verts = x_verts;
+    import.y_verts = y_verts;
+  }
+  import.min_elevation = min_elevation.unwrap_or(import.min_elevation);
+  import.max_elevation = max_elevation.unwrap_or(import.max_elevation);
+  import.sea_level = sea_level;
+  let world = World {
+    name: input.file_stem().and_then(|s| s.to_str()).unwrap_or("Imported").to_string(),
+    terrain: try!(import.terrain(&dem)), terrain_storage: TerrainStorage::Inline,
+    water_height: import.water_height(), things: Vec::new()
+  };
+  save::write(out, &world)
+}
+
+// Parses "<x>x<y>", or a single number for a square.
+fn parse_size(value: &str) -> (usize, usize) {
+  let parts: Vec<usize> = value.split('x')
+    .map(|part| part.parse().unwrap_or_else(|_| usage()))
+    .collect();
+  match parts.len() {
+    1 => (parts[0], parts[0]),
+    2 => (parts[0], parts[1]),
+    _ => usage()
+  }
+}
\ No newline at end of file
@@ -0,0 +1,76 @@
+extern crate cities;
+extern crate rustc_serialize;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process;
+use rustc_serialize::json::{self, Json};
+
+use cities::inspect;
+use cities::inspect::Kind;
+
+const USAGE: &'static str = "\
+Usage:
+  cities-inspect [--json] [--assets <folder>] <file>...
+
+Files must end in .city, .model, or .thing. With --assets, references to other assets are
+checked against <folder>/models and <folder>/things.
+
+Example:
+  cities-inspect --assets assets saves/test.city";
+
+// Prints what's inside save and asset files. Exits with status 1 if any file has problems.
+fn main() {
+  let mut json_output = false;
+  let mut assets: Option<String> = None;
+  let mut paths: Vec<String> = Vec::new();
+  let mut args = env::args().skip(1);
+  while let Some(arg) = args.next() {
+    match arg.as_ref() {
+      "--json" => json_output = true,
+      "--assets" => assets = args.next(),
+      _ if arg.starts_with("-") => usage(),
+      _ => paths.push(arg)
+    }
+  }
+  if paths.is_empty() {
+    usage();
+  }
+
+  let assets = assets.as_ref().map(|a| Path::new(a));
+  let mut healthy = true;
+  let mut reports: BTreeMap<String, Json> = BTreeMap::new();
+  for path in paths.iter() {
+    let kind = match Kind::from_path(&Path::new(path)) {
+      Some(kind) => kind,
+      None => {
+        eprintln!("{}: not a .city, .model, or .thing file", path);
+        process::exit(2);
+      }
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    if let Err(err) = File::open(path).and_then(|mut file| file.read_to_end(&mut bytes)) {
+      eprintln!("{}: {}", path, err);
+      process::exit(2);
+    }
+    let report = inspect::inspect(kind, &bytes, assets);
+    healthy = healthy && report.problems.is_empty();
+    if json_output {
+      reports.insert(path.clone(), report.to_json());
+    } else {
+      println!("{}: {}", path, report.to_text());
+    }
+  }
+  if json_output {
+    println!("{}", json::as_pretty_json(&Json::Object(reports)));
+  }
+  process::exit(if healthy { 0 } else { 1 });
+}
+
+fn usage() -> ! {
+  eprintln!("{}", USAGE);
+  process::exit(2);
+}
\ No newline at end of file
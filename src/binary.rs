@@ -0,0 +1,263 @@
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str;
+
+use errors::GameError;
+
+// Helpers for the binary file formats described in doc/. All multi-byte values are big-
+// endian. Every section begins with its own size in bytes, and that size includes the
+// size field itself.
+
+// Reads values from a byte slice, tracking the position as it goes. A Reader can be
+// narrowed to a single section, in which case reading past the end of the section is an
+// error even if the file continues.
+pub struct Reader<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+
+  // Offset of bytes[0] from the start of the file. Only used for error reporting.
+  base: usize,
+
+  section: &'static str
+}
+
+impl <'a> Reader<'a> {
+  pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+    Reader {bytes: bytes, pos: 0, base: 0, section: "file"}
+  }
+
+  // Current position, measured from the start of the file.
+  pub fn offset(&self) -> usize {
+    self.base + self.pos
+  }
+
+  pub fn remaining(&self) -> usize {
+    self.bytes.len() - self.pos
+  }
+
+  pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], GameError> {
+    if len > self.remaining() {
+      return Err(GameError::Truncated {
+        section: self.section, offset: self.offset(), wanted: len - self.remaining()
+      });
+    }
+    let bytes: &'a [u8] = &self.bytes[self.pos..self.pos + len];
+    self.pos += len;
+    Ok(bytes)
+  }
+
+  pub fn u8(&mut self) -> Result<u8, GameError> {
+    let b = try!(self.bytes(1));
+    Ok(b[0])
+  }
+
+  pub fn u16(&mut self) -> Result<u16, GameError> {
+    let b = try!(self.bytes(2));
+    Ok((b[0] as u16) << 8 | b[1] as u16)
+  }
+
+  pub fn u32(&mut self) -> Result<u32, GameError> {
+    let b = try!(self.bytes(4));
+    Ok((b[0] as u32) << 24 | (b[1] as u32) << 16 | (b[2] as u32) << 8 | b[3] as u32)
+  }
+
+  pub fn f32(&mut self) -> Result<f32, GameError> {
+    let bits = try!(self.u32());
+    Ok(f32::from_bits(bits))
+  }
+
+  // A string prefixed with its length as a u16. Must be ASCII.
+  pub fn ascii(&mut self) -> Result<String, GameError> {
+    let offset = self.offset();
+    let string = try!(self.utf8());
+    if !string.is_ascii() {
+      return Err(GameError::Invalid(format!(
+        "Expected ASCII string in {} section at offset {}", self.section, offset
+      )));
+    }
+    Ok(string)
+  }
+
+  // A string prefixed with its length as a u16.
+  pub fn utf8(&mut self) -> Result<String, GameError> {
+    let offset = self.offset();
+    let len = try!(self.u16()) as usize;
+    let bytes = try!(self.bytes(len));
+    match str::from_utf8(bytes) {
+      Ok(s) => Ok(s.to_string()),
+      Err(_) => Err(GameError::Invalid(format!(
+        "Invalid UTF-8 string in {} section at offset {}", self.section, offset
+      )))
+    }
+  }
+
+  // Reads a section whose size is given by a leading u16. Returns a Reader over just that
+  // section. The size field has already been consumed from the returned Reader.
+  pub fn section_u16(&mut self, section: &'static str) -> Result<Reader<'a>, GameError> {
+    let start = self.pos;
+    let declared = try!(self.u16()) as usize;
+    self.section_body(section, start, declared)
+  }
+
+  // Like section_u16, but the size is a u32.
+  pub fn section_u32(&mut self, section: &'static str) -> Result<Reader<'a>, GameError> {
+    let start = self.pos;
+    let declared = try!(self.u32()) as usize;
+    self.section_body(section, start, declared)
+  }
+
+  // Returns a Reader over the next len bytes, for data whose size is given separately
+  // rather than by a leading size field.
+  pub fn take(&mut self, section: &'static str, len: usize) -> Result<Reader<'a>, GameError> {
+    let start = self.pos;
+    try!(self.bytes(len));
+    Ok(Reader {
+      bytes: &self.bytes[start..start + len],
+      pos: 0,
+      base: self.base + start,
+      section: section
+    })
+  }
+
+  // Call when finished reading a section. Errors if any of the section's declared bytes
+  // went unread.
+  pub fn finish(self) -> Result<(), GameError> {
+    if self.remaining() != 0 {
+      return Err(GameError::SectionSize {
+        section: self.section, declared: self.bytes.len(), actual: self.pos
+      });
+    }
+    Ok(())
+  }
+
+  fn section_body(
+    &mut self, section: &'static str, start: usize, declared: usize
+  ) -> Result<Reader<'a>, GameError> {
+    let header_len = self.pos - start;
+    if declared < header_len || start + declared > self.bytes.len() {
+      return Err(GameError::SectionSize {
+        section: section, declared: declared, actual: self.bytes.len() - start
+      });
+    }
+    let reader = Reader {
+      bytes: &self.bytes[start..start + declared],
+      pos: header_len,
+      base: self.base + start,
+      section: section
+    };
+    self.pos = start + declared;
+    Ok(reader)
+  }
+}
+
+// Builds a byte buffer in the same format Reader reads.
+pub struct Writer {
+  bytes: Vec<u8>
+}
+
+impl Writer {
+  pub fn new() -> Writer {
+    Writer {bytes: Vec::new()}
+  }
+
+  pub fn into_bytes(self) -> Vec<u8> {
+    self.bytes
+  }
+
+  pub fn bytes(&mut self, bytes: &[u8]) {
+    self.bytes.extend_from_slice(bytes);
+  }
+
+  pub fn u8(&mut self, value: u8) {
+    self.bytes.push(value);
+  }
+
+  pub fn u16(&mut self, value: u16) {
+    self.bytes.push((value >> 8) as u8);
+    self.bytes.push(value as u8);
+  }
+
+  pub fn u32(&mut self, value: u32) {
+    self.bytes.push((value >> 24) as u8);
+    self.bytes.push((value >> 16) as u8);
+    self.bytes.push((value >> 8) as u8);
+    self.bytes.push(value as u8);
+  }
+
+  pub fn f32(&mut self, value: f32) {
+    self.u32(value.to_bits());
+  }
+
+  // Writes a string prefixed with its length as a u16. Errors if the string is too long.
+  pub fn string(&mut self, string: &str) -> Result<(), GameError> {
+    if string.len() > 0xffff {
+      return Err(GameError::Invalid(format!(
+        "String of {} bytes is too long. The limit is 65535.", string.len()
+      )));
+    }
+    self.u16(string.len() as u16);
+    self.bytes(string.as_bytes());
+    Ok(())
+  }
+
+  // Starts a section whose size is a u16. Write the section's contents, then pass the
+  // returned mark to end_section_u16, which fills in the size.
+  pub fn begin_section_u16(&mut self) -> usize {
+    let mark = self.bytes.len();
+    self.u16(0);
+    mark
+  }
+
+  pub fn end_section_u16(&mut self, mark: usize) -> Result<(), GameError> {
+    let size = self.bytes.len() - mark;
+    if size > 0xffff {
+      return Err(GameError::Invalid(format!(
+        "Section of {} bytes is too long. The limit is 65535.", size
+      )));
+    }
+    self.bytes[mark] = (size >> 8) as u8;
+    self.bytes[mark + 1] = size as u8;
+    Ok(())
+  }
+
+  // Like begin_section_u16, but the size is a u32.
+  pub fn begin_section_u32(&mut self) -> usize {
+    let mark = self.bytes.len();
+    self.u32(0);
+    mark
+  }
+
+  pub fn end_section_u32(&mut self, mark: usize) -> Result<(), GameError> {
+    let size = self.bytes.len() - mark;
+    if size > 0xffffffff {
+      return Err(GameError::Invalid(format!(
+        "Section of {} bytes is too long. The limit is 4294967295.", size
+      )));
+    }
+    self.bytes[mark] = (size >> 24) as u8;
+    self.bytes[mark + 1] = (size >> 16) as u8;
+    self.bytes[mark + 2] = (size >> 8) as u8;
+    self.bytes[mark + 3] = size as u8;
+    Ok(())
+  }
+}
+
+// Writes bytes to path. We write to a temporary file first and then rename it, so a crash
+// partway through never leaves a corrupt file in place of a good one.
+pub fn write_file(path: &Path, bytes: &[u8]) -> Result<(), GameError> {
+  let mut tmp_name = path.file_name().map_or_else(
+    || "file".into(), |name| name.to_os_string()
+  );
+  tmp_name.push(".tmp");
+  let tmp_path: PathBuf = path.with_file_name(tmp_name);
+
+  {
+    let mut file = try!(File::create(&tmp_path));
+    try!(file.write_all(bytes));
+    try!(file.sync_all());
+  }
+  try!(fs::rename(&tmp_path, path));
+  Ok(())
+}
\ No newline at end of file
@@ -1,7 +1,7 @@
 use std::ops::Neg;
 use cgmath::{
-  EuclideanSpace, Matrix, Matrix3, Matrix4, Ortho, Point2, Point3,
-  Rad, SquareMatrix, Transform, Vector2, Vector3
+  Matrix, Matrix3, Matrix4, Ortho, Point2, Point3,
+  Rad, SquareMatrix, Transform, Vector2
 };
 use glfw::{Window, Action, Key};
 
@@ -49,6 +49,12 @@ impl Camera {
     self.transform.transform_point(point.clone()).z
   }
   
+  // Centers the view on the given point of the XY plane.
+  pub fn focus_on(&mut self, focus: Vector2<f32>) {
+    self.focus = focus;
+    self.rebuild_matrices();
+  }
+  
   pub fn increment_orbit(&mut self) {
     if self.orbit == 3 {
       self.orbit_to(0);
@@ -123,19 +129,17 @@ impl Camera {
   // the lower right is (self.width, self.height).
   pub fn unproject(&self, p: Point2<f32>) -> (Point3<f32>, Point3<f32>) {
     // Convert to OpenGL clip space, i.e. [-1, 1].
-    let v1: Vector3<f32> = Vector3::new(
+    let p1: Point3<f32> = Point3::new(
       p.x *  2.0 / self.width  as f32 - 1.0,
       p.y * -2.0 / self.height as f32 + 1.0,
       -1.0
     );
     
-    let mut v2: Vector3<f32> = v1.clone();
-    v2.z = 1.0;
+    let mut p2: Point3<f32> = p1.clone();
+    p2.z = 1.0;
     
-    (
-      Point3::from_vec(self.inverse.transform_vector(v1)),
-      Point3::from_vec(self.inverse.transform_vector(v2))
-    )
+    // Points, not vectors, so the focus translation is undone too.
+    (self.inverse.transform_point(p1), self.inverse.transform_point(p2))
   }
   
   pub fn zoom_by(&mut self, multiplier: f32) {
@@ -179,4 +183,20 @@ impl Camera {
     
     self.inverse = self.transform.invert().unwrap();
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn unproject_through_focus() {
+    let mut camera = Camera::new(800, 600, 0.1);
+    camera.focus_on(Vector2::new(30.0, -12.0));
+    let (a, b) = camera.unproject(Point2::new(400.0, 300.0));
+    // Where the ray crosses the XY plane.
+    let t = a.z / (a.z - b.z);
+    let hit = a + (b - a) * t;
+    assert!((hit.x - 30.0).abs() < 0.001 && (hit.y + 12.0).abs() < 0.001, "{:?}", hit);
+  }
+}
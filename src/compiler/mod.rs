@@ -0,0 +1,60 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use rustc_serialize::json::Json;
+
+use errors::GameError;
+
+mod model;
+mod thing;
+
+pub use self::model::{build_model, compile_model};
+pub use self::thing::{build_thing, compile_thing};
+
+// The asset compiler turns the JSON files produced by our Blender scripts and written by
+// hand into the binary formats described in doc/. It uses the same writers as the game,
+// so anything it produces is guaranteed to load.
+// 
+// Rather than stopping at the first problem in the input, the compiler collects all of
+// them and reports them together.
+
+// Checks that a name follows the rules in doc/model-file-format.md: lowercase English
+// letters, numbers, and dashes.
+fn check_name(what: &str, name: &str, problems: &mut Vec<String>) {
+  let valid = !name.is_empty() && name.chars().all(|c|
+    (c >= 'a' && c <= 'z') || (c >= '0' && c <= '9') || c == '-'
+  );
+  if !valid {
+    problems.push(format!(
+      "{} \"{}\" must be non-empty and contain only lowercase letters, numbers, and dashes",
+      what, name
+    ));
+  }
+}
+
+fn read_json(path: &Path) -> Result<Json, GameError> {
+  let mut source = String::new();
+  let mut file = try!(File::open(path));
+  try!(file.read_to_string(&mut source));
+  Json::from_str(&source).map_err(|err|
+    GameError::Invalid(format!("Couldn't parse {}: {}", path.display(), err))
+  )
+}
+
+fn f32_field(json: &Json, key: &str, problems: &mut Vec<String>) -> f32 {
+  match json.find(key).and_then(|j| j.as_f64()) {
+    Some(value) => value as f32,
+    None => {
+      problems.push(format!("Missing number \"{}\"", key));
+      0.0
+    }
+  }
+}
+
+// Builds a single error listing every problem found. name is the qualified name of the
+// asset being compiled.
+fn problems_error(name: &str, problems: Vec<String>) -> GameError {
+  GameError::Invalid(format!(
+    "Problems in the sources for {}:\n  {}", name, problems.join("\n  ")
+  ))
+}
\ No newline at end of file
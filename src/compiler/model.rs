@@ -0,0 +1,170 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use cgmath::Point2;
+use rustc_serialize::json::Json;
+
+use binary;
+use errors::GameError;
+use model::{MetaModel, Shape, Images, Uvs3d, DIRECTIONS};
+use super::{check_name, read_json, f32_field, problems_error};
+
+// Builds a MetaModel from the two JSON files for a model:
+// 
+// - author-name.model.blendout.json, exported from Blender. For a 3d model, it has
+//   xSize, ySize, zSize, and a "directions" object with an entry for each direction
+//   "0" through "7". Each entry maps the vertex labels in doc/model-rendering.md
+//   (lowercase) to [u, v] pairs. For a 2d model, it has width, height, tl, and br.
+// 
+// - author-name.model.config.json, written by hand. It has author_name, model_name,
+//   shape (0 for 3d, 1 for 2d), and optionally embed_images. If embed_images is true,
+//   the sprite images are read from the same folder and stored in the .model file.
+pub fn build_model(folder: &Path, author_name: &str, model_name: &str) -> Result<MetaModel, GameError> {
+  let base = format!("{}-{}", author_name, model_name);
+  // The names make up the file names, so check them before looking for the files.
+  let mut problems: Vec<String> = Vec::new();
+  check_name("Author name", author_name, &mut problems);
+  check_name("Model name", model_name, &mut problems);
+  if !problems.is_empty() {
+    return Err(problems_error(&base, problems));
+  }
+  let blendout_path = folder.join(format!("{}.model.blendout.json", base));
+  let config_path = folder.join(format!("{}.model.config.json", base));
+  let blendout = try!(read_json(&blendout_path));
+  let config = try!(read_json(&config_path));
+
+  for &(key, expected) in [("author_name", author_name), ("model_name", model_name)].iter() {
+    match config.find(key).and_then(|j| j.as_string()) {
+      Some(name) if name == expected => {},
+      Some(name) => problems.push(format!(
+        "{} is \"{}\", but the file name says \"{}\"", key, name, expected
+      )),
+      None => problems.push(format!("Missing string \"{}\"", key))
+    }
+  }
+  let shape = match config.find("shape").and_then(|j| j.as_u64()) {
+    Some(0) => build_3d(&blendout, &mut problems),
+    Some(1) => build_2d(&blendout, &mut problems),
+    _ => {
+      problems.push("shape must be 0 (3d) or 1 (2d)".to_string());
+      return Err(problems_error(&base, problems));
+    }
+  };
+  let embed_images = config.find("embed_images").and_then(|j| j.as_boolean()).unwrap_or(false);
+  if !problems.is_empty() {
+    return Err(problems_error(&base, problems));
+  }
+  let mut model = MetaModel {
+    author_name: author_name.to_string(),
+    model_name: model_name.to_string(),
+    shape: shape,
+    images: Images::External
+  };
+
+  if embed_images {
+    let count = if model.is_3d() { DIRECTIONS } else { 1 };
+    let mut images: Vec<Vec<u8>> = Vec::with_capacity(count);
+    for direction in 0..count {
+      let mut png: Vec<u8> = Vec::new();
+      let mut file = try!(File::open(model.external_image_path(folder, direction)));
+      try!(file.read_to_end(&mut png));
+      images.push(png);
+    }
+    model.images = Images::Embedded(images);
+  }
+
+  Ok(model)
+}
+
+// Builds the model and writes it to author-name.model in the same folder. Returns the
+// path written.
+pub fn compile_model(folder: &Path, author_name: &str, model_name: &str) -> Result<PathBuf, GameError> {
+  let model = try!(build_model(folder, author_name, model_name));
+  let path = folder.join(format!("{}.model", model.qualified_name()));
+  // Round-trip through the reader to catch any mistakes in the writer.
+  let bytes = try!(model.to_bytes());
+  try!(MetaModel::from_bytes(&bytes));
+  try!(binary::write_file(&path, &bytes));
+  Ok(path)
+}
+
+fn uv_field(json: &Json, key: &str, context: &str, problems: &mut Vec<String>) -> Point2<f32> {
+  let pair: Option<Vec<f64>> = json.find(key).and_then(|j| j.as_array()).and_then(|a|
+    a.iter().map(|j| j.as_f64()).collect()
+  );
+  match pair {
+    Some(ref uv) if uv.len() == 2 => Point2::new(uv[0] as f32, uv[1] as f32),
+    _ => {
+      problems.push(format!("{}: \"{}\" must be a pair of numbers", context, key));
+      Point2::new(0.0, 0.0)
+    }
+  }
+}
+
+fn build_3d(blendout: &Json, problems: &mut Vec<String>) -> Shape {
+  let x_size = f32_field(blendout, "xSize", problems);
+  let y_size = f32_field(blendout, "ySize", problems);
+  let z_size = f32_field(blendout, "zSize", problems);
+  let mut directions: Vec<Uvs3d> = Vec::with_capacity(DIRECTIONS);
+  for direction in 0..DIRECTIONS {
+    let context = format!("Direction {}", direction);
+    match blendout.find_path(&["directions", &direction.to_string()]) {
+      Some(d) => directions.push(Uvs3d {
+        tb: uv_field(d, "tb", &context, problems),
+        tr: uv_field(d, "tr", &context, problems),
+        tf: uv_field(d, "tf", &context, problems),
+        tl: uv_field(d, "tl", &context, problems),
+        bl: uv_field(d, "bl", &context, problems),
+        bf: uv_field(d, "bf", &context, problems),
+        br: uv_field(d, "br", &context, problems)
+      }),
+      None => problems.push(format!("{} is missing", context))
+    }
+  }
+  Shape::ThreeD {x_size: x_size, y_size: y_size, z_size: z_size, directions: directions}
+}
+
+fn build_2d(blendout: &Json, problems: &mut Vec<String>) -> Shape {
+  Shape::TwoD {
+    width: f32_field(blendout, "width", problems),
+    height: f32_field(blendout, "height", problems),
+    top_left: uv_field(blendout, "tl", "2d model", problems),
+    bottom_right: uv_field(blendout, "br", "2d model", problems)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs::File;
+  use std::io::Read;
+  use std::path::Path;
+  use errors::GameError;
+  use super::*;
+
+  #[test]
+  fn matches_checked_in_model() {
+    let mut expected: Vec<u8> = Vec::new();
+    File::open("assets/models/jarrett-test.model").unwrap().read_to_end(&mut expected).unwrap();
+    let model = build_model(&Path::new("assets/models"), "jarrett", "test").unwrap();
+    assert_eq!(expected, model.to_bytes().unwrap());
+  }
+
+  #[test]
+  fn missing_files() {
+    match build_model(&Path::new("assets/models"), "nobody", "test") {
+      Err(GameError::Io(_)) => {},
+      _ => panic!("Expected an I/O error")
+    }
+  }
+
+  #[test]
+  fn invalid_names() {
+    // Checked before the files are read, so it doesn't matter that they don't exist.
+    for &(author_name, model_name) in [("Jarrett", "test"), ("jarrett", "te_st")].iter() {
+      match build_model(&Path::new("assets/models"), author_name, model_name) {
+        Err(GameError::Invalid(ref msg)) => assert!(msg.contains("must be non-empty")),
+        _ => panic!("Expected an invalid name error for {}-{}", author_name, model_name)
+      }
+    }
+  }
+}
\ No newline at end of file
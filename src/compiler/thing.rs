@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+use cgmath::Vector3;
+use rustc_serialize::json::Json;
+
+use binary;
+use binary::Writer;
+use errors::GameError;
+use thing::{keys, Config, MetaThing, ModelRef};
+use super::{check_name, read_json, f32_field, problems_error};
+
+// Builds a MetaThing from author-name.thing.json. The JSON has:
+// 
+// - "models", an array of objects with authorName, modelName, direction, x, y, and z.
+// 
+// - "configs", an object mapping config names from thing/keys.rs (e.g.
+//   "thing_has_pylon") to values. Booleans, numbers, and strings are encoded as described
+//   in doc/thing-file-format.md. Enum options are given by name, e.g.
+//   "PYLON_ALIGN_WATER_TABLE".
+pub fn build_thing(folder: &Path, author_name: &str, thing_name: &str) -> Result<MetaThing, GameError> {
+  let base = format!("{}-{}", author_name, thing_name);
+  let mut problems: Vec<String> = Vec::new();
+  check_name("Author name", author_name, &mut problems);
+  check_name("Thing name", thing_name, &mut problems);
+  if !problems.is_empty() {
+    return Err(problems_error(&base, problems));
+  }
+  let json = try!(read_json(&folder.join(format!("{}.thing.json", base))));
+
+  let mut models: Vec<ModelRef> = Vec::new();
+  match json.find("models").and_then(|j| j.as_array()) {
+    Some(array) => for (i, model) in array.iter().enumerate() {
+      if let Some(model) = build_model_ref(i, model, &mut problems) {
+        models.push(model);
+      }
+    },
+    None => problems.push("Missing array \"models\"".to_string())
+  }
+
+  let mut config = Config::default();
+  match json.find("configs") {
+    Some(&Json::Object(ref configs)) => for (name, value) in configs.iter() {
+      set_config(&mut config, name, value, &mut problems);
+    },
+    None => {},
+    Some(_) => problems.push("\"configs\" must be an object".to_string())
+  }
+
+  if !problems.is_empty() {
+    return Err(problems_error(&base, problems));
+  }
+  Ok(MetaThing {
+    author_name: author_name.to_string(),
+    thing_name: thing_name.to_string(),
+    models: models,
+    config: config
+  })
+}
+
+// Builds the thing and writes it to author-name.thing in the same folder. Returns the
+// path written.
+pub fn compile_thing(folder: &Path, author_name: &str, thing_name: &str) -> Result<PathBuf, GameError> {
+  let thing = try!(build_thing(folder, author_name, thing_name));
+  let path = folder.join(format!("{}.thing", thing.qualified_name()));
+  // Round-trip through the reader to catch any mistakes in the writer.
+  let bytes = try!(thing.to_bytes());
+  try!(MetaThing::from_bytes(&bytes));
+  try!(binary::write_file(&path, &bytes));
+  Ok(path)
+}
+
+fn build_model_ref(index: usize, json: &Json, problems: &mut Vec<String>) -> Option<ModelRef> {
+  let mut model_problems: Vec<String> = Vec::new();
+  let mut names: Vec<String> = Vec::with_capacity(2);
+  for &(key, what) in [("authorName", "Author name"), ("modelName", "Model name")].iter() {
+    match json.find(key).and_then(|j| j.as_string()) {
+      Some(name) => {
+        check_name(what, name, &mut model_problems);
+        names.push(name.to_string());
+      },
+      None => model_problems.push(format!("Missing string \"{}\"", key))
+    }
+  }
+  let direction = match json.find("direction").and_then(|j| j.as_u64()) {
+    Some(d) if d < 8 => d as u8,
+    _ => {
+      model_problems.push("direction must be an integer from 0 to 7".to_string());
+      0
+    }
+  };
+  let x = f32_field(json, "x", &mut model_problems);
+  let y = f32_field(json, "y", &mut model_problems);
+  let z = f32_field(json, "z", &mut model_problems);
+
+  if !model_problems.is_empty() {
+    for problem in model_problems {
+      problems.push(format!("Model {}: {}", index, problem));
+    }
+    return None;
+  }
+  let model_name = names.pop().unwrap();
+  let author_name = names.pop().unwrap();
+  Some(ModelRef {
+    author_name: author_name, model_name: model_name, direction: direction,
+    offset: Vector3::new(x, y, z)
+  })
+}
+
+// Encodes the JSON value the same way it would be stored in a .thing file, then hands it
+// to Config::set, so the compiler accepts exactly what the reader accepts.
+fn set_config(config: &mut Config, name: &str, value: &Json, problems: &mut Vec<String>) {
+  let key = match keys::NAMES.iter().position(|&n| n == name) {
+    Some(key) => key as u8,
+    None => {
+      problems.push(format!("Unknown config \"{}\"", name));
+      return;
+    }
+  };
+  let mut w = Writer::new();
+  match *value {
+    Json::Boolean(b) => w.u8(if b { 1 } else { 0 }),
+    Json::String(ref s) => w.bytes(s.as_bytes()),
+    ref number => match number.as_f64() {
+      Some(n) => w.f32(n as f32),
+      None => {
+        problems.push(format!("Config \"{}\" must be a boolean, number, or string", name));
+        return;
+      }
+    }
+  }
+  if let Err(err) = config.set(key, &w.into_bytes()) {
+    problems.push(format!("Config \"{}\": {}", name, err));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs::File;
+  use std::io::Read;
+  use std::path::Path;
+  use rustc_serialize::json::Json;
+  use errors::GameError;
+  use thing::{Config, PylonAlign};
+  use super::*;
+  use super::set_config;
+
+  #[test]
+  fn matches_checked_in_thing() {
+    let mut expected: Vec<u8> = Vec::new();
+    File::open("assets/things/jarrett-test.thing").unwrap().read_to_end(&mut expected).unwrap();
+    let thing = build_thing(&Path::new("assets/things"), "jarrett", "test").unwrap();
+    assert_eq!(expected, thing.to_bytes().unwrap());
+  }
+
+  #[test]
+  fn invalid_names() {
+    // Checked before the file is read, so it doesn't matter that it doesn't exist.
+    for &(author_name, thing_name) in [("Jarrett", "test"), ("jarrett", "te_st")].iter() {
+      match build_thing(&Path::new("assets/things"), author_name, thing_name) {
+        Err(GameError::Invalid(ref msg)) => assert!(msg.contains("must be non-empty")),
+        _ => panic!("Expected an invalid name error for {}-{}", author_name, thing_name)
+      }
+    }
+  }
+
+  #[test]
+  fn configs() {
+    let json = Json::from_str(r#"{
+      "thing_has_pylon": true,
+      "thing_base_x_size": 2.5,
+      "thing_pylon_align": "PYLON_ALIGN_WATER_TABLE",
+      "thing_pylon_model": 7,
+      "thing_color": "red"
+    }"#).unwrap();
+    let mut config = Config::default();
+    let mut problems: Vec<String> = Vec::new();
+    for (name, value) in json.as_object().unwrap().iter() {
+      set_config(&mut config, name, value, &mut problems);
+    }
+    assert!(config.has_pylon);
+    assert_eq!(2.5, config.base_x_size);
+    assert_eq!(PylonAlign::WaterTable, config.pylon_align);
+    // A number where a string belongs, and an unknown name.
+    assert_eq!(2, problems.len());
+  }
+}
\ No newline at end of file
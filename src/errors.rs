@@ -0,0 +1,74 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use image;
+
+// The error type shared by everything that can fail at runtime without it being a bug,
+// e.g. reading an asset or save file from disk.
+#[derive(Debug)]
+pub enum GameError {
+  Io(io::Error),
+
+  Image(image::ImageError),
+
+  // A value ran past the end of the file or of the section containing it. offset is
+  // measured from the start of the file.
+  Truncated {section: &'static str, offset: usize, wanted: usize},
+
+  // A section's declared size disagrees with what it actually contains.
+  SectionSize {section: &'static str, declared: usize, actual: usize},
+
+  // The file was written by a newer version of the game, in a format version this code
+  // doesn't know how to read.
+  UnsupportedVersion {found: u16, supported: u16},
+
+  // Anything else that makes a file unreadable, e.g. an unknown shape byte.
+  Invalid(String)
+}
+
+impl fmt::Display for GameError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      GameError::Io(ref err) => write!(f, "I/O error: {}", err),
+      GameError::Image(ref err) => write!(f, "Image error: {}", err),
+      GameError::Truncated {section, offset, wanted} => write!(
+        f, "Truncated {} section: needed {} more bytes at offset {}", section, wanted, offset
+      ),
+      GameError::SectionSize {section, declared, actual} => write!(
+        f, "Bad {} section size: declared {} bytes, but found {}", section, declared, actual
+      ),
+      GameError::UnsupportedVersion {found, supported} => write!(
+        f,
+        "File format version {} is newer than this version of the game supports (up to {}). \
+         Upgrade the game to read this file.",
+        found, supported
+      ),
+      GameError::Invalid(ref msg) => write!(f, "{}", msg)
+    }
+  }
+}
+
+impl Error for GameError {
+  fn description(&self) -> &str {
+    match *self {
+      GameError::Io(ref err) => err.description(),
+      GameError::Image(ref err) => err.description(),
+      GameError::Truncated {..} => "truncated file",
+      GameError::SectionSize {..} => "bad section size",
+      GameError::UnsupportedVersion {..} => "unsupported file format version",
+      GameError::Invalid(ref msg) => msg
+    }
+  }
+}
+
+impl From<io::Error> for GameError {
+  fn from(err: io::Error) -> GameError {
+    GameError::Io(err)
+  }
+}
+
+impl From<image::ImageError> for GameError {
+  fn from(err: image::ImageError) -> GameError {
+    GameError::Image(err)
+  }
+}
\ No newline at end of file
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use cgmath::Point2;
+use rustc_serialize::json::{Json, ToJson};
+
+use model::{MetaModel, Shape, Images};
+use thing::MetaThing;
+use world::{World, TerrainStorage};
+
+// JSON views of what the game's readers produce. Large data, like terrain heights and
+// image bytes, is summarized rather than dumped.
+
+pub fn object(pairs: Vec<(&str, Json)>) -> Json {
+  let mut map: BTreeMap<String, Json> = BTreeMap::new();
+  for (key, value) in pairs {
+    map.insert(key.to_string(), value);
+  }
+  Json::Object(map)
+}
+
+fn xyz(x: f32, y: f32, z: f32) -> Json {
+  vec![x, y, z].to_json()
+}
+
+fn uv(point: &Point2<f32>) -> Json {
+  vec![point.x, point.y].to_json()
+}
+
+pub fn world(world: &World) -> Json {
+  let terrain = &world.terrain;
+  let min = terrain.heights.iter().cloned().fold(::std::f32::INFINITY, f32::min);
+  let max = terrain.heights.iter().cloned().fold(::std::f32::NEG_INFINITY, f32::max);
+  let storage = match world.terrain_storage {
+    TerrainStorage::Inline => "inline".to_json(),
+    TerrainStorage::Image {ref path, vertical_scale} => object(vec![
+      ("image", path.to_json()), ("vertical_scale", vertical_scale.to_json())
+    ])
+  };
+  let things: Vec<Json> = world.things.iter().map(|thing| {
+    let ext = &thing.extensions;
+    let plugins: Vec<Json> = ext.plugin_data.iter().map(|&(ref plugin, ref data)|
+      object(vec![("plugin", plugin.to_json()), ("bytes", data.len().to_json())])
+    ).collect();
+    let unknown: Vec<Json> = ext.unknown.iter().map(|&(tag, ref data)|
+      object(vec![("tag", tag.to_json()), ("bytes", data.len().to_json())])
+    ).collect();
+    object(vec![
+      ("meta_thing", thing.meta_thing.to_json()),
+      ("direction", thing.direction.to_json()),
+      ("origin", xyz(thing.origin.x, thing.origin.y, thing.origin.z)),
+      ("custom_name", ext.custom_name.to_json()),
+      ("district", ext.district.to_json()),
+      ("construction_progress", ext.construction_progress.to_json()),
+      ("plugin_data", Json::Array(plugins)),
+      ("unknown_extensions", Json::Array(unknown))
+    ])
+  }).collect();
+  object(vec![
+    ("name", world.name.to_json()),
+    ("terrain", object(vec![
+      ("storage", storage),
+      ("water_height", world.water_height.to_json()),
+      ("x_verts", terrain.x_verts.to_json()),
+      ("y_verts", terrain.y_verts.to_json()),
+      ("min_height", min.to_json()),
+      ("max_height", max.to_json())
+    ])),
+    ("things", Json::Array(things))
+  ])
+}
+
+pub fn model(model: &MetaModel) -> Json {
+  let shape = match model.shape {
+    Shape::ThreeD {x_size, y_size, z_size, ref directions} => {
+      let directions: Vec<Json> = directions.iter().map(|uvs| object(vec![
+        ("tb", uv(&uvs.tb)), ("tr", uv(&uvs.tr)), ("tf", uv(&uvs.tf)), ("tl", uv(&uvs.tl)),
+        ("bl", uv(&uvs.bl)), ("bf", uv(&uvs.bf)), ("br", uv(&uvs.br))
+      ])).collect();
+      object(vec![
+        ("type", "3d".to_json()),
+        ("size", xyz(x_size, y_size, z_size)),
+        ("directions", Json::Array(directions))
+      ])
+    },
+    Shape::TwoD {width, height, ref top_left, ref bottom_right} => object(vec![
+      ("type", "2d".to_json()),
+      ("width", width.to_json()),
+      ("height", height.to_json()),
+      ("top_left", uv(top_left)),
+      ("bottom_right", uv(bottom_right))
+    ])
+  };
+  let images = match model.images {
+    Images::External => "external".to_json(),
+    Images::Embedded(ref images) => {
+      let sizes: Vec<usize> = images.iter().map(|png| png.len()).collect();
+      object(vec![("embedded_bytes", sizes.to_json())])
+    }
+  };
+  object(vec![
+    ("author_name", model.author_name.to_json()),
+    ("model_name", model.model_name.to_json()),
+    ("shape", shape),
+    ("images", images)
+  ])
+}
+
+pub fn thing(thing: &MetaThing) -> Json {
+  let models: Vec<Json> = thing.models.iter().map(|model| object(vec![
+    ("model", model.qualified_name().to_json()),
+    ("direction", model.direction.to_json()),
+    ("offset", xyz(model.offset.x, model.offset.y, model.offset.z))
+  ])).collect();
+  let c = &thing.config;
+  let unknown: Vec<Json> = c.unknown.iter().map(|&(key, ref value)|
+    object(vec![("key", key.to_json()), ("bytes", value.len().to_json())])
+  ).collect();
+  object(vec![
+    ("author_name", thing.author_name.to_json()),
+    ("thing_name", thing.thing_name.to_json()),
+    ("models", Json::Array(models)),
+    ("config", object(vec![
+      ("main_model", c.main_model.to_json()),
+      ("base_size", vec![c.base_x_size, c.base_y_size].to_json()),
+      ("base_offset", vec![c.base_x_offset, c.base_y_offset].to_json()),
+      ("builds_foundation", c.builds_foundation.to_json()),
+      ("digs_foundation", c.digs_foundation.to_json()),
+      ("foundation_min_slope", c.foundation_min_slope.to_json()),
+      ("built_foundation_wall_texture", c.built_foundation_wall_texture.to_json()),
+      ("built_foundation_cap_texture", c.built_foundation_cap_texture.to_json()),
+      ("dug_foundation_wall_texture", c.dug_foundation_wall_texture.to_json()),
+      ("dug_foundation_floor_texture", c.dug_foundation_floor_texture.to_json()),
+      ("has_footer", c.has_footer.to_json()),
+      ("footer_model", c.footer_model.to_json()),
+      ("has_pylon", c.has_pylon.to_json()),
+      ("pylon_model", c.pylon_model.to_json()),
+      ("pylon_align", c.pylon_align.name().to_json()),
+      ("pylon_repeat", c.pylon_repeat.name().to_json()),
+      ("align_front", c.align_front.to_json()),
+      ("align_back", c.align_back.to_json()),
+      ("align_left", c.align_left.to_json()),
+      ("align_right", c.align_right.to_json()),
+      ("foundation_max_height", c.foundation_max_height.to_json()),
+      ("unknown", Json::Array(unknown))
+    ]))
+  ])
+}
+
+// Renders JSON as indented "key: value" lines. Short arrays of scalars stay on one line.
+pub fn write_text(out: &mut String, json: &Json, depth: usize) {
+  let indent = "  ".repeat(depth);
+  match *json {
+    Json::Object(ref map) => for (key, value) in map.iter() {
+      if is_scalar(value) {
+        out.push_str(&format!("{}{}: {}\n", indent, key, value));
+      } else {
+        out.push_str(&format!("{}{}:\n", indent, key));
+        write_text(out, value, depth + 1);
+      }
+    },
+    Json::Array(ref items) => for (i, item) in items.iter().enumerate() {
+      if is_scalar(item) {
+        out.push_str(&format!("{}[{}] {}\n", indent, i, item));
+      } else {
+        out.push_str(&format!("{}[{}]\n", indent, i));
+        write_text(out, item, depth + 1);
+      }
+    },
+    ref scalar => out.push_str(&format!("{}{}\n", indent, scalar))
+  }
+}
+
+fn is_scalar(json: &Json) -> bool {
+  match *json {
+    Json::Object(_) => false,
+    Json::Array(ref items) => items.len() <= 4 && items.iter().all(|item| match *item {
+      Json::Object(_) | Json::Array(_) => false,
+      _ => true
+    }),
+    _ => true
+  }
+}
\ No newline at end of file
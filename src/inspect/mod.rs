@@ -0,0 +1,415 @@
+use std::path::Path;
+use rustc_serialize::json::{Json, ToJson};
+
+use binary::Reader;
+use model::MetaModel;
+use save;
+use thing::{keys, MetaThing};
+
+mod contents;
+
+// The inspector explains what's inside a .city, .model, or .thing file for debugging. It
+// runs the game's own readers over the file, and separately walks the raw sections so
+// that it can still say something useful about a file the readers reject. Nothing here
+// touches OpenGL or opens a window.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+  Save,
+  Model,
+  Thing
+}
+
+// One top-level section of a file, as declared by its size field.
+pub struct Section {
+  pub name: &'static str,
+  pub offset: usize,
+  pub size: usize,
+
+  // Notable entries within the section, e.g. each Thing in a save file. Offsets are
+  // measured from the start of the file.
+  pub items: Vec<(usize, String)>
+}
+
+pub struct Report {
+  pub kind: Kind,
+  pub file_size: usize,
+  pub version: Option<u16>,
+  pub sections: Vec<Section>,
+
+  // What the game's reader made of the file, or None if it couldn't read it.
+  pub contents: Option<Json>,
+
+  // Everything wrong with the file. Empty if it's healthy.
+  pub problems: Vec<String>
+}
+
+impl Kind {
+  // Guesses the kind of file from its extension.
+  pub fn from_path(path: &Path) -> Option<Kind> {
+    match path.extension().and_then(|e| e.to_str()) {
+      Some("city") => Some(Kind::Save),
+      Some("model") => Some(Kind::Model),
+      Some("thing") => Some(Kind::Thing),
+      _ => None
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match *self {
+      Kind::Save => "save",
+      Kind::Model => "model",
+      Kind::Thing => "thing"
+    }
+  }
+}
+
+// Inspects a file's bytes. If assets is given, references to other assets are checked
+// against the .model and .thing files in its models and things subfolders.
+pub fn inspect(kind: Kind, bytes: &[u8], assets: Option<&Path>) -> Report {
+  let mut report = Report {
+    kind: kind, file_size: bytes.len(), version: None, sections: Vec::new(), contents: None,
+    problems: Vec::new()
+  };
+  {
+    let mut file = Reader::new(bytes);
+    report.version = file.u16().and_then(|_| file.u16()).ok();
+  }
+
+  // Section names and the widths of their size fields, in file order.
+  let layout: &[(&'static str, usize)] = match kind {
+    Kind::Save => &[("header", 2), ("terrain", 4), ("meta things table", 4), ("things", 4)],
+    // Byte 5 is the images embedded flag.
+    Kind::Model if bytes.get(5) == Some(&1) => &[("header", 2), ("geometry", 2), ("images", 4)],
+    Kind::Model => &[("header", 2), ("geometry", 2)],
+    Kind::Thing => &[("header", 2), ("models", 4), ("configs", 4)]
+  };
+  read_layout(bytes, layout, &mut report);
+
+  match kind {
+    Kind::Save => inspect_save(bytes, assets, &mut report),
+    Kind::Model => match MetaModel::from_bytes(bytes) {
+      Ok(model) => report.contents = Some(contents::model(&model)),
+      Err(err) => report.problems.push(format!("The game can't read this file: {}", err))
+    },
+    Kind::Thing => inspect_thing(bytes, assets, &mut report)
+  }
+  report
+}
+
+impl Report {
+  pub fn to_json(&self) -> Json {
+    let sections: Vec<Json> = self.sections.iter().map(|section| {
+      let items: Vec<Json> = section.items.iter().map(|&(offset, ref description)|
+        contents::object(vec![
+          ("offset", offset.to_json()), ("description", description.to_json())
+        ])
+      ).collect();
+      contents::object(vec![
+        ("name", section.name.to_json()),
+        ("offset", section.offset.to_json()),
+        ("size", section.size.to_json()),
+        ("items", Json::Array(items))
+      ])
+    }).collect();
+    contents::object(vec![
+      ("kind", self.kind.name().to_json()),
+      ("file_size", self.file_size.to_json()),
+      ("version", self.version.to_json()),
+      ("sections", Json::Array(sections)),
+      ("contents", self.contents.clone().unwrap_or(Json::Null)),
+      ("problems", self.problems.to_json())
+    ])
+  }
+
+  pub fn to_text(&self) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("{} file, {} bytes", self.kind.name(), self.file_size));
+    if let Some(version) = self.version {
+      out.push_str(&format!(", format version {}", version));
+    }
+    out.push_str("\n\nSections:\n");
+    for section in self.sections.iter() {
+      out.push_str(&format!(
+        "  {:<20} offset {:>8}  size {:>8}\n", section.name, section.offset, section.size
+      ));
+      for &(offset, ref description) in section.items.iter() {
+        out.push_str(&format!("    @{:<8} {}\n", offset, description));
+      }
+    }
+    if let Some(ref contents) = self.contents {
+      out.push_str("\nContents:\n");
+      contents::write_text(&mut out, contents, 1);
+    }
+    if self.problems.is_empty() {
+      out.push_str("\nNo problems found.\n");
+    } else {
+      out.push_str("\nProblems:\n");
+      for problem in self.problems.iter() {
+        out.push_str(&format!("  {}\n", problem));
+      }
+    }
+    out
+  }
+}
+
+// Walks the top-level sections using only their size fields, flagging any size that
+// doesn't fit the file.
+fn read_layout(bytes: &[u8], layout: &[(&'static str, usize)], report: &mut Report) {
+  let mut offset = 0;
+  for &(name, width) in layout.iter() {
+    let mut file = Reader::new(bytes);
+    let size = file.bytes(offset).and_then(|_|
+      if width == 2 { file.u16().map(|s| s as usize) } else { file.u32().map(|s| s as usize) }
+    );
+    let size = match size {
+      Ok(size) => size,
+      Err(_) => {
+        report.problems.push(format!(
+          "The file ends at offset {}, before the {} section", bytes.len(), name
+        ));
+        return;
+      }
+    };
+    report.sections.push(Section {name: name, offset: offset, size: size, items: Vec::new()});
+    if size < width {
+      report.problems.push(format!(
+        "The {} section at offset {} declares {} bytes, which is less than its own size field",
+        name, offset, size
+      ));
+      return;
+    }
+    if offset + size > bytes.len() {
+      report.problems.push(format!(
+        "The {} section at offset {} declares {} bytes, but only {} remain in the file",
+        name, offset, size, bytes.len() - offset
+      ));
+      return;
+    }
+    offset += size;
+  }
+  if offset < bytes.len() {
+    report.problems.push(format!(
+      "{} unexpected bytes after the last section, at offset {}", bytes.len() - offset, offset
+    ));
+  }
+}
+
+// Returns a Reader over a section found by read_layout, positioned after its size field.
+fn open_section<'a>(bytes: &'a [u8], report: &Report, name: &'static str) -> Option<Reader<'a>> {
+  let offset = match report.sections.iter().find(|s| s.name == name) {
+    Some(section) => section.offset,
+    None => { return None; }
+  };
+  let mut file = Reader::new(bytes);
+  match file.bytes(offset) {
+    Ok(_) => file.section_u32(name).ok(),
+    Err(_) => None
+  }
+}
+
+fn set_items(report: &mut Report, name: &'static str, items: Vec<(usize, String)>) {
+  if let Some(section) = report.sections.iter_mut().find(|s| s.name == name) {
+    section.items = items;
+  }
+}
+
+fn check_asset(assets: Option<&Path>, folder: &str, file_name: &str, what: &str, problems: &mut Vec<String>) {
+  if let Some(assets) = assets {
+    let path = assets.join(folder).join(file_name);
+    if !path.is_file() {
+      problems.push(format!("{} refers to {}, which doesn't exist", what, path.display()));
+    }
+  }
+}
+
+fn inspect_save(bytes: &[u8], assets: Option<&Path>, report: &mut Report) {
+  // The table and the Things are walked by hand rather than taken from the loaded World,
+  // because the loader resolves IDs to names and forgets the table.
+  let mut table: Vec<(usize, String)> = Vec::new();
+  if let Some(mut section) = open_section(bytes, report, "meta things table") {
+    let count = section.u32().unwrap_or(0);
+    for _ in 0..count {
+      let offset = section.offset();
+      match section.ascii() {
+        Ok(name) => table.push((offset, name)),
+        Err(_) => { break; }
+      }
+    }
+  }
+
+  let mut used: Vec<bool> = vec![false; table.len()];
+  let mut thing_items: Vec<(usize, String)> = Vec::new();
+  if let Some(mut section) = open_section(bytes, report, "things") {
+    let count = section.u32().unwrap_or(0);
+    for i in 0..count {
+      let offset = section.offset();
+      let id = match section.u32() {
+        Ok(id) => id as usize,
+        Err(_) => { break; }
+      };
+      // Skip the direction and origin, then the reserved section.
+      let reserved = match section.bytes(13).and_then(|_| section.u32()) {
+        Ok(len) => len as usize,
+        Err(_) => { break; }
+      };
+      if section.bytes(reserved).is_err() {
+        break;
+      }
+      match table.get(id) {
+        Some(&(_, ref name)) => {
+          used[id] = true;
+          thing_items.push((offset, format!("Thing {}: {} ({} reserved bytes)", i, name, reserved)));
+        },
+        None => {
+          thing_items.push((offset, format!("Thing {}: Meta Thing {}", i, id)));
+          report.problems.push(format!(
+            "Thing {} at offset {} refers to Meta Thing {}, but the table only has {} entries",
+            i, offset, id, table.len()
+          ));
+        }
+      }
+    }
+  }
+
+  let mut table_items: Vec<(usize, String)> = Vec::new();
+  for (id, &(offset, ref name)) in table.iter().enumerate() {
+    table_items.push((offset, format!("Meta Thing {}: {}", id, name)));
+    if !used[id] {
+      report.problems.push(format!(
+        "Meta Thing {} ({}) at offset {} isn't used by any Thing", id, name, offset
+      ));
+    }
+    check_asset(
+      assets, "things", &format!("{}.thing", name), &format!("Meta Thing {}", id),
+      &mut report.problems
+    );
+  }
+  set_items(report, "meta things table", table_items);
+  set_items(report, "things", thing_items);
+
+  match save::from_bytes(bytes) {
+    Ok(world) => report.contents = Some(contents::world(&world)),
+    Err(err) => report.problems.push(format!("The game can't load this file: {}", err))
+  }
+}
+
+fn inspect_thing(bytes: &[u8], assets: Option<&Path>, report: &mut Report) {
+  let mut model_items: Vec<(usize, String)> = Vec::new();
+  if let Some(mut section) = open_section(bytes, report, "models") {
+    let count = section.u16().unwrap_or(0);
+    for i in 0..count {
+      let offset = section.offset();
+      let names = section.ascii().and_then(|author| section.ascii().map(|model| (author, model)));
+      match names.and_then(|names| section.bytes(13).map(|_| names)) {
+        Ok((author, model)) => model_items.push((offset, format!("Model {}: {}-{}", i, author, model))),
+        Err(_) => { break; }
+      }
+    }
+  }
+  set_items(report, "models", model_items);
+
+  let mut config_items: Vec<(usize, String)> = Vec::new();
+  if let Some(mut section) = open_section(bytes, report, "configs") {
+    let count = section.u16().unwrap_or(0);
+    for _ in 0..count {
+      let offset = section.offset();
+      let pair = section.u8().and_then(|key|
+        section.u16().and_then(|len| section.bytes(len as usize).map(|_| (key, len)))
+      );
+      match pair {
+        Ok((key, len)) => {
+          let name = keys::NAMES.get(key as usize).cloned().unwrap_or("unknown key");
+          config_items.push((offset, format!("Key {} ({}): {} bytes", key, name, len)));
+        },
+        Err(_) => { break; }
+      }
+    }
+  }
+  set_items(report, "configs", config_items);
+
+  let thing = match MetaThing::from_bytes(bytes) {
+    Ok(thing) => thing,
+    Err(err) => {
+      report.problems.push(format!("The game can't read this file: {}", err));
+      return;
+    }
+  };
+  for (i, model) in thing.models.iter().enumerate() {
+    check_asset(
+      assets, "models", &format!("{}.model", model.qualified_name()), &format!("Model {}", i),
+      &mut report.problems
+    );
+  }
+  let config = &thing.config;
+  for &(name, ref model) in [
+    ("thing_main_model", &config.main_model),
+    ("thing_footer_model", &config.footer_model),
+    ("thing_pylon_model", &config.pylon_model)
+  ].iter() {
+    if let Some(ref model) = **model {
+      check_asset(assets, "models", &format!("{}.model", model), name, &mut report.problems);
+    }
+  }
+  report.contents = Some(contents::thing(&thing));
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs::File;
+  use std::io::Read;
+  use std::path::Path;
+  use super::*;
+
+  fn read(path: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut bytes).unwrap();
+    bytes
+  }
+
+  #[test]
+  fn healthy_save() {
+    let report = inspect(Kind::Save, &read("saves/test.city"), Some(&Path::new("assets")));
+    assert_eq!(Vec::<String>::new(), report.problems);
+    let offsets: Vec<usize> = report.sections.iter().map(|s| s.offset).collect();
+    assert_eq!(vec![0, 19, 65], offsets[0..3].to_vec());
+    assert_eq!(8, report.sections[3].items.len());
+    assert!(report.contents.is_some());
+  }
+
+  #[test]
+  fn unused_meta_thing() {
+    let mut bytes = read("saves/test.city");
+    // Add a second entry named "x" to the end of the Meta Things table.
+    let table = inspect(Kind::Save, &bytes, None).sections[2].offset;
+    bytes[table + 3] += 3;
+    bytes[table + 7] = 2;
+    for (i, &b) in [0, 1, b'x'].iter().enumerate() {
+      bytes.insert(table + 22 + i, b);
+    }
+    let report = inspect(Kind::Save, &bytes, None);
+    assert_eq!(1, report.problems.len());
+    assert!(report.problems[0].contains("isn't used"));
+    // An unused entry isn't an error as far as the game is concerned.
+    assert!(report.contents.is_some());
+  }
+
+  #[test]
+  fn dangling_meta_thing() {
+    let mut bytes = read("saves/test.city");
+    let things = inspect(Kind::Save, &bytes, None).sections[3].offset;
+    bytes[things + 11] = 5;
+    let report = inspect(Kind::Save, &bytes, None);
+    assert!(report.problems[0].contains("refers to Meta Thing 5"));
+    assert!(report.contents.is_none());
+  }
+
+  #[test]
+  fn bad_section_size() {
+    let mut bytes = read("assets/models/jarrett-test.model");
+    bytes.truncate(100);
+    let report = inspect(Kind::Model, &bytes, None);
+    assert_eq!(2, report.sections.len());
+    assert!(report.problems[0].contains("geometry section at offset 21"));
+  }
+}
\ No newline at end of file
@@ -0,0 +1,27 @@
+extern crate cgmath;
+extern crate freetype;
+extern crate gl;
+extern crate glfw;
+extern crate image;
+extern crate libc;
+extern crate png;
+extern crate rustc_serialize;
+
+pub mod assets;
+pub mod binary;
+pub mod camera;
+pub mod compiler;
+pub mod errors;
+pub mod inspect;
+pub mod migrate;
+pub mod mode;
+pub mod model;
+pub mod opengl;
+pub mod save;
+pub mod terrain;
+pub mod thing;
+pub mod ui;
+pub mod world;
+
+#[cfg(test)]
+mod testing;
\ No newline at end of file
@@ -1,17 +1,15 @@
-extern crate cgmath;
-extern crate freetype;
+extern crate cities;
 extern crate gl;
 extern crate glfw;
-extern crate image;
-extern crate libc;
-
-mod camera;
-mod mode;
-mod opengl;
-mod ui;
 
+use cities::assets::AssetRegistry;
+use cities::mode;
+use cities::save;
 use glfw::Context;
 use std::boxed::Box;
+use std::env;
+use std::path::Path;
+use std::process;
 use std::time::{Duration, Instant};
 use std::thread;
 
@@ -29,8 +27,34 @@ fn main() {
     window.get_proc_address(s) as *const std::os::raw::c_void
   );
 
+  // Load every asset up front, so that broken assets are reported all at once before the
+  // game starts rather than one at a time as they're used.
+  let assets = match AssetRegistry::load(&Path::new("assets")) {
+    Ok(assets) => assets,
+    Err(err) => {
+      eprintln!("{}", err);
+      process::exit(1);
+    }
+  };
+
   // The current game mode. Can change from one iteration of the main loop to the next.
-  let mut mode: Box<mode::Mode> = Box::new(mode::Menu);
+  // If a save file is given on the command line, we go straight into the game.
+  let mut mode: Box<mode::Mode> = match env::args().nth(1) {
+    Some(path) => {
+      let (width, height) = window.get_framebuffer_size();
+      let game = save::load(&Path::new(&path)).and_then(|world|
+        mode::Game::new(world, assets, width as u16, height as u16)
+      );
+      match game {
+        Ok(game) => Box::new(game),
+        Err(err) => {
+          eprintln!("Couldn't load {}: {}", path, err);
+          process::exit(1);
+        }
+      }
+    },
+    None => Box::new(mode::Menu)
+  };
   mode.configure_gl();
 
   // Main loop. Mostly just delegates to the game mode.
@@ -41,6 +65,7 @@ fn main() {
     glfw.poll_events();
     glfw::flush_messages(&events);
 
+    mode.receive_input(&window);
     mode.draw();
 
     if let Some(new_mode) = mode.transition() {
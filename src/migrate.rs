@@ -0,0 +1,92 @@
+use std::borrow::Cow;
+
+use errors::GameError;
+
+// Every binary format in doc/ begins with a u16 header size followed by a u16 format
+// version. When we change a format, we bump its version and add a Migration that
+// rewrites a file from the previous version into the new one. Readers only ever parse
+// the latest version; older files are upgraded one step at a time before parsing.
+
+// Rewrites a whole file from one version of its format to the next. The Migration
+// doesn't need to update the version field. upgrade does that after each step.
+pub type Migration = fn(&[u8]) -> Result<Vec<u8>, GameError>;
+
+// Upgrades bytes to current_version. migrations[n] upgrades version n to n + 1, so
+// there must be exactly current_version migrations. If the file is already current,
+// returns it without copying.
+pub fn upgrade<'a>(
+  bytes: &'a [u8], current_version: u16, migrations: &[Migration]
+) -> Result<Cow<'a, [u8]>, GameError> {
+  debug_assert_eq!(current_version as usize, migrations.len());
+
+  let mut version = try!(read_version(bytes));
+  if version > current_version {
+    return Err(GameError::UnsupportedVersion {found: version, supported: current_version});
+  }
+
+  let mut bytes: Cow<'a, [u8]> = Cow::Borrowed(bytes);
+  while version < current_version {
+    let mut upgraded: Vec<u8> = try!(migrations[version as usize](&bytes));
+    version += 1;
+    try!(write_version(&mut upgraded, version));
+    bytes = Cow::Owned(upgraded);
+  }
+  Ok(bytes)
+}
+
+fn read_version(bytes: &[u8]) -> Result<u16, GameError> {
+  if bytes.len() < 4 {
+    return Err(GameError::Truncated {
+      section: "header", offset: bytes.len(), wanted: 4 - bytes.len()
+    });
+  }
+  Ok((bytes[2] as u16) << 8 | bytes[3] as u16)
+}
+
+fn write_version(bytes: &mut Vec<u8>, version: u16) -> Result<(), GameError> {
+  if bytes.len() < 4 {
+    return Err(GameError::Truncated {
+      section: "header", offset: bytes.len(), wanted: 4 - bytes.len()
+    });
+  }
+  bytes[2] = (version >> 8) as u8;
+  bytes[3] = version as u8;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use errors::GameError;
+  use super::*;
+
+  // Version 1 appends a byte to the file.
+  fn v0_to_v1(bytes: &[u8]) -> Result<Vec<u8>, GameError> {
+    let mut upgraded = bytes.to_vec();
+    upgraded.push(1);
+    Ok(upgraded)
+  }
+
+  // Version 2 doubles the appended byte.
+  fn v1_to_v2(bytes: &[u8]) -> Result<Vec<u8>, GameError> {
+    let mut upgraded = bytes.to_vec();
+    let last = upgraded.pop().unwrap();
+    upgraded.push(last * 2);
+    Ok(upgraded)
+  }
+
+  #[test]
+  fn upgrade_step_by_step() {
+    let migrations: &[Migration] = &[v0_to_v1, v1_to_v2];
+    assert_eq!(vec![0, 4, 0, 2, 2], upgrade(&[0, 4, 0, 0], 2, migrations).unwrap().to_vec());
+    assert_eq!(vec![0, 4, 0, 2, 2], upgrade(&[0, 4, 0, 1, 1], 2, migrations).unwrap().to_vec());
+    assert_eq!(vec![0, 4, 0, 2, 9], upgrade(&[0, 4, 0, 2, 9], 2, migrations).unwrap().to_vec());
+  }
+
+  #[test]
+  fn newer_version() {
+    match upgrade(&[0, 4, 0, 3], 2, &[v0_to_v1, v1_to_v2]) {
+      Err(GameError::UnsupportedVersion {found: 3, supported: 2}) => {},
+      _ => panic!("Expected unsupported version error")
+    }
+  }
+}
\ No newline at end of file
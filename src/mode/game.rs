@@ -0,0 +1,237 @@
+use cgmath::{Point2, Vector2};
+use gl;
+use glfw::{Action, Key, MouseButton, Window};
+
+use assets::AssetRegistry;
+use camera::Camera;
+use errors::GameError;
+use model::ModelRenderer;
+use terrain::{Brush, BrushKind, Erosion, ErosionJob, Hydrology, Sculptor};
+use terrain::{TerrainRenderer, WaterRenderer};
+use thing::ModelList;
+use world::World;
+use super::Mode;
+
+// The game mode for playing in a loaded World.
+// 
+// Terrain sculpting controls: hold the left mouse button to apply the brush. 1 through 5
+// pick raise, lower, flatten, smooth, and level. T sets the level brush's target to the
+// height under the cursor. [ and ] change the radius, - and = change the strength, and U
+// undoes the last stroke. R starts eroding the whole map in the background, or cancels
+// erosion that's already running. Sculpting is paused until erosion finishes, and the
+// result can be undone like a stroke.
+//
+// The camera has its own keys, which these mustn't overlap: W, A, S, and D pan, Q and E
+// orbit, and Z and X zoom. See Camera::receive_input.
+pub struct Game {
+  world: World,
+  camera: Camera,
+  terrain_renderer: TerrainRenderer,
+  water_renderer: WaterRenderer,
+  model_renderer: ModelRenderer,
+  assets: AssetRegistry,
+
+  // Where the water is. Recomputed after each sculpting stroke, since it depends on the
+  // shape of the whole terrain.
+  hydrology: Hydrology,
+
+  // The models drawn for the world's Things. Rebuilt along with the hydrology, since
+  // Things sit on the ground and pylons reach down from the water table.
+  models: ModelList,
+
+  sculptor: Sculptor,
+
+  // Erosion running in the background, and the last progress we printed, in tenths.
+  erosion: Option<(ErosionJob, u32)>,
+
+  // Keys that were down last frame, so each press is only handled once.
+  keys_down: Vec<Key>
+}
+
+// Keys handled on press rather than while held.
+const PRESS_KEYS: [Key; 12] = [
+  Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::T,
+  Key::LeftBracket, Key::RightBracket, Key::Minus, Key::Equal, Key::U, Key::R
+];
+
+impl Game {
+  // width and height are the size of the framebuffer in pixels. The camera starts out
+  // looking at the center of the map, zoomed so that the whole map fits.
+  pub fn new(
+    world: World, assets: AssetRegistry, width: u16, height: u16
+  ) -> Result<Game, GameError> {
+    let terrain_renderer = try!(TerrainRenderer::new(&world.terrain, world.water_height));
+    let hydrology = Hydrology::new(&world.terrain, world.water_height);
+    let water_renderer = try!(WaterRenderer::new(&world.terrain, &hydrology));
+    let models = ModelList::new(&world, &hydrology, &assets);
+    let model_renderer = ModelRenderer::new(&assets);
+    try!(model_renderer.load_textures(&assets, &models));
+    let map_size = world.terrain.x_size().max(world.terrain.y_size()).max(1.0);
+    let mut camera = Camera::new(width, height, width.min(height) as f32 / map_size * 1.5);
+    camera.focus_on(Vector2::new(world.terrain.x_size() / 2.0, world.terrain.y_size() / 2.0));
+    Ok(Game {
+      world: world, camera: camera,
+      terrain_renderer: terrain_renderer, water_renderer: water_renderer,
+      model_renderer: model_renderer, assets: assets, hydrology: hydrology, models: models,
+      sculptor: Sculptor::new(Brush::default()), erosion: None, keys_down: Vec::new()
+    })
+  }
+
+  pub fn world(&self) -> &World {
+    &self.world
+  }
+
+  // Starts erosion, or cancels it if it's already running.
+  fn toggle_erosion(&mut self) {
+    match self.erosion.take() {
+      Some((job, _)) => {
+        job.cancel();
+        eprintln!("Erosion cancelled");
+      },
+      None => {
+        self.sculptor.end_stroke();
+        self.erosion = Some((ErosionJob::start(Erosion::default(), &self.world.terrain), 0));
+        eprintln!("Erosion started");
+      }
+    }
+  }
+
+  // Reports progress on stderr, and applies the result once erosion finishes. If the erosion
+  // thread panicked, there's no result, but sculpting is unlocked all the same.
+  fn poll_erosion(&mut self) {
+    let done = match self.erosion {
+      Some((ref job, ref mut reported)) => {
+        let tenths = (job.progress() * 10.0) as u32;
+        if tenths > *reported {
+          eprintln!("Erosion {}%", tenths * 10);
+          *reported = tenths;
+        }
+        job.is_done()
+      },
+      None => false
+    };
+    if !done {
+      return;
+    }
+    let finished = self.erosion.take().and_then(|(mut job, _)| job.try_finish());
+    match finished {
+      Some(eroded) => {
+        let changed = self.sculptor.replace_heights(&mut self.world.terrain, eroded.heights);
+        if let Some(region) = changed {
+          self.terrain_renderer.mark_dirty(&region);
+          self.update_hydrology();
+        }
+      },
+      None => eprintln!("Erosion failed")
+    }
+  }
+
+  fn update_hydrology(&mut self) {
+    self.hydrology = Hydrology::new(&self.world.terrain, self.world.water_height);
+    self.water_renderer.mark_all_dirty();
+    self.models = ModelList::new(&self.world, &self.hydrology, &self.assets);
+  }
+
+  // Returns the keys in PRESS_KEYS that went down since last frame.
+  fn pressed_keys(&mut self, window: &Window) -> Vec<Key> {
+    let down: Vec<Key> = PRESS_KEYS.iter().cloned()
+      .filter(|&key| window.get_key(key) == Action::Press)
+      .collect();
+    let pressed: Vec<Key> = down.iter().cloned()
+      .filter(|key| !self.keys_down.contains(key))
+      .collect();
+    self.keys_down = down;
+    pressed
+  }
+}
+
+impl Mode for Game {
+  fn configure_gl(&self) {
+    unsafe {
+      gl::Enable(gl::DEPTH_TEST);
+      gl::Enable(gl::BLEND);
+      gl::DepthFunc(gl::LEQUAL);
+      gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+      gl::ClearColor(0.9, 0.94, 1.0, 1.0);
+    }
+  }
+
+  fn receive_input(&mut self, window: &Window) {
+    self.camera.receive_input(window);
+
+    // Find the point on the terrain under the cursor. The cursor position is in screen
+    // coords, which differ from framebuffer pixels on high-DPI displays.
+    let (cursor_x, cursor_y) = window.get_cursor_pos();
+    let (window_w, window_h) = window.get_size();
+    let (ray_a, ray_b) = self.camera.unproject(Point2::new(
+      cursor_x as f32 * self.camera.width as f32 / window_w.max(1) as f32,
+      cursor_y as f32 * self.camera.height as f32 / window_h.max(1) as f32
+    ));
+    let hit = self.world.terrain.ray_hit(ray_a, ray_b);
+
+    for key in self.pressed_keys(window) {
+      let brush = &mut self.sculptor.brush;
+      match key {
+        Key::Num1 => brush.kind = BrushKind::Raise,
+        Key::Num2 => brush.kind = BrushKind::Lower,
+        Key::Num3 => brush.kind = BrushKind::Flatten,
+        Key::Num4 => brush.kind = BrushKind::Smooth,
+        Key::Num5 => brush.kind = BrushKind::Level,
+        Key::T => if let Some(point) = hit { brush.target = point.z; },
+        Key::LeftBracket => brush.radius = (brush.radius / 1.25).max(0.5),
+        Key::RightBracket => brush.radius = (brush.radius * 1.25).min(64.0),
+        Key::Minus => brush.strength = (brush.strength / 1.25).max(0.01),
+        Key::Equal => brush.strength = (brush.strength * 1.25).min(1.0),
+        _ => {}
+      }
+      if key == Key::U && self.erosion.is_none() {
+        if let Some(region) = self.sculptor.undo(&mut self.world.terrain) {
+          self.terrain_renderer.mark_dirty(&region);
+          self.update_hydrology();
+        }
+      }
+      if key == Key::R {
+        self.toggle_erosion();
+      }
+    }
+    self.poll_erosion();
+
+    // Sculpting is paused during erosion, since edits made now would be overwritten when
+    // it finishes.
+    let mouse_down = self.erosion.is_none() &&
+      window.get_mouse_button(MouseButton::Button1) == Action::Press;
+    if mouse_down {
+      if let Some(point) = hit {
+        let at = Point2::new(point.x, point.y);
+        if !self.sculptor.in_stroke() {
+          self.sculptor.begin_stroke(&self.world.terrain, at);
+        }
+        if let Some(region) = self.sculptor.dab(&mut self.world.terrain, at) {
+          self.terrain_renderer.mark_dirty(&region);
+          self.water_renderer.mark_dirty(&region);
+        }
+      }
+    } else if self.sculptor.in_stroke() {
+      self.sculptor.end_stroke();
+      self.update_hydrology();
+    }
+
+    self.terrain_renderer.set_cursor(hit.map(|point| (point, self.sculptor.brush.radius)));
+    self.terrain_renderer.flush(&self.world.terrain);
+    self.water_renderer.flush(&self.world.terrain, &self.hydrology);
+  }
+
+  fn draw(&self) {
+    unsafe {
+      gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    }
+    self.terrain_renderer.draw(&self.camera);
+    // Before the water, so it shows over anything submerged.
+    self.model_renderer.draw(&self.camera, &self.assets, &self.models);
+    self.water_renderer.draw(&self.camera);
+  }
+
+  fn transition(&self) -> Option<Box<Mode>> {
+    None
+  }
+}
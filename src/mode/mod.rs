@@ -1,13 +1,20 @@
 use std::boxed::Box;
+use glfw::Window;
 
+pub use self::game::Game;
 pub use self::menu::Menu;
 
+mod game;
 mod menu;
 
 pub trait Mode {
   // Configure OpenGL with functions such as glClearColor.
   fn configure_gl(&self);
 
+  // Called once per frame before draw. Modes that don't respond to input needn't
+  // implement this.
+  fn receive_input(&mut self, _window: &Window) {}
+
   fn draw(&self);
 
   // Optionally tell the main loop to switch to a new mode at the next iteration.
@@ -0,0 +1,318 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use cgmath::{Point2, Vector3};
+
+use binary::{Reader, Writer};
+use errors::GameError;
+use migrate;
+use migrate::Migration;
+
+// The latest version of the .model format. See doc/model-file-format.md.
+pub const FORMAT_VERSION: u16 = 0;
+
+// Upgrades from each older version of the format. See migrate.rs.
+const MIGRATIONS: &'static [Migration] = &[];
+
+// Number of directions (orientations) each 3d sprite is rendered from.
+pub const DIRECTIONS: usize = 8;
+
+// The geometry and texturing of a stationary object, as loaded from a .model file. Any
+// number of Models in the world may share one MetaModel.
+pub struct MetaModel {
+  pub author_name: String,
+  pub model_name: String,
+  pub shape: Shape,
+  pub images: Images
+}
+
+pub enum Shape {
+  // An axis-aligned box with right, left, and top sides. directions has exactly
+  // DIRECTIONS elements.
+  ThreeD {x_size: f32, y_size: f32, z_size: f32, directions: Vec<Uvs3d>},
+
+  // A single quad that always faces the camera. Sizes are in world units.
+  TwoD {width: f32, height: f32, top_left: Point2<f32>, bottom_right: Point2<f32>}
+}
+
+// Where the sprite images come from. There's one image per direction for 3d models, and a
+// single image for 2d models.
+pub enum Images {
+  // Loose PNG files next to the .model file. See external_image_path.
+  External,
+
+  // PNG data stored in the .model file itself, indexed by direction.
+  Embedded(Vec<Vec<u8>>)
+}
+
+// The first eight bytes of every PNG file.
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+// UV coords for one direction of a 3d sprite. See doc/model-rendering.md for the vertex
+// labels.
+pub struct Uvs3d {
+  pub tb: Point2<f32>,
+  pub tr: Point2<f32>,
+  pub tf: Point2<f32>,
+  pub tl: Point2<f32>,
+  pub bl: Point2<f32>,
+  pub bf: Point2<f32>,
+  pub br: Point2<f32>
+}
+
+impl MetaModel {
+  pub fn from_file(path: &Path) -> Result<MetaModel, GameError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut file = try!(File::open(path));
+    try!(file.read_to_end(&mut bytes));
+    MetaModel::from_bytes(&bytes)
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<MetaModel, GameError> {
+    let bytes = try!(migrate::upgrade(bytes, FORMAT_VERSION, MIGRATIONS));
+    let mut file = Reader::new(&bytes);
+
+    let mut header = try!(file.section_u16("header"));
+    try!(header.u16()); // Version. Already checked by migrate::upgrade.
+    let shape_byte = try!(header.u8());
+    let images_embedded = try!(header.u8());
+    let author_name = try!(header.ascii());
+    let model_name = try!(header.ascii());
+    try!(header.finish());
+
+    let mut geometry = try!(file.section_u16("geometry"));
+    let shape = match shape_byte {
+      0 => try!(read_3d(&mut geometry)),
+      1 => try!(read_2d(&mut geometry)),
+      _ => {
+        return Err(GameError::Invalid(format!("Unknown model shape: {}", shape_byte)));
+      }
+    };
+    try!(geometry.finish());
+
+    let image_count = match shape {
+      Shape::ThreeD {..} => DIRECTIONS,
+      Shape::TwoD {..} => 1
+    };
+    let images = match images_embedded {
+      0 => Images::External,
+      1 => {
+        let mut images_section = try!(file.section_u32("images"));
+        let images = try!(read_images(&mut images_section, image_count));
+        try!(images_section.finish());
+        Images::Embedded(images)
+      },
+      _ => {
+        return Err(GameError::Invalid(format!(
+          "Invalid images embedded flag: {}", images_embedded
+        )));
+      }
+    };
+
+    try!(file.finish());
+
+    Ok(MetaModel {
+      author_name: author_name, model_name: model_name, shape: shape, images: images
+    })
+  }
+
+  pub fn to_bytes(&self) -> Result<Vec<u8>, GameError> {
+    let mut w = Writer::new();
+
+    let header = w.begin_section_u16();
+    w.u16(FORMAT_VERSION);
+    w.u8(if self.is_3d() { 0 } else { 1 });
+    w.u8(match self.images { Images::External => 0, Images::Embedded(_) => 1 });
+    try!(w.string(&self.author_name));
+    try!(w.string(&self.model_name));
+    try!(w.end_section_u16(header));
+
+    let geometry = w.begin_section_u16();
+    match self.shape {
+      Shape::ThreeD {x_size, y_size, z_size, ref directions} => {
+        w.f32(x_size);
+        w.f32(y_size);
+        w.f32(z_size);
+        for uvs in directions.iter() {
+          for uv in [uvs.tb, uvs.tr, uvs.tf, uvs.tl, uvs.bl, uvs.bf, uvs.br].iter() {
+            write_uv(&mut w, uv);
+          }
+        }
+      },
+      Shape::TwoD {width, height, ref top_left, ref bottom_right} => {
+        w.f32(width);
+        w.f32(height);
+        write_uv(&mut w, top_left);
+        write_uv(&mut w, bottom_right);
+      }
+    }
+    try!(w.end_section_u16(geometry));
+
+    if let Images::Embedded(ref images) = self.images {
+      let section = w.begin_section_u32();
+      for png in images.iter() {
+        w.u32(png.len() as u32);
+        w.bytes(png);
+      }
+      try!(w.end_section_u32(section));
+    }
+
+    Ok(w.into_bytes())
+  }
+
+  // The name by which Things and save files refer to this model, e.g. "jarrett-test".
+  pub fn qualified_name(&self) -> String {
+    format!("{}-{}", self.author_name, self.model_name)
+  }
+
+  // Where to find the image for the given direction if the images aren't embedded, e.g.
+  // assets/models/jarrett-test-0.png. folder is the folder containing the .model file.
+  pub fn external_image_path(&self, folder: &Path, direction: usize) -> PathBuf {
+    folder.join(format!("{}-{}.png", self.qualified_name(), direction))
+  }
+
+  // Size of the box the model fills, centered on its origin. A 2d sprite turns to face
+  // the camera, so it's as deep as it is wide.
+  pub fn size(&self) -> Vector3<f32> {
+    match self.shape {
+      Shape::ThreeD {x_size, y_size, z_size, ..} => Vector3::new(x_size, y_size, z_size),
+      Shape::TwoD {width, height, ..} => Vector3::new(width, width, height)
+    }
+  }
+
+  pub fn is_3d(&self) -> bool {
+    match self.shape {
+      Shape::ThreeD {..} => true,
+      Shape::TwoD {..} => false
+    }
+  }
+}
+
+fn read_uv(reader: &mut Reader) -> Result<Point2<f32>, GameError> {
+  let u = try!(reader.f32());
+  let v = try!(reader.f32());
+  Ok(Point2::new(u, v))
+}
+
+fn write_uv(w: &mut Writer, uv: &Point2<f32>) {
+  w.f32(uv.x);
+  w.f32(uv.y);
+}
+
+fn read_3d(geometry: &mut Reader) -> Result<Shape, GameError> {
+  let x_size = try!(geometry.f32());
+  let y_size = try!(geometry.f32());
+  let z_size = try!(geometry.f32());
+  let mut directions: Vec<Uvs3d> = Vec::with_capacity(DIRECTIONS);
+  for _ in 0..DIRECTIONS {
+    // The order in the file differs from the field order in doc/model-rendering.md, so
+    // we can't use a struct literal here.
+    let tb = try!(read_uv(geometry));
+    let tr = try!(read_uv(geometry));
+    let tf = try!(read_uv(geometry));
+    let tl = try!(read_uv(geometry));
+    let bl = try!(read_uv(geometry));
+    let bf = try!(read_uv(geometry));
+    let br = try!(read_uv(geometry));
+    directions.push(Uvs3d {tb: tb, tr: tr, tf: tf, tl: tl, bl: bl, bf: bf, br: br});
+  }
+  Ok(Shape::ThreeD {x_size: x_size, y_size: y_size, z_size: z_size, directions: directions})
+}
+
+fn read_2d(geometry: &mut Reader) -> Result<Shape, GameError> {
+  let width = try!(geometry.f32());
+  let height = try!(geometry.f32());
+  let top_left = try!(read_uv(geometry));
+  let bottom_right = try!(read_uv(geometry));
+  Ok(Shape::TwoD {width: width, height: height, top_left: top_left, bottom_right: bottom_right})
+}
+
+fn read_images(section: &mut Reader, count: usize) -> Result<Vec<Vec<u8>>, GameError> {
+  let mut images: Vec<Vec<u8>> = Vec::with_capacity(count);
+  for direction in 0..count {
+    let len = try!(section.u32()) as usize;
+    let png: &[u8] = try!(section.bytes(len));
+    if !png.starts_with(&PNG_SIGNATURE) {
+      return Err(GameError::Invalid(format!(
+        "Embedded image for direction {} is not a PNG", direction
+      )));
+    }
+    images.push(png.to_vec());
+  }
+  Ok(images)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+  use errors::GameError;
+  use super::*;
+
+  #[test]
+  fn from_file() {
+    let model = MetaModel::from_file(&Path::new("assets/models/jarrett-test.model")).unwrap();
+    assert_eq!("jarrett-test", model.qualified_name());
+    match model.shape {
+      Shape::ThreeD {x_size, ref directions, ..} => {
+        assert!((x_size - 2.352065682411194).abs() < 0.0001);
+        assert_eq!(DIRECTIONS, directions.len());
+        assert!((directions[0].tb.x - 0.35571399331092834).abs() < 0.0001);
+        assert!((directions[7].br.y - 0.8682934641838074).abs() < 0.0001);
+      },
+      Shape::TwoD {..} => panic!("Expected 3d model")
+    }
+  }
+
+  #[test]
+  fn round_trip() {
+    let mut bytes: Vec<u8> = Vec::new();
+    File::open("assets/models/jarrett-test.model").unwrap().read_to_end(&mut bytes).unwrap();
+    assert_eq!(bytes, MetaModel::from_bytes(&bytes).unwrap().to_bytes().unwrap());
+  }
+
+  #[test]
+  fn embedded_images() {
+    let mut bytes: Vec<u8> = Vec::new();
+    File::open("assets/models/jarrett-test.model").unwrap().read_to_end(&mut bytes).unwrap();
+    bytes[5] = 1;
+    // Images section: 8 images of 9 bytes each, plus a size field for each image and
+    // one for the section.
+    let section_size: u8 = 4 + 8 * (4 + 9);
+    bytes.extend_from_slice(&[0, 0, 0, section_size]);
+    for direction in 0..DIRECTIONS {
+      bytes.extend_from_slice(&[0, 0, 0, 9]);
+      bytes.extend_from_slice(&PNG_SIGNATURE);
+      bytes.push(direction as u8);
+    }
+    let model = MetaModel::from_bytes(&bytes).unwrap();
+    match model.images {
+      Images::Embedded(ref images) => {
+        assert_eq!(DIRECTIONS, images.len());
+        assert_eq!(7, images[7][8]);
+      },
+      Images::External => panic!("Expected embedded images")
+    }
+  }
+
+  #[test]
+  fn truncated() {
+    let mut bytes: Vec<u8> = Vec::new();
+    File::open("assets/models/jarrett-test.model").unwrap().read_to_end(&mut bytes).unwrap();
+    bytes.truncate(100);
+    match MetaModel::from_bytes(&bytes) {
+      Err(GameError::SectionSize {section: "geometry", ..}) => {},
+      _ => panic!("Expected geometry section size error")
+    }
+  }
+
+  #[test]
+  fn unsupported_version() {
+    let mut bytes: Vec<u8> = Vec::new();
+    File::open("assets/models/jarrett-test.model").unwrap().read_to_end(&mut bytes).unwrap();
+    bytes[3] = 99;
+    match MetaModel::from_bytes(&bytes) {
+      Err(GameError::UnsupportedVersion {found: 99, ..}) => {},
+      _ => panic!("Expected unsupported version error")
+    }
+  }
+}
\ No newline at end of file
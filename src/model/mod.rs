@@ -0,0 +1,5 @@
+mod meta_model;
+mod renderer;
+
+pub use self::meta_model::{MetaModel, Shape, Images, Uvs3d, DIRECTIONS};
+pub use self::renderer::{Block, ModelBuffers, ModelRenderer, model_transform};
\ No newline at end of file
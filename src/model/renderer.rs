@@ -0,0 +1,285 @@
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+use std::mem::size_of;
+use std::path::Path;
+use cgmath::{EuclideanSpace, Matrix, Matrix4, Point2, Point3, Rad};
+use gl;
+use gl::types::*;
+
+use assets::AssetRegistry;
+use camera::Camera;
+use errors::GameError;
+use opengl::{Attributes, Indices, Program, Vao, Vbo};
+use thing::ModelList;
+use super::meta_model::{MetaModel, Shape, DIRECTIONS};
+
+// Draws models with glsl/model3d.vert.glsl and glsl/model.frag.glsl, as described in
+// doc/model-rendering.md. Every MetaModel in the registry is buffered once at startup into
+// one VAO, so drawing a model is just a matter of picking its block and texture.
+pub struct ModelRenderer {
+  program: Program,
+  vao: Vao,
+  attribute_buffer: Vbo<Attributes>,
+  index_buffer: Vbo<Indices>,
+
+  // Indexed by model ID and then by direction.
+  blocks: Vec<Vec<Block>>,
+
+  // Uniform locations.
+  camera_idx: GLint,
+  model_idx: GLint,
+  sprite_idx: GLint,
+
+  // Attribute locations.
+  position_idx: GLuint,
+  uv_idx: GLuint
+}
+
+// Vertex data for every MetaModel, ready to upload to OpenGL.
+pub struct ModelBuffers {
+  // x, y, z, u, v for each vertex. Positions are relative to the center of the model's
+  // box.
+  pub attributes: Vec<f32>,
+
+  // Triangles. These are 32 bits, unlike the terrain's, because every model shares the
+  // one buffer.
+  pub indices: Vec<u32>,
+
+  // Indexed by model ID and then by direction. 3d models have DIRECTIONS blocks, and 2d
+  // models have one.
+  pub blocks: Vec<Vec<Block>>
+}
+
+// The indices for one direction of one model.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Block {
+  // Offset of the first index, counted in indices.
+  pub first: usize,
+  pub count: usize
+}
+
+// Floats per vertex in ModelBuffers::attributes.
+const STRIDE: usize = 5;
+
+// Top, left, and right faces of a 3d model, from the vertices in the order TB, TR, TF, TL,
+// BL, BF, BR.
+const INDICES_3D: [u32; 18] = [0, 1, 2, 0, 2, 3, 3, 2, 5, 3, 5, 4, 2, 1, 6, 2, 6, 5];
+
+// A 2d model's quad, from the vertices in the order top left, top right, bottom right,
+// bottom left.
+const INDICES_2D: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+impl ModelBuffers {
+  pub fn new(meta_models: &[&MetaModel]) -> ModelBuffers {
+    let mut buffers = ModelBuffers {
+      attributes: Vec::new(), indices: Vec::new(), blocks: Vec::with_capacity(meta_models.len())
+    };
+    for meta_model in meta_models.iter() {
+      let blocks = match meta_model.shape {
+        Shape::ThreeD {x_size, y_size, z_size, ref directions} => {
+          directions.iter().enumerate().map(|(direction, uvs)| {
+            // The box is seen turned by the direction, so its X and Y sizes trade places
+            // every other quarter turn. The shader turns odd directions the rest of the way.
+            let (x, y) = if (direction / 2) % 2 == 1 { (y_size, x_size) } else { (x_size, y_size) };
+            let (x, y, z) = (x / 2.0, y / 2.0, z_size / 2.0);
+            buffers.block(&[
+              ([-x, -y, z], uvs.tb), ([x, -y, z], uvs.tr), ([x, y, z], uvs.tf),
+              ([-x, y, z], uvs.tl), ([-x, y, -z], uvs.bl), ([x, y, -z], uvs.bf),
+              ([x, -y, -z], uvs.br)
+            ], &INDICES_3D)
+          }).collect()
+        },
+        Shape::TwoD {width, height, top_left, bottom_right} => {
+          // A quad across the screen, which runs from -X +Y to +X -Y before the shader turns
+          // it to face the camera.
+          let (r, z) = (width / 2.0 / SQRT_2, height / 2.0);
+          let (tl, br) = (top_left, bottom_right);
+          vec![buffers.block(&[
+            ([-r, r, z], tl), ([r, -r, z], Point2::new(br.x, tl.y)),
+            ([r, -r, -z], br), ([-r, r, -z], Point2::new(tl.x, br.y))
+          ], &INDICES_2D)]
+        }
+      };
+      buffers.blocks.push(blocks);
+    }
+    buffers
+  }
+
+  fn block(&mut self, vertices: &[([f32; 3], Point2<f32>)], indices: &[u32]) -> Block {
+    let base = (self.attributes.len() / STRIDE) as u32;
+    for &(position, uv) in vertices.iter() {
+      self.attributes.extend_from_slice(&position);
+      self.attributes.extend_from_slice(&[uv.x, uv.y]);
+    }
+    let block = Block {first: self.indices.len(), count: indices.len()};
+    self.indices.extend(indices.iter().map(|&i| base + i));
+    block
+  }
+}
+
+impl ModelRenderer {
+  // Buffers every MetaModel in the registry. Call once the OpenGL context exists.
+  pub fn new(assets: &AssetRegistry) -> ModelRenderer {
+    let program = Program::new(
+      &Path::new("glsl/model3d.vert.glsl"),
+      &Path::new("glsl/model.frag.glsl")
+    );
+    let meta_models: Vec<&MetaModel> = (0..assets.model_count())
+      .map(|id| assets.model(id))
+      .collect();
+    let buffers = ModelBuffers::new(&meta_models);
+    let mut renderer = ModelRenderer {
+      camera_idx: program.get_uniform_location("camera"),
+      model_idx: program.get_uniform_location("model"),
+      sprite_idx: program.get_uniform_location("sprite"),
+      position_idx: program.get_attrib_location("position"),
+      uv_idx: program.get_attrib_location("uv"),
+      program: program,
+      vao: Vao::new(),
+      attribute_buffer: Vbo::new(),
+      index_buffer: Vbo::new(),
+      blocks: buffers.blocks
+    };
+    renderer.attribute_buffer.buffer_data(
+      buffers.attributes.len() * size_of::<GLfloat>(), &buffers.attributes, gl::STATIC_DRAW
+    );
+    renderer.index_buffer.buffer_data(
+      buffers.indices.len() * size_of::<GLuint>(), &buffers.indices, gl::STATIC_DRAW
+    );
+    let stride = STRIDE * size_of::<GLfloat>();
+    unsafe { renderer.vao.bind(); }
+    renderer.vao.attrib(
+      &renderer.attribute_buffer, renderer.position_idx, 3, gl::FLOAT, stride, 0
+    );
+    renderer.vao.attrib(
+      &renderer.attribute_buffer, renderer.uv_idx, 2, gl::FLOAT, stride, 3 * size_of::<GLfloat>()
+    );
+    unsafe { renderer.vao.unbind(); }
+    renderer
+  }
+
+  // Loads the textures for every direction of every model in the list, so broken images
+  // are reported up front rather than while drawing.
+  pub fn load_textures(&self, assets: &AssetRegistry, models: &ModelList) -> Result<(), GameError> {
+    for instance in models.instances.iter() {
+      for direction in 0..self.blocks[instance.model_id].len() {
+        try!(assets.texture(instance.model_id, direction));
+      }
+    }
+    Ok(())
+  }
+
+  // Draws every model in the list. Call after drawing the terrain. Models whose textures
+  // can't be loaded are skipped, since there's no way to report errors mid-frame. Call
+  // load_textures first to catch those.
+  pub fn draw(&self, camera: &Camera, assets: &AssetRegistry, models: &ModelList) {
+    unsafe {
+      gl::UseProgram(self.program.id);
+      gl::UniformMatrix4fv(self.camera_idx, 1, gl::FALSE, camera.transform.as_ptr());
+      self.vao.bind();
+      self.index_buffer.bind();
+    }
+    for instance in models.instances.iter() {
+      self.draw_model(camera, assets, instance.model_id, instance.direction, instance.center);
+    }
+    unsafe {
+      self.index_buffer.unbind();
+      self.vao.unbind();
+      gl::UseProgram(0);
+    }
+  }
+
+  // Draws one model with its center at origin. The MetaModel is drawn from whichever
+  // direction the camera sees it from: turning the camera a quarter turn shows the model
+  // two directions further around.
+  fn draw_model(
+    &self, camera: &Camera, assets: &AssetRegistry,
+    model_id: usize, direction: u8, origin: Point3<f32>
+  ) {
+    let blocks = &self.blocks[model_id];
+    let (view, direction) = if blocks.len() == DIRECTIONS {
+      ((direction as usize + DIRECTIONS - 2 * camera.orbit as usize) % DIRECTIONS, direction)
+    } else {
+      // 2d models always face the camera.
+      (0, 0)
+    };
+    let texture = match assets.texture(model_id, view) {
+      Ok(texture) => texture,
+      Err(_) => { return; }
+    };
+    let block = blocks[view];
+    let transform = model_transform(origin, camera.orbit, direction);
+    unsafe {
+      gl::UniformMatrix4fv(self.model_idx, 1, gl::FALSE, transform.as_ptr());
+      texture.bind(self.sprite_idx, 0);
+      gl::DrawElements(
+        gl::TRIANGLES, block.count as GLsizei, gl::UNSIGNED_INT,
+        (block.first * size_of::<GLuint>()) as *const GLvoid
+      );
+    }
+  }
+}
+
+// Where a model's vertices go in world space, for the model uniform of
+// glsl/model3d.vert.glsl. Each step in the model's direction is 45 degrees, and each step
+// in the camera's orbit is 90. The block is turned back by the orbit so it always faces
+// the camera, and another 45 degrees for odd directions, then moved to the origin.
+pub fn model_transform(origin: Point3<f32>, orbit: u8, direction: u8) -> Matrix4<f32> {
+  let steps = (direction % 2) as f32 - 2.0 * orbit as f32;
+  Matrix4::from_translation(origin.to_vec()) * Matrix4::from_angle_z(Rad(steps * FRAC_PI_4))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+  use cgmath::{InnerSpace, Point2, Point3, Vector4};
+  use model::{Images, MetaModel, Shape, DIRECTIONS};
+  use super::*;
+
+  #[test]
+  fn blocks() {
+    let three_d = MetaModel::from_file(&Path::new("assets/models/jarrett-test.model")).unwrap();
+    let two_d = MetaModel {
+      author_name: "test".to_string(), model_name: "tree".to_string(),
+      shape: Shape::TwoD {
+        width: 2.0, height: 4.0,
+        top_left: Point2::new(0.0, 0.0), bottom_right: Point2::new(1.0, 1.0)
+      },
+      images: Images::External
+    };
+    let buffers = ModelBuffers::new(&[&three_d, &two_d]);
+    assert_eq!(DIRECTIONS, buffers.blocks[0].len());
+    assert_eq!(Block {first: 18, count: 18}, buffers.blocks[0][1]);
+    assert_eq!(vec![Block {first: 18 * DIRECTIONS, count: 6}], buffers.blocks[1]);
+    assert_eq!((DIRECTIONS * 7 + 4) * STRIDE, buffers.attributes.len());
+
+    // The 2d model's vertices follow all of the 3d model's.
+    let base = (DIRECTIONS * 7) as u32;
+    assert_eq!(vec![base, base + 1, base + 2, base, base + 2, base + 3], buffers.indices[18 * 8..]);
+    assert_eq!(2.0, buffers.attributes[base as usize * STRIDE + 2]);
+
+    // TF is at the top of the +X +Y corner, and the box's sides trade places a quarter
+    // turn around.
+    let size = three_d.size();
+    let tf = |direction: usize| buffers.attributes[(direction * 7 + 2) * STRIDE..][..3].to_vec();
+    assert_eq!(vec![size.x / 2.0, size.y / 2.0, size.z / 2.0], tf(0));
+    assert_eq!(vec![size.y / 2.0, size.x / 2.0, size.z / 2.0], tf(2));
+  }
+  #[test]
+  fn vertex_positions() {
+    // The TF vertex of a 2 x 4 x 6 box, seen from direction 0, as the shader places it.
+    let origin = Point3::new(10.0, 20.0, 5.0);
+    let tf = Vector4::new(1.0, 2.0, 3.0, 1.0);
+    let placed = model_transform(origin, 0, 0) * tf;
+    assert_eq!(Vector4::new(11.0, 22.0, 8.0, 1.0), placed);
+
+    // A quarter turn of the camera turns the box back a quarter turn around its origin,
+    // and an odd direction another eighth. The origin stays put, and w stays 1.
+    let turned = model_transform(origin, 1, 0) * tf;
+    assert!((turned - Vector4::new(12.0, 19.0, 8.0, 1.0)).magnitude() < 0.0001);
+    let diagonal = model_transform(origin, 0, 1) * Vector4::new(1.0, 0.0, 0.0, 1.0);
+    let expected = Vector4::new(10.0 + FRAC_PI_4.cos(), 20.0 + FRAC_PI_4.sin(), 5.0, 1.0);
+    assert!((diagonal - expected).magnitude() < 0.0001);
+    let center = model_transform(origin, 3, 5) * Vector4::unit_w();
+    assert_eq!(Vector4::new(10.0, 20.0, 5.0, 1.0), center);
+  }
+}
\ No newline at end of file
@@ -5,6 +5,8 @@ use gl::types::{GLint, GLuint, GLenum, GLvoid};
 use image;
 use image::GenericImage;
 
+use errors::GameError;
+
 pub struct Texture2d {
   id: GLuint,
   width: usize,
@@ -35,8 +37,18 @@ impl Texture2d {
     texture
   }
   
-  pub fn from_file(path: &Path, config: &Config) -> Texture2d {
-    let dyn_img: image::DynamicImage = image::open(path).unwrap();        
+  pub fn from_file(path: &Path, config: &Config) -> Result<Texture2d, GameError> {
+    let dyn_img: image::DynamicImage = try!(image::open(path));
+    Ok(Texture2d::from_image(dyn_img, config))
+  }
+  
+  // Decodes an image that's already in memory, e.g. a PNG embedded in a .model file.
+  pub fn from_memory(bytes: &[u8], config: &Config) -> Result<Texture2d, GameError> {
+    let dyn_img: image::DynamicImage = try!(image::load_from_memory(bytes));
+    Ok(Texture2d::from_image(dyn_img, config))
+  }
+  
+  fn from_image(dyn_img: image::DynamicImage, config: &Config) -> Texture2d {
     let (width, height) = dyn_img.dimensions();
     
     let texture = Texture2d::new(config, width as usize, height as usize);
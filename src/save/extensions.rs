@@ -0,0 +1,109 @@
+use binary::{Reader, Writer};
+use errors::GameError;
+use world::Extensions;
+
+// Tags for the records in a Thing's reserved section. Never reuse a tag, even if the
+// record it identified is no longer written.
+const CUSTOM_NAME: u16 = 1;
+const DISTRICT: u16 = 2;
+const CONSTRUCTION_PROGRESS: u16 = 3;
+const PLUGIN_DATA: u16 = 4;
+
+// Reads records until the end of the reserved section.
+pub fn read(section: &mut Reader) -> Result<Extensions, GameError> {
+  let mut extensions = Extensions::default();
+  while section.remaining() > 0 {
+    let tag = try!(section.u16());
+    let len = try!(section.u32()) as usize;
+    let mut value = try!(section.take("thing extension", len));
+    match tag {
+      CUSTOM_NAME => {
+        extensions.custom_name = Some(try!(value.utf8()));
+      },
+      DISTRICT => {
+        extensions.district = Some(try!(value.u32()));
+      },
+      CONSTRUCTION_PROGRESS => {
+        extensions.construction_progress = Some(try!(value.f32()));
+      },
+      PLUGIN_DATA => {
+        let plugin = try!(value.ascii());
+        let rest = value.remaining();
+        let data = try!(value.bytes(rest)).to_vec();
+        extensions.plugin_data.push((plugin, data));
+      },
+      _ => {
+        extensions.unknown.push((tag, try!(value.bytes(len)).to_vec()));
+      }
+    }
+    try!(value.finish());
+  }
+  Ok(extensions)
+}
+
+// Writes known records in tag order, followed by unknown records in the order we read
+// them.
+pub fn write(w: &mut Writer, extensions: &Extensions) -> Result<(), GameError> {
+  if let Some(ref name) = extensions.custom_name {
+    let mut value = Writer::new();
+    try!(value.string(name));
+    write_record(w, CUSTOM_NAME, value);
+  }
+  if let Some(district) = extensions.district {
+    let mut value = Writer::new();
+    value.u32(district);
+    write_record(w, DISTRICT, value);
+  }
+  if let Some(progress) = extensions.construction_progress {
+    let mut value = Writer::new();
+    value.f32(progress);
+    write_record(w, CONSTRUCTION_PROGRESS, value);
+  }
+  for &(ref plugin, ref data) in extensions.plugin_data.iter() {
+    let mut value = Writer::new();
+    try!(value.string(plugin));
+    value.bytes(data);
+    write_record(w, PLUGIN_DATA, value);
+  }
+  for &(tag, ref data) in extensions.unknown.iter() {
+    let mut value = Writer::new();
+    value.bytes(data);
+    write_record(w, tag, value);
+  }
+  Ok(())
+}
+
+fn write_record(w: &mut Writer, tag: u16, value: Writer) {
+  let value: Vec<u8> = value.into_bytes();
+  w.u16(tag);
+  w.u32(value.len() as u32);
+  w.bytes(&value);
+}
+
+#[cfg(test)]
+mod tests {
+  use binary::{Reader, Writer};
+  use world::Extensions;
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let bytes: Vec<u8> = vec![
+      // Unknown tag 99 with a 2 byte value.
+      0, 99, 0, 0, 0, 2, 1, 2,
+      // District 7.
+      0, 2, 0, 0, 0, 4, 0, 0, 0, 7
+    ];
+    let extensions: Extensions = read(&mut Reader::new(&bytes)).unwrap();
+    assert_eq!(None, extensions.custom_name);
+    assert_eq!(Some(7), extensions.district);
+    assert_eq!(vec![(99u16, vec![1u8, 2u8])], extensions.unknown);
+
+    // Known records come first when written back out.
+    let mut w = Writer::new();
+    write(&mut w, &extensions).unwrap();
+    let rewritten = w.into_bytes();
+    assert_eq!(&bytes[8..], &rewritten[..10]);
+    assert_eq!(&bytes[..8], &rewritten[10..]);
+  }
+}
\ No newline at end of file
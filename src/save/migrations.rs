@@ -0,0 +1,101 @@
+use binary::{Reader, Writer};
+use errors::GameError;
+
+// Version 1 defines each Thing's reserved section as a sequence of tagged records. In
+// version 0, the reserved section was meant to be empty. We can't interpret anything
+// else that might be there, so we drop it.
+pub fn v0_to_v1(bytes: &[u8]) -> Result<Vec<u8>, GameError> {
+  let mut r = Reader::new(bytes);
+  let mut w = Writer::new();
+
+  // The header, terrain, and Meta Things table are unchanged.
+  let start = r.offset();
+  try!(r.section_u16("header"));
+  try!(r.section_u32("terrain"));
+  try!(r.section_u32("meta things table"));
+  w.bytes(&bytes[start..r.offset()]);
+
+  let mut things = try!(r.section_u32("things"));
+  let section = w.begin_section_u32();
+  let count = try!(things.u32());
+  w.u32(count);
+  for _ in 0..count {
+    // Meta Thing ID, direction, and X, Y, Z.
+    w.bytes(try!(things.bytes(17)));
+    let reserved_len = try!(things.u32()) as usize;
+    try!(things.bytes(reserved_len));
+    w.u32(0);
+  }
+  try!(things.finish());
+  try!(w.end_section_u32(section));
+
+  try!(r.finish());
+  Ok(w.into_bytes())
+}
+
+// Version 2 stores the water height at the start of the terrain section. Before that, it
+// was hard-coded in glsl/terrain.frag.glsl.
+pub fn v1_to_v2(bytes: &[u8]) -> Result<Vec<u8>, GameError> {
+  let mut r = Reader::new(bytes);
+  let mut w = Writer::new();
+
+  let start = r.offset();
+  try!(r.section_u16("header"));
+  w.bytes(&bytes[start..r.offset()]);
+
+  let mut terrain = try!(r.section_u32("terrain"));
+  let section = w.begin_section_u32();
+  w.f32(V1_WATER_HEIGHT);
+  let rest = terrain.remaining();
+  w.bytes(try!(terrain.bytes(rest)));
+  try!(w.end_section_u32(section));
+
+  // Everything after the terrain is unchanged.
+  let rest = r.remaining();
+  w.bytes(try!(r.bytes(rest)));
+  Ok(w.into_bytes())
+}
+
+// The water height glsl/terrain.frag.glsl used before it was stored in save files.
+const V1_WATER_HEIGHT: f32 = 5.0;
+
+#[cfg(test)]
+mod tests {
+  use save;
+
+  #[test]
+  fn v0_reserved_data_is_dropped() {
+    let bytes: Vec<u8> = vec![
+      // Header, version 0.
+      0, 7, 0, 0, 0, 1, b'a',
+      // Terrain: 2 x 2 vertices.
+      0, 0, 0, 29, 1, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+      // Meta Things table.
+      0, 0, 0, 11, 0, 0, 0, 1, 0, 1, b'a',
+      // Things: one Thing with 3 bytes of reserved data.
+      0, 0, 0, 32, 0, 0, 0, 1,
+      0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 1, 2, 3
+    ];
+    let world = save::from_bytes(&bytes).unwrap();
+    assert_eq!(1, world.things.len());
+    assert_eq!(3, world.things[0].direction);
+    assert!(world.things[0].extensions.unknown.is_empty());
+  }
+
+  #[test]
+  fn v1_gets_default_water_height() {
+    let bytes: Vec<u8> = vec![
+      // Header, version 1.
+      0, 7, 0, 1, 0, 1, b'a',
+      // Terrain: 2 x 2 vertices at height 1.
+      0, 0, 0, 29, 1, 0, 0, 0, 2, 0, 0, 0, 2,
+      0x3f, 0x80, 0, 0, 0x3f, 0x80, 0, 0, 0x3f, 0x80, 0, 0, 0x3f, 0x80, 0, 0,
+      // Empty Meta Things table and Things.
+      0, 0, 0, 8, 0, 0, 0, 0,
+      0, 0, 0, 8, 0, 0, 0, 0
+    ];
+    let world = save::from_bytes(&bytes).unwrap();
+    assert_eq!(5.0, world.water_height);
+    assert_eq!(vec![1.0; 4], world.terrain.heights);
+  }
+}
\ No newline at end of file
@@ -0,0 +1,15 @@
+use migrate::Migration;
+
+mod extensions;
+mod migrations;
+mod read;
+mod write;
+
+pub use self::read::{load, from_bytes};
+pub use self::write::{write, to_bytes};
+
+// The latest version of the .city format. See doc/save-file-format.md.
+pub const FORMAT_VERSION: u16 = 2;
+
+// Upgrades from each older version of the format. See migrate.rs.
+const MIGRATIONS: &'static [Migration] = &[migrations::v0_to_v1, migrations::v1_to_v2];
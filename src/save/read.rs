@@ -0,0 +1,216 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use cgmath::Point3;
+
+use binary::Reader;
+use errors::GameError;
+use migrate;
+use terrain::Terrain;
+use world::{World, TerrainStorage, Thing};
+use super::{FORMAT_VERSION, MIGRATIONS};
+use super::extensions;
+
+pub fn load(path: &Path) -> Result<World, GameError> {
+  let mut bytes: Vec<u8> = Vec::new();
+  let mut file = try!(File::open(path));
+  try!(file.read_to_end(&mut bytes));
+  from_bytes(&bytes)
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Result<World, GameError> {
+  let bytes = try!(migrate::upgrade(bytes, FORMAT_VERSION, MIGRATIONS));
+  let mut file = Reader::new(&bytes);
+
+  let mut header = try!(file.section_u16("header"));
+  try!(header.u16()); // Version. Already checked by migrate::upgrade.
+  let name = try!(header.utf8());
+  try!(header.finish());
+
+  let mut terrain_section = try!(file.section_u32("terrain"));
+  let water_height = try!(terrain_section.f32());
+  let (terrain, terrain_storage) = try!(read_terrain(&mut terrain_section));
+  try!(terrain_section.finish());
+
+  let mut table_section = try!(file.section_u32("meta things table"));
+  let count = try!(table_section.u32());
+  let mut table: Vec<String> = Vec::new();
+  for _ in 0..count {
+    table.push(try!(table_section.ascii()));
+  }
+  try!(table_section.finish());
+
+  let mut things_section = try!(file.section_u32("things"));
+  let count = try!(things_section.u32());
+  let mut things: Vec<Thing> = Vec::new();
+  for _ in 0..count {
+    things.push(try!(read_thing(&mut things_section, &table)));
+  }
+  try!(things_section.finish());
+
+  try!(file.finish());
+
+  Ok(World {
+    name: name, terrain: terrain, terrain_storage: terrain_storage,
+    water_height: water_height, things: things
+  })
+}
+
+// Terrain smaller than this on either axis has no cells, which the rest of the game
+// can't work with.
+const MIN_TERRAIN_VERTS: usize = 2;
+
+fn read_terrain(section: &mut Reader) -> Result<(Terrain, TerrainStorage), GameError> {
+  let (terrain, storage) = try!(read_terrain_storage(section));
+  if terrain.x_verts < MIN_TERRAIN_VERTS || terrain.y_verts < MIN_TERRAIN_VERTS {
+    return Err(GameError::Invalid(format!(
+      "Terrain of {} x {} vertices is too small. It must be at least {} x {}.",
+      terrain.x_verts, terrain.y_verts, MIN_TERRAIN_VERTS, MIN_TERRAIN_VERTS
+    )));
+  }
+  Ok((terrain, storage))
+}
+
+fn read_terrain_storage(section: &mut Reader) -> Result<(Terrain, TerrainStorage), GameError> {
+  match try!(section.u8()) {
+    0 => {
+      let path = try!(section.ascii());
+      let vertical_scale = try!(section.f32());
+      let terrain = try!(Terrain::from_image(&Path::new(&path), vertical_scale));
+      Ok((terrain, TerrainStorage::Image {path: path, vertical_scale: vertical_scale}))
+    },
+    1 => {
+      let x_verts = try!(section.u32()) as usize;
+      let y_verts = try!(section.u32()) as usize;
+      // Check the size up front so a corrupt vertex count can't make us allocate
+      // gigabytes. 17 bytes precede the heights: the section size, the water height,
+      // the storage strategy, and the vertex counts.
+      let size = match x_verts.checked_mul(y_verts).and_then(|verts| verts.checked_mul(4)) {
+        Some(size) => size,
+        None => {
+          return Err(GameError::Invalid(format!(
+            "Terrain of {} x {} vertices is too large", x_verts, y_verts
+          )));
+        }
+      };
+      if size != section.remaining() {
+        return Err(GameError::SectionSize {
+          section: "terrain",
+          declared: 17 + section.remaining(),
+          actual: 17 + size
+        });
+      }
+      let mut heights: Vec<f32> = Vec::with_capacity(size / 4);
+      for _ in 0..(size / 4) {
+        heights.push(try!(section.f32()));
+      }
+      Ok((Terrain::new(x_verts, y_verts, heights), TerrainStorage::Inline))
+    },
+    strategy => Err(GameError::Invalid(format!("Unknown terrain storage strategy: {}", strategy)))
+  }
+}
+
+fn read_thing(section: &mut Reader, table: &Vec<String>) -> Result<Thing, GameError> {
+  let offset = section.offset();
+  let id = try!(section.u32()) as usize;
+  let meta_thing = match table.get(id) {
+    Some(name) => name.clone(),
+    None => {
+      return Err(GameError::Invalid(format!(
+        "Thing at offset {} refers to Meta Thing {}, but the table only has {} entries",
+        offset, id, table.len()
+      )));
+    }
+  };
+  let direction = try!(section.u8());
+  if direction > 7 {
+    return Err(GameError::Invalid(format!(
+      "Thing at offset {} has invalid direction {}", offset, direction
+    )));
+  }
+  let x = try!(section.f32());
+  let y = try!(section.f32());
+  let z = try!(section.f32());
+  let reserved_len = try!(section.u32()) as usize;
+  let mut reserved = try!(section.take("thing reserved", reserved_len));
+  let extensions = try!(extensions::read(&mut reserved));
+  try!(reserved.finish());
+  Ok(Thing {
+    meta_thing: meta_thing, direction: direction, origin: Point3::new(x, y, z),
+    extensions: extensions
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+  use errors::GameError;
+  use world::TerrainStorage;
+  use super::*;
+
+  #[test]
+  fn load_test_save() {
+    let world = load(&Path::new("saves/test.city")).unwrap();
+    assert_eq!("river-128x128", world.name);
+    assert_eq!(129, world.terrain.x_verts);
+    assert_eq!(129, world.terrain.y_verts);
+    match world.terrain_storage {
+      TerrainStorage::Image {ref path, ..} => assert_eq!("assets/height/river-128x128.png", path),
+      TerrainStorage::Inline => panic!("Expected image terrain")
+    }
+    assert_eq!(8, world.things.len());
+    for (i, thing) in world.things.iter().enumerate() {
+      assert_eq!("jarrett-test", thing.meta_thing);
+      assert_eq!(i as u8, thing.direction);
+    }
+    assert_eq!(45.0, world.things[0].origin.z);
+    assert_eq!(5.0, world.water_height);
+  }
+
+  #[test]
+  fn bad_terrain_size() {
+    let bytes: Vec<u8> = vec![
+      // Header.
+      0, 7, 0, 2, 0, 1, b'a',
+      // Terrain: water height 0, 2 x 1 vertices, but a section size that only has room
+      // for one float.
+      0, 0, 0, 21, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0
+    ];
+    match from_bytes(&bytes) {
+      Err(GameError::SectionSize {section: "terrain", ..}) => {},
+      _ => panic!("Expected terrain section size error")
+    }
+  }
+
+  #[test]
+  fn tiny_terrain() {
+    for &(x_verts, y_verts) in [(0u8, 0u8), (1, 1), (1, 3), (3, 1)].iter() {
+      let count = x_verts as usize * y_verts as usize;
+      let mut bytes: Vec<u8> = vec![
+        // Header.
+        0, 7, 0, 2, 0, 1, b'a',
+        // Terrain: water height 0, inline heights of 0.
+        0, 0, 0, 17 + 4 * count as u8, 0, 0, 0, 0, 1, 0, 0, 0, x_verts, 0, 0, 0, y_verts
+      ];
+      bytes.extend(vec![0; 4 * count]);
+      match from_bytes(&bytes) {
+        Err(GameError::Invalid(ref msg)) => assert!(msg.contains("too small")),
+        _ => panic!("Expected an error for {} x {} vertices", x_verts, y_verts)
+      }
+    }
+  }
+
+  #[test]
+  fn huge_terrain() {
+    let bytes: Vec<u8> = vec![
+      // Header.
+      0, 7, 0, 2, 0, 1, b'a',
+      // Terrain: 2^32 - 1 vertices on each side, more than a usize can count in bytes.
+      0, 0, 0, 17, 0, 0, 0, 0, 1, 255, 255, 255, 255, 255, 255, 255, 255
+    ];
+    match from_bytes(&bytes) {
+      Err(GameError::Invalid(_)) => {},
+      _ => panic!("Expected an error for an impossible terrain size")
+    }
+  }
+}
\ No newline at end of file
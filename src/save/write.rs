@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use binary;
+use binary::Writer;
+use errors::GameError;
+use world::{World, TerrainStorage};
+use super::FORMAT_VERSION;
+use super::extensions;
+
+// Writes the World to path atomically, so a crash partway through never leaves a corrupt
+// save in place of a good one.
+pub fn write(path: &Path, world: &World) -> Result<(), GameError> {
+  let bytes = try!(to_bytes(world));
+  binary::write_file(path, &bytes)
+}
+
+pub fn to_bytes(world: &World) -> Result<Vec<u8>, GameError> {
+  let mut w = Writer::new();
+
+  let header = w.begin_section_u16();
+  w.u16(FORMAT_VERSION);
+  try!(w.string(&world.name));
+  try!(w.end_section_u16(header));
+
+  let terrain = w.begin_section_u32();
+  w.f32(world.water_height);
+  match world.terrain_storage {
+    TerrainStorage::Image {ref path, vertical_scale} => {
+      w.u8(0);
+      try!(w.string(path));
+      w.f32(vertical_scale);
+    },
+    TerrainStorage::Inline => {
+      w.u8(1);
+      w.u32(world.terrain.x_verts as u32);
+      w.u32(world.terrain.y_verts as u32);
+      for &z in world.terrain.heights.iter() {
+        w.f32(z);
+      }
+    }
+  }
+  try!(w.end_section_u32(terrain));
+
+  // Build a fresh Meta Things table holding only the MetaThings that are actually in
+  // use. IDs are assigned in order of first use, so the same World always produces the
+  // same table.
+  let mut table: Vec<&str> = Vec::new();
+  let mut ids: HashMap<&str, u32> = HashMap::new();
+  for thing in world.things.iter() {
+    let name: &str = &thing.meta_thing;
+    if !ids.contains_key(name) {
+      ids.insert(name, table.len() as u32);
+      table.push(name);
+    }
+  }
+
+  let table_section = w.begin_section_u32();
+  w.u32(table.len() as u32);
+  for name in table.iter() {
+    try!(w.string(name));
+  }
+  try!(w.end_section_u32(table_section));
+
+  let things = w.begin_section_u32();
+  w.u32(world.things.len() as u32);
+  for thing in world.things.iter() {
+    w.u32(ids[&thing.meta_thing as &str]);
+    w.u8(thing.direction);
+    w.f32(thing.origin.x);
+    w.f32(thing.origin.y);
+    w.f32(thing.origin.z);
+    let mut reserved = Writer::new();
+    try!(extensions::write(&mut reserved, &thing.extensions));
+    let reserved: Vec<u8> = reserved.into_bytes();
+    w.u32(reserved.len() as u32);
+    w.bytes(&reserved);
+  }
+  try!(w.end_section_u32(things));
+
+  Ok(w.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use std::fs::File;
+  use std::io::Read;
+  use cgmath::Point3;
+  use save;
+  use terrain::Terrain;
+  use testing;
+  use world::{World, TerrainStorage, Thing, Extensions};
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let mut original: Vec<u8> = Vec::new();
+    File::open("saves/test.city").unwrap().read_to_end(&mut original).unwrap();
+    let world = save::from_bytes(&original).unwrap();
+    let written = to_bytes(&world).unwrap();
+    assert_eq!(original, written);
+    let rewritten = to_bytes(&save::from_bytes(&written).unwrap()).unwrap();
+    assert_eq!(written, rewritten);
+  }
+
+  #[test]
+  fn write_file() {
+    let world = save::load(&Path::new("saves/test.city")).unwrap();
+    let dir = testing::scratch_dir("save-write");
+    let path = dir.join("test.city");
+    write(&path, &world).unwrap();
+    assert_eq!(8, save::load(&path).unwrap().things.len());
+    assert!(!dir.join("test.city.tmp").exists());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn compact_table() {
+    let thing = |name: &str| Thing {
+      meta_thing: name.to_string(), direction: 0, origin: Point3::new(0.0, 0.0, 0.0),
+      extensions: Extensions::default()
+    };
+    let world = World {
+      name: "Test".to_string(),
+      terrain: Terrain::new(2, 2, vec![0.0, 1.0, 2.0, 3.0]),
+      terrain_storage: TerrainStorage::Inline,
+      water_height: 0.5,
+      things: vec![thing("b"), thing("a"), thing("b")]
+    };
+    let loaded = save::from_bytes(&to_bytes(&world).unwrap()).unwrap();
+    assert_eq!(vec![3.0, 1.0], vec![loaded.terrain.heights[3], loaded.terrain.heights[1]]);
+    assert_eq!(0.5, loaded.water_height);
+    let names: Vec<&str> = loaded.things.iter().map(|t| &t.meta_thing as &str).collect();
+    assert_eq!(vec!["b", "a", "b"], names);
+  }
+}
\ No newline at end of file
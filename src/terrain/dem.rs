@@ -0,0 +1,269 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use image;
+use png;
+use png::HasParameters;
+
+use errors::GameError;
+use super::Terrain;
+
+// A digital elevation model: a grid of real-world elevations, e.g. in meters, as
+// downloaded from a mapping agency. Rows run from north to south, the same way they run
+// from top to bottom in a heightmap image, and become the terrain's Y axis in the same
+// order Terrain::from_image uses.
+pub struct Dem {
+  pub columns: usize,
+  pub rows: usize,
+
+  // Indexed by row * columns + column. None where the file has no data.
+  pub elevations: Vec<Option<f32>>
+}
+
+// Byte order of the samples in a raw DEM file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ByteOrder {
+  Little,
+  Big
+}
+
+// How to turn a Dem into a Terrain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DemImport {
+  // Size of the Terrain. The Dem is resampled to fit.
+  pub x_verts: usize,
+  pub y_verts: usize,
+
+  // Elevations that map to Z = 0 and Z = max_height. Elevations outside the range are
+  // clamped to it.
+  pub min_elevation: f32,
+  pub max_elevation: f32,
+  pub max_height: f32,
+
+  // Elevation of the sea, which becomes the world's water height.
+  pub sea_level: f32
+}
+
+impl Dem {
+  // Reads an ESRI ASCII grid, the .asc format most elevation data can be exported as.
+  pub fn from_asc(path: &Path) -> Result<Dem, GameError> {
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+    Dem::parse_asc(&text)
+  }
+
+  // The header is a series of "key value" lines: ncols, nrows, the corner or center
+  // coords, cellsize, and optionally NODATA_value. Elevations follow, separated by any
+  // whitespace. We don't need the coords or cell size, so they're ignored.
+  pub fn parse_asc(text: &str) -> Result<Dem, GameError> {
+    let mut tokens = text.split_whitespace().peekable();
+    let mut columns: Option<usize> = None;
+    let mut rows: Option<usize> = None;
+    let mut nodata: Option<f32> = None;
+    loop {
+      let is_key = tokens.peek().map_or(false, |token|
+        token.chars().next().map_or(false, |c| c.is_alphabetic())
+      );
+      if !is_key {
+        break;
+      }
+      let key = tokens.next().unwrap().to_lowercase();
+      let value = match tokens.next() {
+        Some(value) => value,
+        None => { return Err(asc_error(&format!("{} has no value", key))); }
+      };
+      match key.as_ref() {
+        "ncols" => columns = Some(try!(parse(value, &key))),
+        "nrows" => rows = Some(try!(parse(value, &key))),
+        "nodata_value" => nodata = Some(try!(parse(value, &key))),
+        _ => {}
+      }
+    }
+    let (columns, rows) = match (columns, rows) {
+      (Some(columns), Some(rows)) if columns > 0 && rows > 0 => (columns, rows),
+      _ => { return Err(asc_error("header must give ncols and nrows")); }
+    };
+
+    let mut elevations: Vec<Option<f32>> = Vec::with_capacity(columns * rows);
+    for token in tokens {
+      let elevation: f32 = try!(parse(token, "elevation"));
+      elevations.push(if Some(elevation) == nodata { None } else { Some(elevation) });
+    }
+    if elevations.len() != columns * rows {
+      return Err(asc_error(&format!(
+        "expected {} x {} = {} elevations, but found {}",
+        columns, rows, columns * rows, elevations.len()
+      )));
+    }
+    Ok(Dem {columns: columns, rows: rows, elevations: elevations})
+  }
+
+  // Reads a 16-bit grayscale PNG. Each sample is an elevation from 0 to 65535.
+  pub fn from_png(path: &Path) -> Result<Dem, GameError> {
+    let mut decoder = png::Decoder::new(BufReader::new(try!(File::open(path))));
+    decoder.set(png::Transformations::IDENTITY);
+    let (info, mut reader) = try!(decoder.read_info().map_err(image::ImageError::from));
+    let is_gray16 =
+      info.color_type == png::ColorType::Grayscale && info.bit_depth == png::BitDepth::Sixteen;
+    if !is_gray16 {
+      return Err(GameError::Invalid(format!(
+        "{} is not a 16-bit grayscale PNG", path.display()
+      )));
+    }
+    let mut samples: Vec<u8> = vec![0; info.buffer_size()];
+    try!(reader.next_frame(&mut samples).map_err(image::ImageError::from));
+    Dem::from_samples(&samples, info.width as usize, info.height as usize, ByteOrder::Big)
+  }
+
+  // Reads a headerless file of unsigned 16-bit samples, as exported by many terrain
+  // tools. The size has to be given, since the file doesn't record it.
+  pub fn from_raw(
+    path: &Path, columns: usize, rows: usize, order: ByteOrder
+  ) -> Result<Dem, GameError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    try!(try!(File::open(path)).read_to_end(&mut bytes));
+    Dem::from_samples(&bytes, columns, rows, order)
+  }
+
+  pub fn from_samples(
+    bytes: &[u8], columns: usize, rows: usize, order: ByteOrder
+  ) -> Result<Dem, GameError> {
+    if columns == 0 || rows == 0 || bytes.len() != columns * rows * 2 {
+      return Err(GameError::Invalid(format!(
+        "{} x {} 16-bit samples need {} bytes, but got {}",
+        columns, rows, columns * rows * 2, bytes.len()
+      )));
+    }
+    let elevations: Vec<Option<f32>> = bytes.chunks(2).map(|pair| {
+      let sample = match order {
+        ByteOrder::Big => (pair[0] as u16) << 8 | pair[1] as u16,
+        ByteOrder::Little => (pair[1] as u16) << 8 | pair[0] as u16
+      };
+      Some(sample as f32)
+    }).collect();
+    Ok(Dem {columns: columns, rows: rows, elevations: elevations})
+  }
+
+  // Lowest and highest elevations with data. None if there's no data at all.
+  pub fn elevation_range(&self) -> Option<(f32, f32)> {
+    self.elevations.iter().filter_map(|&e| e).fold(None, |range, e| match range {
+      Some((min, max)) => Some((e.min(min), e.max(max))),
+      None => Some((e, e))
+    })
+  }
+}
+
+impl DemImport {
+  // Keeps the Dem's own size and maps its whole elevation range onto Z = 0 to
+  // max_height, with the sea at elevation 0.
+  pub fn for_dem(dem: &Dem, max_height: f32) -> DemImport {
+    let (min, max) = dem.elevation_range().unwrap_or((0.0, 1.0));
+    DemImport {
+      x_verts: dem.columns, y_verts: dem.rows,
+      min_elevation: min, max_elevation: max, max_height: max_height, sea_level: 0.0
+    }
+  }
+
+  // Z coord for an elevation.
+  pub fn z(&self, elevation: f32) -> f32 {
+    let range = self.max_elevation - self.min_elevation;
+    if range <= 0.0 {
+      return 0.0;
+    }
+    let e = elevation.max(self.min_elevation).min(self.max_elevation);
+    (e - self.min_elevation) / range * self.max_height
+  }
+
+  // The world's water height: the Z coord of the sea level. Unlike terrain heights, this
+  // isn't clamped, so a sea below the elevation range stays below all the terrain.
+  pub fn water_height(&self) -> f32 {
+    let range = self.max_elevation - self.min_elevation;
+    if range <= 0.0 {
+      return 0.0;
+    }
+    (self.sea_level - self.min_elevation) / range * self.max_height
+  }
+
+  // Resamples the Dem to x_verts by y_verts and converts elevations to Z coords. Cells
+  // with no data are treated as lying at the bottom of the elevation range, which is
+  // usually where they are: DEMs of coastlines mark the sea as having no data.
+  pub fn terrain(&self, dem: &Dem) -> Result<Terrain, GameError> {
+    if self.x_verts < 2 || self.y_verts < 2 {
+      return Err(GameError::Invalid(format!(
+        "Terrain must be at least 2 x 2 vertices, not {} x {}", self.x_verts, self.y_verts
+      )));
+    }
+    let source = Terrain::new(dem.columns, dem.rows, dem.elevations.iter()
+      .map(|e| self.z(e.unwrap_or(self.min_elevation)))
+      .collect()
+    );
+    let x_scale = dem.columns.saturating_sub(1) as f32 / (self.x_verts - 1) as f32;
+    let y_scale = dem.rows.saturating_sub(1) as f32 / (self.y_verts - 1) as f32;
+    let mut heights: Vec<f32> = Vec::with_capacity(self.x_verts * self.y_verts);
+    for y in 0..self.y_verts {
+      for x in 0..self.x_verts {
+        heights.push(source.sample_bilinear(x as f32 * x_scale, y as f32 * y_scale));
+      }
+    }
+    Ok(Terrain::new(self.x_verts, self.y_verts, heights))
+  }
+}
+
+fn parse<T: ::std::str::FromStr>(token: &str, what: &str) -> Result<T, GameError> {
+  token.parse().map_err(|_| asc_error(&format!("invalid {}: {}", what, token)))
+}
+
+fn asc_error(msg: &str) -> GameError {
+  GameError::Invalid(format!("Invalid ASCII grid: {}", msg))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ASC: &'static str = "\
+ncols 3
+nrows 2
+xllcorner 500000
+yllcorner 4000000
+cellsize 30
+NODATA_value -9999
+10 20 30
+-9999 40 50
+";
+
+  #[test]
+  fn parse_asc() {
+    let dem = Dem::parse_asc(ASC).unwrap();
+    assert_eq!((3, 2), (dem.columns, dem.rows));
+    assert_eq!(
+      vec![Some(10.0), Some(20.0), Some(30.0), None, Some(40.0), Some(50.0)], dem.elevations
+    );
+    assert_eq!(Some((10.0, 50.0)), dem.elevation_range());
+    assert!(Dem::parse_asc("ncols 2\nnrows 2\n1 2 3").is_err());
+  }
+
+  #[test]
+  fn samples() {
+    let little = Dem::from_samples(&[1, 0, 0, 1], 2, 1, ByteOrder::Little).unwrap();
+    assert_eq!(vec![Some(1.0), Some(256.0)], little.elevations);
+    let big = Dem::from_samples(&[1, 0, 0, 1], 2, 1, ByteOrder::Big).unwrap();
+    assert_eq!(vec![Some(256.0), Some(1.0)], big.elevations);
+    assert!(Dem::from_samples(&[1, 0, 0], 2, 1, ByteOrder::Big).is_err());
+  }
+
+  #[test]
+  fn import() {
+    let dem = Dem::parse_asc(ASC).unwrap();
+    let import = DemImport {
+      x_verts: 5, y_verts: 2,
+      min_elevation: 10.0, max_elevation: 50.0, max_height: 8.0, sea_level: 0.0
+    };
+    let terrain = import.terrain(&dem).unwrap();
+    // Every other vertex lands on a DEM sample. The missing sample is at the bottom of
+    // the range.
+    assert_eq!(vec![0.0, 1.0, 2.0, 3.0, 4.0], terrain.heights[0..5].to_vec());
+    assert_eq!(vec![0.0, 3.0, 6.0, 7.0, 8.0], terrain.heights[5..10].to_vec());
+    assert_eq!(-2.0, import.water_height());
+  }
+}
\ No newline at end of file
@@ -0,0 +1,417 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+
+use super::Terrain;
+use super::random::{Rng, splitmix};
+
+// Weathers a heightfield so it looks less artificial. Thermal weathering crumbles slopes
+// steeper than the talus angle onto the ground below them. Hydraulic erosion drops
+// raindrops at random, each of which runs downhill picking up sediment where it speeds
+// up and dropping it where it slows down, which carves gullies and fills valleys.
+//
+// The same settings and seed always give the same result.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Erosion {
+  pub seed: u64,
+
+  // Number of passes of thermal weathering over the whole map.
+  pub thermal_iterations: usize,
+
+  // Height difference between neighboring vertices above which material slides down.
+  pub talus: f32,
+
+  // Fraction of the excess height that slides per pass, from 0 to 1.
+  pub thermal_rate: f32,
+
+  // Number of raindrops for hydraulic erosion.
+  pub droplets: usize,
+
+  // How much of its direction a droplet keeps each step, from 0 to 1. Higher values give
+  // smoother, straighter gullies.
+  pub inertia: f32,
+
+  // How much sediment a droplet can carry per unit of speed, water, and drop in height.
+  pub capacity: f32,
+
+  // Fraction of the spare capacity a droplet fills by eroding per step, from 0 to 1.
+  pub erode_rate: f32,
+
+  // Fraction of the excess sediment a droplet drops per step, from 0 to 1.
+  pub deposit_rate: f32,
+
+  // Fraction of a droplet's water that evaporates per step, from 0 to 1.
+  pub evaporation: f32,
+
+  // Steps after which a droplet is dropped even if it still has water.
+  pub max_steps: usize,
+
+  // Distance in world units over which a droplet spreads what it erodes. Eroding only
+  // the vertices right around the droplet digs spiky pits.
+  pub erode_radius: f32
+}
+
+impl Default for Erosion {
+  fn default() -> Erosion {
+    Erosion {
+      seed: 0,
+      thermal_iterations: 20, talus: 1.0, thermal_rate: 0.5,
+      droplets: 50000, inertia: 0.3, capacity: 4.0, erode_rate: 0.3, deposit_rate: 0.3,
+      evaporation: 0.02, max_steps: 64, erode_radius: 2.5
+    }
+  }
+}
+
+// Droplets report progress after this many drops.
+const DROPLETS_PER_REPORT: usize = 1000;
+
+// Directions a droplet can wander off in on flat ground, 22.5 degrees apart. These are
+// written out rather than computed with sin and cos, which can differ in the last bit
+// from one platform's math library to the next.
+const WANDER: [(f32, f32); 16] = [
+  (1.0, 0.0), (0.9238795, 0.38268343), (0.70710677, 0.70710677), (0.38268343, 0.9238795),
+  (0.0, 1.0), (-0.38268343, 0.9238795), (-0.70710677, 0.70710677), (-0.9238795, 0.38268343),
+  (-1.0, 0.0), (-0.9238795, -0.38268343), (-0.70710677, -0.70710677), (-0.38268343, -0.9238795),
+  (0.0, -1.0), (0.38268343, -0.9238795), (0.70710677, -0.70710677), (0.9238795, -0.38268343)
+];
+
+impl Erosion {
+  // Erodes the terrain in place. progress is called now and then with the fraction of the
+  // work done so far, from 0 to 1. If it returns false, erosion stops early, leaving the
+  // terrain partly eroded. Returns false if it was stopped.
+  pub fn run(&self, terrain: &mut Terrain, progress: &mut FnMut(f32) -> bool) -> bool {
+    let total = (self.thermal_iterations + self.droplets).max(1) as f32;
+    if !progress(0.0) {
+      return false;
+    }
+    for i in 0..self.thermal_iterations {
+      self.thermal_pass(terrain);
+      if !progress((i + 1) as f32 / total) {
+        return false;
+      }
+    }
+    if terrain.x_verts >= 2 && terrain.y_verts >= 2 {
+      let mut rng = Rng::new(splitmix(self.seed));
+      for i in 0..self.droplets {
+        self.droplet(terrain, &mut rng);
+        if (i + 1) % DROPLETS_PER_REPORT == 0 &&
+           !progress((self.thermal_iterations + i + 1) as f32 / total) {
+          return false;
+        }
+      }
+    }
+    progress(1.0);
+    true
+  }
+
+  fn thermal_pass(&self, terrain: &mut Terrain) {
+    let (x_verts, y_verts) = (terrain.x_verts, terrain.y_verts);
+    // Collect every move before applying any, so the result doesn't depend on the order
+    // we visit vertices in.
+    let mut deltas: Vec<f32> = vec![0.0; terrain.heights.len()];
+    for y in 0..y_verts {
+      for x in 0..x_verts {
+        let i = y * x_verts + x;
+        let z = terrain.heights[i];
+        if x + 1 < x_verts {
+          self.slide(&mut deltas, i, i + 1, z - terrain.heights[i + 1]);
+        }
+        if y + 1 < y_verts {
+          self.slide(&mut deltas, i, i + x_verts, z - terrain.heights[i + x_verts]);
+        }
+      }
+    }
+    for (z, delta) in terrain.heights.iter_mut().zip(deltas.iter()) {
+      *z += *delta;
+    }
+  }
+
+  // Moves material from the higher of two neighboring vertices to the lower one. diff is
+  // the height of a above b.
+  fn slide(&self, deltas: &mut Vec<f32>, a: usize, b: usize, diff: f32) {
+    let excess = diff.abs() - self.talus;
+    if excess <= 0.0 {
+      return;
+    }
+    // Each vertex has up to four neighbors sharing its material, so move at most a
+    // quarter of the excess to any one of them.
+    let amount = excess * self.thermal_rate / 4.0;
+    let (high, low) = if diff > 0.0 { (a, b) } else { (b, a) };
+    deltas[high] -= amount;
+    deltas[low] += amount;
+  }
+
+  fn droplet(&self, terrain: &mut Terrain, rng: &mut Rng) {
+    let x_size = terrain.x_size();
+    let y_size = terrain.y_size();
+    let (mut x, mut y) = (rng.unit() * x_size, rng.unit() * y_size);
+    let (mut dir_x, mut dir_y) = (0.0f32, 0.0f32);
+    let mut speed = 1.0f32;
+    let mut water = 1.0f32;
+    let mut sediment = 0.0f32;
+
+    for _ in 0..self.max_steps {
+      let (height, grad_x, grad_y) = height_and_gradient(terrain, x, y);
+
+      // Turn downhill, keeping some of the old direction.
+      dir_x = dir_x * self.inertia - grad_x * (1.0 - self.inertia);
+      dir_y = dir_y * self.inertia - grad_y * (1.0 - self.inertia);
+      let len = (dir_x * dir_x + dir_y * dir_y).sqrt();
+      if len < 1e-6 {
+        // On flat ground. Wander off in a random direction.
+        let (wander_x, wander_y) = WANDER[rng.below(WANDER.len())];
+        dir_x = wander_x;
+        dir_y = wander_y;
+      } else {
+        dir_x /= len;
+        dir_y /= len;
+      }
+
+      let (old_x, old_y) = (x, y);
+      x += dir_x;
+      y += dir_y;
+      if x < 0.0 || y < 0.0 || x >= x_size || y >= y_size {
+        break;
+      }
+
+      let (new_height, _, _) = height_and_gradient(terrain, x, y);
+      let dz = new_height - height;
+      let capacity = (-dz * speed * water * self.capacity).max(0.01);
+      if dz > 0.0 || sediment > capacity {
+        // Going uphill, fill in the hole behind us. Otherwise drop the excess.
+        let amount = if dz > 0.0 {
+          dz.min(sediment)
+        } else {
+          (sediment - capacity) * self.deposit_rate
+        };
+        sediment -= amount;
+        spread(terrain, old_x, old_y, amount);
+      } else {
+        // Never dig deeper than the drop, or we'd leave a pit.
+        let amount = ((capacity - sediment) * self.erode_rate).min(-dz);
+        sediment += erode_around(terrain, old_x, old_y, amount, self.erode_radius, new_height);
+      }
+
+      speed = (speed * speed - dz).max(0.0).sqrt();
+      water *= 1.0 - self.evaporation;
+    }
+  }
+}
+
+// Bilinear height and its gradient at the given world coords, which must be inside the
+// terrain.
+fn height_and_gradient(terrain: &Terrain, x: f32, y: f32) -> (f32, f32, f32) {
+  let x0 = (x.floor() as usize).min(terrain.x_verts - 2);
+  let y0 = (y.floor() as usize).min(terrain.y_verts - 2);
+  let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+  let h00 = terrain.height_at(x0, y0);
+  let h10 = terrain.height_at(x0 + 1, y0);
+  let h01 = terrain.height_at(x0, y0 + 1);
+  let h11 = terrain.height_at(x0 + 1, y0 + 1);
+  let grad_x = (h10 - h00) * (1.0 - fy) + (h11 - h01) * fy;
+  let grad_y = (h01 - h00) * (1.0 - fx) + (h11 - h10) * fx;
+  let height = h00 * (1.0 - fx) * (1.0 - fy) + h10 * fx * (1.0 - fy) +
+               h01 * (1.0 - fx) * fy + h11 * fx * fy;
+  (height, grad_x, grad_y)
+}
+
+// Adds amount to the ground at the given world coords, split between the four vertices
+// around it by bilinear weight.
+fn spread(terrain: &mut Terrain, x: f32, y: f32, amount: f32) {
+  let x0 = (x.floor() as usize).min(terrain.x_verts - 2);
+  let y0 = (y.floor() as usize).min(terrain.y_verts - 2);
+  let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+  let row = terrain.x_verts;
+  let i = y0 * row + x0;
+  terrain.heights[i] += amount * (1.0 - fx) * (1.0 - fy);
+  terrain.heights[i + 1] += amount * fx * (1.0 - fy);
+  terrain.heights[i + row] += amount * (1.0 - fx) * fy;
+  terrain.heights[i + row + 1] += amount * fx * fy;
+}
+
+// Removes up to amount from the ground around the given world coords, taking the most
+// from the closest vertices and none from those radius or more away. No vertex is
+// lowered below floor, since a hollow lower than where the droplet is heading would draw
+// in the next droplets and only get deeper. Returns the amount actually removed.
+fn erode_around(
+  terrain: &mut Terrain, x: f32, y: f32, amount: f32, radius: f32, floor: f32
+) -> f32 {
+  let min_x = (x - radius).ceil().max(0.0) as usize;
+  let min_y = (y - radius).ceil().max(0.0) as usize;
+  let max_x = ((x + radius).floor() as usize).min(terrain.x_verts - 1);
+  let max_y = ((y + radius).floor() as usize).min(terrain.y_verts - 1);
+  let mut weights: Vec<(usize, f32)> = Vec::new();
+  let mut total = 0.0;
+  for vy in min_y..(max_y + 1) {
+    for vx in min_x..(max_x + 1) {
+      let distance = ((vx as f32 - x).powi(2) + (vy as f32 - y).powi(2)).sqrt();
+      if distance < radius {
+        weights.push((vy * terrain.x_verts + vx, radius - distance));
+        total += radius - distance;
+      }
+    }
+  }
+  let mut removed = 0.0;
+  for &(i, weight) in weights.iter() {
+    let z = terrain.heights[i];
+    let new_z = (z - amount * weight / total).max(floor.min(z));
+    terrain.heights[i] = new_z;
+    removed += z - new_z;
+  }
+  removed
+}
+
+// Erosion running on a background thread, so the game stays responsive on large maps.
+pub struct ErosionJob {
+  // Progress in millionths.
+  progress: Arc<AtomicUsize>,
+  cancelled: Arc<AtomicBool>,
+
+  // Set when the thread stops for any reason, including a panic.
+  done: Arc<AtomicBool>,
+  handle: Option<JoinHandle<Terrain>>
+}
+
+// Sets the flag when dropped, which happens even if the thread panics.
+struct DoneGuard(Arc<AtomicBool>);
+
+impl Drop for DoneGuard {
+  fn drop(&mut self) {
+    self.0.store(true, Ordering::Release);
+  }
+}
+
+impl ErosionJob {
+  // Starts eroding a copy of the terrain.
+  pub fn start(erosion: Erosion, terrain: &Terrain) -> ErosionJob {
+    let mut copy = Terrain::new(terrain.x_verts, terrain.y_verts, terrain.heights.clone());
+    ErosionJob::spawn(move |progress| {
+      erosion.run(&mut copy, progress);
+      copy
+    })
+  }
+
+  // Runs work on a new thread. work is passed a progress callback like Erosion::run's.
+  fn spawn<F: FnOnce(&mut FnMut(f32) -> bool) -> Terrain + Send + 'static>(work: F) -> ErosionJob {
+    let progress = Arc::new(AtomicUsize::new(0));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let done = Arc::new(AtomicBool::new(false));
+    let (thread_progress, thread_cancelled, thread_done) =
+      (progress.clone(), cancelled.clone(), done.clone());
+    let handle = thread::spawn(move || {
+      let _guard = DoneGuard(thread_done);
+      work(&mut |fraction| {
+        thread_progress.store((fraction * 1e6) as usize, Ordering::Relaxed);
+        !thread_cancelled.load(Ordering::Relaxed)
+      })
+    });
+    ErosionJob {progress: progress, cancelled: cancelled, done: done, handle: Some(handle)}
+  }
+
+  // Fraction of the work done so far, from 0 to 1.
+  pub fn progress(&self) -> f32 {
+    self.progress.load(Ordering::Relaxed) as f32 / 1e6
+  }
+
+  // Asks the thread to stop soon. try_finish then returns None.
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::Relaxed);
+  }
+
+  // Whether the thread has stopped, because it finished, was cancelled, or panicked.
+  // try_finish won't return anything more once it has.
+  pub fn is_done(&self) -> bool {
+    self.done.load(Ordering::Acquire)
+  }
+
+  // Returns the eroded terrain once, when the thread has finished. Returns None while
+  // it's still running, if it was cancelled or panicked, and every time after the first.
+  pub fn try_finish(&mut self) -> Option<Terrain> {
+    if !self.done.load(Ordering::Acquire) {
+      return None;
+    }
+    let terrain = match self.handle.take().map(|handle| handle.join()) {
+      Some(Ok(terrain)) => terrain,
+      _ => { return None; }
+    };
+    if self.cancelled.load(Ordering::Relaxed) { None } else { Some(terrain) }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use terrain::{Generator, Terrain};
+  use super::*;
+
+  fn hills(seed: u64) -> Terrain {
+    Generator {seed: seed, x_verts: 33, y_verts: 33, rivers: 0, ..Generator::default()}.generate()
+  }
+
+  fn quick(seed: u64) -> Erosion {
+    Erosion {seed: seed, thermal_iterations: 5, droplets: 2000, ..Erosion::default()}
+  }
+
+  #[test]
+  fn deterministic() {
+    let mut a = hills(1);
+    let mut b = hills(1);
+    quick(5).run(&mut a, &mut |_| true);
+    quick(5).run(&mut b, &mut |_| true);
+    assert_eq!(a.heights, b.heights);
+    assert!(a.heights != hills(1).heights);
+    let mut c = hills(1);
+    quick(6).run(&mut c, &mut |_| true);
+    assert!(a.heights != c.heights);
+  }
+
+  #[test]
+  fn thermal_flattens_cliffs() {
+    // A 2 unit step between the two columns.
+    let mut terrain = Terrain::new(2, 2, vec![0.0, 2.0, 0.0, 2.0]);
+    let erosion = Erosion {thermal_iterations: 50, droplets: 0, ..Erosion::default()};
+    erosion.run(&mut terrain, &mut |_| true);
+    let step = terrain.height_at(1, 0) - terrain.height_at(0, 0);
+    assert!(step <= erosion.talus + 0.01 && step > 0.0);
+    // Material is only moved, never created or destroyed.
+    assert!((terrain.heights.iter().fold(0.0, |sum, z| sum + z) - 4.0).abs() < 0.0001);
+  }
+
+  #[test]
+  fn progress_and_cancel() {
+    let mut reports: Vec<f32> = Vec::new();
+    assert!(quick(1).run(&mut hills(1), &mut |fraction| { reports.push(fraction); true }));
+    assert_eq!(Some(&0.0), reports.first());
+    assert_eq!(Some(&1.0), reports.last());
+    assert!(reports.windows(2).all(|pair| pair[0] <= pair[1]));
+
+    let mut calls = 0;
+    assert!(!quick(1).run(&mut hills(1), &mut |_| { calls += 1; calls < 3 }));
+    assert_eq!(3, calls);
+  }
+
+  #[test]
+  fn background_job() {
+    let terrain = hills(2);
+    let mut job = ErosionJob::start(quick(3), &terrain);
+    let mut eroded: Option<Terrain> = None;
+    while eroded.is_none() {
+      thread::yield_now();
+      eroded = job.try_finish();
+    }
+    assert_eq!(1.0, job.progress());
+    let mut expected = hills(2);
+    quick(3).run(&mut expected, &mut |_| true);
+    assert_eq!(expected.heights, eroded.unwrap().heights);
+    assert!(job.is_done());
+  }
+
+  #[test]
+  fn panicked_job() {
+    let mut job = ErosionJob::spawn(|_| panic!("Erosion failed on purpose"));
+    while !job.is_done() {
+      thread::yield_now();
+    }
+    assert!(job.try_finish().is_none());
+  }
+}
\ No newline at end of file
@@ -0,0 +1,265 @@
+use std::collections::HashSet;
+
+use super::Terrain;
+use super::random::{Rng, splitmix, unit};
+
+// Procedural terrain from layered value noise. Everything is derived from the seed with
+// our own hash and random number generator rather than a library, so the same seed gives
+// the same terrain on every platform and in every future version of the game. Changing
+// how a given seed is turned into terrain breaks every map testers have shared, so don't.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Generator {
+  pub seed: u64,
+  pub x_verts: usize,
+  pub y_verts: usize,
+
+  // Height of the tallest possible peak. Heights are always in [0, max_height].
+  pub max_height: f32,
+
+  // Z coord of the water surface. Land near it is flattened into beaches and rivers are
+  // carved until they reach it.
+  pub sea_level: f32,
+
+  // How much of the map is mountains, from 0 (rolling hills only) to 1.
+  pub mountains: f32,
+
+  // Number of rivers to carve.
+  pub rivers: usize
+}
+
+// Rivers are carved this far below the surrounding ground, in world units.
+const RIVER_DEPTH: f32 = 1.5;
+
+// Distance from a river's center at which its banks meet the ground, in world units.
+const RIVER_WIDTH: f32 = 3.0;
+
+// Offsets to the eight vertices around a vertex.
+const NEIGHBORS: [(i32, i32); 8] = [
+  (-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)
+];
+
+impl Default for Generator {
+  fn default() -> Generator {
+    Generator {
+      seed: 0, x_verts: 257, y_verts: 257,
+      max_height: 40.0, sea_level: 8.0, mountains: 0.5, rivers: 3
+    }
+  }
+}
+
+impl Generator {
+  pub fn generate(&self) -> Terrain {
+    let mut heights: Vec<f32> = Vec::with_capacity(self.x_verts * self.y_verts);
+    for y in 0..self.y_verts {
+      for x in 0..self.x_verts {
+        heights.push(self.height(x as f32, y as f32));
+      }
+    }
+    let mut terrain = Terrain::new(self.x_verts, self.y_verts, heights);
+    let mut rng = Rng::new(hash(self.seed, 3, 0, 0));
+    for _ in 0..self.rivers {
+      self.carve_river(&mut terrain, &mut rng);
+    }
+    terrain
+  }
+
+  // Height before any rivers are carved.
+  fn height(&self, x: f32, y: f32) -> f32 {
+    // Broad hills and valleys, from 0 to half of max_height. Octaves average out toward
+    // the middle, so stretch the result to use the whole range.
+    let hills = fbm(self.seed, 0, x / 96.0, y / 96.0, 5);
+    let hills = smoothstep(0.2, 0.8, hills) * self.max_height * 0.5;
+
+    // Mountain ranges are ridged noise, only raised where a second, lower frequency noise
+    // is high. More mountains lowers the threshold.
+    let threshold = 0.7 - 0.4 * self.mountains.max(0.0).min(1.0);
+    let mask = smoothstep(threshold, threshold + 0.15, fbm(self.seed, 1, x / 192.0, y / 192.0, 2));
+    let ridges = ridged_fbm(self.seed, 2, x / 48.0, y / 48.0, 4);
+    let peaks = mask * ridges * self.max_height * 0.5 * self.mountains.max(0.0).min(1.0);
+
+    // Flatten the ground around the water line into beaches.
+    let z = hills + peaks;
+    let shore = (z - self.sea_level) / 2.0;
+    let z = if shore.abs() < 1.0 {
+      self.sea_level + shore * shore.abs() * 2.0
+    } else {
+      z
+    };
+    z.max(0.0).min(self.max_height)
+  }
+
+  // Starts at a random point above sea level and flows downhill, lowering the ground
+  // along the way, until it reaches the sea or the edge of the map. Where it gets stuck
+  // in a hollow, it cuts through to the lowest neighbor it hasn't visited.
+  fn carve_river(&self, terrain: &mut Terrain, rng: &mut Rng) {
+    if terrain.x_verts < 3 || terrain.y_verts < 3 {
+      return;
+    }
+    // Try a few times to find a source high enough to be worth a river.
+    let mut source: Option<(usize, usize)> = None;
+    for _ in 0..100 {
+      let x = 1 + rng.below(terrain.x_verts - 2);
+      let y = 1 + rng.below(terrain.y_verts - 2);
+      if terrain.height_at(x, y) > self.sea_level + self.max_height * 0.2 {
+        source = Some((x, y));
+        break;
+      }
+    }
+    let (mut x, mut y) = match source {
+      Some(source) => source,
+      None => { return; }
+    };
+
+    let mut path: Vec<(usize, usize)> = Vec::new();
+    let mut visited: HashSet<(usize, usize)> = HashSet::new();
+    loop {
+      path.push((x, y));
+      visited.insert((x, y));
+      let on_edge = x == 0 || y == 0 || x == terrain.x_verts - 1 || y == terrain.y_verts - 1;
+      if on_edge || terrain.height_at(x, y) < self.sea_level {
+        break;
+      }
+      let mut next: Option<(usize, usize, f32)> = None;
+      for &(dx, dy) in NEIGHBORS.iter() {
+        let nx = (x as i32 + dx) as usize;
+        let ny = (y as i32 + dy) as usize;
+        if visited.contains(&(nx, ny)) {
+          continue;
+        }
+        let z = terrain.height_at(nx, ny);
+        if next.map_or(true, |(_, _, best)| z < best) {
+          next = Some((nx, ny, z));
+        }
+      }
+      match next {
+        Some((nx, ny, _)) => { x = nx; y = ny; },
+        None => break
+      }
+    }
+
+    // The river bed never rises on its way downstream.
+    let mut bed = ::std::f32::INFINITY;
+    for &(x, y) in path.iter() {
+      bed = bed.min(terrain.height_at(x, y) - RIVER_DEPTH).max(0.0);
+      carve_around(terrain, x, y, bed);
+    }
+  }
+}
+
+// Lowers the ground within RIVER_WIDTH of (x, y) toward bed, fully at the center and
+// less toward the banks. Never raises anything.
+fn carve_around(terrain: &mut Terrain, x: usize, y: usize, bed: f32) {
+  let reach = RIVER_WIDTH.ceil() as i32;
+  for dy in -reach..(reach + 1) {
+    for dx in -reach..(reach + 1) {
+      let vx = x as i32 + dx;
+      let vy = y as i32 + dy;
+      if vx < 0 || vy < 0 || vx >= terrain.x_verts as i32 || vy >= terrain.y_verts as i32 {
+        continue;
+      }
+      let distance = ((dx * dx + dy * dy) as f32).sqrt();
+      if distance > RIVER_WIDTH {
+        continue;
+      }
+      let i = vy as usize * terrain.x_verts + vx as usize;
+      let z = terrain.heights[i];
+      let carved = bed + (z - bed).max(0.0) * smoothstep(0.0, RIVER_WIDTH, distance);
+      terrain.heights[i] = z.min(carved);
+    }
+  }
+}
+
+// Fractal noise: octaves of value noise, each at twice the frequency and half the
+// amplitude of the last. In [0, 1].
+fn fbm(seed: u64, layer: u64, x: f32, y: f32, octaves: u32) -> f32 {
+  let mut sum = 0.0;
+  let mut total = 0.0;
+  let mut amplitude = 1.0;
+  let mut frequency = 1.0;
+  for octave in 0..octaves {
+    sum += value_noise(seed, layer * 16 + octave as u64, x * frequency, y * frequency) * amplitude;
+    total += amplitude;
+    amplitude *= 0.5;
+    frequency *= 2.0;
+  }
+  sum / total
+}
+
+// Like fbm, but each octave is folded so its midpoint becomes a sharp crest. In [0, 1].
+fn ridged_fbm(seed: u64, layer: u64, x: f32, y: f32, octaves: u32) -> f32 {
+  let mut sum = 0.0;
+  let mut total = 0.0;
+  let mut amplitude = 1.0;
+  let mut frequency = 1.0;
+  for octave in 0..octaves {
+    let n = value_noise(seed, layer * 16 + octave as u64, x * frequency, y * frequency);
+    let ridge = 1.0 - (n * 2.0 - 1.0).abs();
+    sum += ridge * ridge * amplitude;
+    total += amplitude;
+    amplitude *= 0.5;
+    frequency *= 2.0;
+  }
+  sum / total
+}
+
+// Random values at integer lattice points, smoothly interpolated in between. In [0, 1].
+fn value_noise(seed: u64, layer: u64, x: f32, y: f32) -> f32 {
+  let x0 = x.floor();
+  let y0 = y.floor();
+  let fx = smoothstep(0.0, 1.0, x - x0);
+  let fy = smoothstep(0.0, 1.0, y - y0);
+  let (ix, iy) = (x0 as i64, y0 as i64);
+  let corner = |dx: i64, dy: i64| unit(hash(seed, layer, ix + dx, iy + dy));
+  let bottom = corner(0, 0) * (1.0 - fx) + corner(1, 0) * fx;
+  let top = corner(0, 1) * (1.0 - fx) + corner(1, 1) * fx;
+  bottom * (1.0 - fy) + top * fy
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+  let t = ((x - edge0) / (edge1 - edge0)).max(0.0).min(1.0);
+  t * t * (3.0 - 2.0 * t)
+}
+
+// Mixes the inputs into a well distributed 64-bit value.
+fn hash(seed: u64, layer: u64, x: i64, y: i64) -> u64 {
+  let mut h = seed;
+  for &word in [layer, x as u64, y as u64].iter() {
+    h = splitmix(h ^ word);
+  }
+  h
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn small(seed: u64) -> Generator {
+    Generator {seed: seed, x_verts: 65, y_verts: 65, ..Generator::default()}
+  }
+
+  #[test]
+  fn same_seed_same_terrain() {
+    let a = small(42).generate();
+    let b = small(42).generate();
+    assert_eq!(a.heights, b.heights);
+    assert!(small(43).generate().heights != a.heights);
+  }
+
+  #[test]
+  fn heights_in_range() {
+    let generator = Generator {mountains: 1.0, ..small(7)};
+    let (min, max) = generator.generate().z_range();
+    assert!(min >= 0.0);
+    assert!(max <= generator.max_height);
+    assert!(max > min);
+  }
+
+  #[test]
+  fn rivers_only_lower() {
+    let dry = Generator {rivers: 0, ..small(9)}.generate();
+    let wet = Generator {rivers: 4, ..small(9)}.generate();
+    assert!(dry.heights.iter().zip(wet.heights.iter()).all(|(d, w)| w <= d));
+    assert!(dry.heights != wet.heights);
+  }
+}
\ No newline at end of file
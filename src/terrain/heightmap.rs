@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use image;
+use image::{ColorType, GenericImage};
+use image::png::PNGEncoder;
+use png;
+use png::HasParameters;
+
+use errors::GameError;
+use super::Terrain;
+
+impl Terrain {
+  // Loads a heightmap image. Each pixel becomes one vertex, with a Z coord equal to the
+  // pixel's brightness times vertical_scale.
+  // 
+  // Brightness is measured on the 8-bit scale of 0 to 255, regardless of the image's bit
+  // depth. So a 16-bit grayscale PNG produces the same heights as its 8-bit equivalent,
+  // only with finer steps in between. Color images and other formats are converted to
+  // 8-bit grayscale.
+  pub fn from_image(path: &Path, vertical_scale: f32) -> Result<Terrain, GameError> {
+    if let Some(terrain) = try!(from_gray_png(path, vertical_scale)) {
+      return Ok(terrain);
+    }
+    let dyn_img: image::DynamicImage = try!(image::open(path));
+    let (width, height) = dyn_img.dimensions();
+    let heights: Vec<f32> = dyn_img.to_luma().into_raw().iter()
+      .map(|&luma| luma as f32 * vertical_scale)
+      .collect();
+    Ok(Terrain::new(width as usize, height as usize, heights))
+  }
+
+  // Writes the heights as a 16-bit grayscale PNG that from_image turns back into the same
+  // Terrain, give or take rounding. Heights outside what 16 bits can hold at the given
+  // vertical_scale are clamped.
+  pub fn write_image(&self, path: &Path, vertical_scale: f32) -> Result<(), GameError> {
+    let mut samples: Vec<u8> = Vec::with_capacity(self.heights.len() * 2);
+    for &z in self.heights.iter() {
+      let sample = (z / vertical_scale * 256.0).round().max(0.0).min(65535.0) as u16;
+      samples.push((sample >> 8) as u8);
+      samples.push(sample as u8);
+    }
+    let file = try!(File::create(path));
+    try!(PNGEncoder::new(file).encode(
+      &samples, self.x_verts as u32, self.y_verts as u32, ColorType::Gray(16)
+    ));
+    Ok(())
+  }
+}
+
+// Decodes grayscale PNGs with the png crate, since the image crate reduces 16-bit samples
+// to 8 bits. Returns None if the file isn't an 8 or 16-bit grayscale PNG.
+fn from_gray_png(path: &Path, vertical_scale: f32) -> Result<Option<Terrain>, GameError> {
+  let is_png = path.extension().and_then(|e| e.to_str()).map_or(false, |e|
+    e.eq_ignore_ascii_case("png")
+  );
+  if !is_png {
+    return Ok(None);
+  }
+  let mut decoder = png::Decoder::new(BufReader::new(try!(File::open(path))));
+  decoder.set(png::Transformations::IDENTITY);
+  let (info, mut reader) = try!(decoder.read_info().map_err(image::ImageError::from));
+  match (info.color_type, info.bit_depth) {
+    (png::ColorType::Grayscale, png::BitDepth::Eight) |
+    (png::ColorType::Grayscale, png::BitDepth::Sixteen) => {},
+    _ => { return Ok(None); }
+  }
+  let mut samples: Vec<u8> = vec![0; info.buffer_size()];
+  try!(reader.next_frame(&mut samples).map_err(image::ImageError::from));
+  let heights: Vec<f32> = if info.bit_depth == png::BitDepth::Eight {
+    samples.iter().map(|&luma| luma as f32 * vertical_scale).collect()
+  } else {
+    // 16-bit PNG samples are big-endian.
+    samples.chunks(2)
+      .map(|pair| ((pair[0] as u16) << 8 | pair[1] as u16) as f32 / 256.0 * vertical_scale)
+      .collect()
+  };
+  Ok(Some(Terrain::new(info.width as usize, info.height as usize, heights)))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use std::fs::File;
+  use std::path::Path;
+  use image::ColorType;
+  use image::png::PNGEncoder;
+  use terrain::Terrain;
+  use testing;
+
+  #[test]
+  fn color_image() {
+    let terrain = Terrain::from_image(&Path::new("assets/height/river-128x128.png"), 0.1).unwrap();
+    assert_eq!(129, terrain.x_verts);
+    assert_eq!(129, terrain.y_verts);
+  }
+
+  #[test]
+  fn gray_16_bit() {
+    let dir = testing::scratch_dir("heightmap-16");
+    let path = dir.join("heightmap.png");
+    // A 2 x 1 image with samples 256 and 384.
+    let samples: [u8; 4] = [1, 0, 1, 128];
+    PNGEncoder::new(File::create(&path).unwrap())
+      .encode(&samples, 2, 1, ColorType::Gray(16)).unwrap();
+    let terrain = Terrain::from_image(&path, 2.0).unwrap();
+    assert_eq!(vec![2.0, 3.0], terrain.heights);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn write_image_round_trip() {
+    let dir = testing::scratch_dir("heightmap-write");
+    let path = dir.join("heightmap.png");
+    let terrain = Terrain::new(2, 2, vec![0.0, 1.5, 10.25, 40.0]);
+    terrain.write_image(&path, 0.25).unwrap();
+    assert_eq!(terrain.heights, Terrain::from_image(&path, 0.25).unwrap().heights);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
\ No newline at end of file
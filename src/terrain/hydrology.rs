@@ -0,0 +1,480 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use cgmath::Point3;
+
+use super::{Footprint, Region, Terrain};
+
+// Where the water is. Water comes from two places: the sea, which covers everything
+// connected to the edge of the map below the world's water height, and lakes, which fill
+// inland hollows up to the height at which they would spill over their rims.
+//
+// This is a priority flood: starting from the map edges, we visit vertices from lowest to
+// highest water level. Each vertex's level is the higher of its own height and the level
+// of the neighbor we reached it from, so a hollow fills up to its lowest way out.
+pub struct Hydrology {
+  pub x_verts: usize,
+  pub y_verts: usize,
+
+  // The sea level the hydrology was computed for.
+  pub water_height: f32,
+
+  pub bodies: Vec<WaterBody>,
+
+  // Index into bodies of the body covering each vertex, or None if it's dry. Indexed like
+  // Terrain::heights.
+  body_ids: Vec<Option<usize>>,
+
+  // Depth of the water over each cell, averaged over its four corners. Dry corners count
+  // as zero. Indexed by y * (x_verts - 1) + x.
+  cell_depths: Vec<f32>,
+
+  pub shorelines: Vec<Shoreline>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WaterKind {
+  // Touches the edge of the map, always at the world's water height.
+  Sea,
+
+  // Enclosed by land, at the height of its lowest rim.
+  Lake
+}
+
+// One connected body of water.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WaterBody {
+  pub kind: WaterKind,
+
+  // Z coord of the surface.
+  pub level: f32,
+
+  // Number of vertices under water.
+  pub vertex_count: usize,
+
+  pub max_depth: f32,
+
+  // The vertices under water.
+  pub region: Region
+}
+
+// Where the water surface of one body meets the terrain. Points are at the body's level,
+// in order along the shore. Closed shorelines loop back to their first point; the last
+// point isn't repeated. Shorelines that run off the edge of the map are open.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shoreline {
+  // Index into Hydrology::bodies.
+  pub body: usize,
+  pub points: Vec<Point3<f32>>,
+  pub closed: bool
+}
+
+// Lakes shallower than this everywhere are left dry. Otherwise every little dip in the
+// terrain would hold a puddle.
+const MIN_LAKE_DEPTH: f32 = 0.25;
+
+impl Hydrology {
+  pub fn new(terrain: &Terrain, water_height: f32) -> Hydrology {
+    let levels = flood_levels(terrain, water_height);
+    let (bodies, body_ids) = find_bodies(terrain, &levels);
+
+    let x_cells = terrain.x_verts.saturating_sub(1);
+    let y_cells = terrain.y_verts.saturating_sub(1);
+    let depth = |x: usize, y: usize| {
+      let i = y * terrain.x_verts + x;
+      body_ids[i].map_or(0.0, |id| bodies[id].level - terrain.heights[i])
+    };
+    let mut cell_depths: Vec<f32> = Vec::with_capacity(x_cells * y_cells);
+    for y in 0..y_cells {
+      for x in 0..x_cells {
+        let sum = depth(x, y) + depth(x + 1, y) + depth(x + 1, y + 1) + depth(x, y + 1);
+        cell_depths.push(sum / 4.0);
+      }
+    }
+
+    let mut hydrology = Hydrology {
+      x_verts: terrain.x_verts, y_verts: terrain.y_verts, water_height: water_height,
+      bodies: bodies, body_ids: body_ids, cell_depths: cell_depths, shorelines: Vec::new()
+    };
+    for body in 0..hydrology.bodies.len() {
+      let shorelines = hydrology.trace_shorelines(terrain, body);
+      hydrology.shorelines.extend(shorelines);
+    }
+    hydrology
+  }
+
+  // The body of water covering the vertex at the given grid coords, if any.
+  pub fn body_at(&self, x: usize, y: usize) -> Option<&WaterBody> {
+    self.body_ids[y * self.x_verts + x].map(|id| &self.bodies[id])
+  }
+
+  // Height of the water surface over the vertex at the given grid coords, if it's under
+  // water.
+  pub fn level_at(&self, x: usize, y: usize) -> Option<f32> {
+    self.body_at(x, y).map(|body| body.level)
+  }
+
+  // Height of the water table at the given world coords: the surface of the water over
+  // the nearest vertex, or the sea level if that vertex is dry. Coords outside the
+  // terrain are clamped to the nearest edge. The sea level if there are no vertices.
+  pub fn water_table(&self, x: f32, y: f32) -> f32 {
+    if self.x_verts == 0 || self.y_verts == 0 {
+      return self.water_height;
+    }
+    let x = x.max(0.0).min((self.x_verts - 1) as f32).round() as usize;
+    let y = y.max(0.0).min((self.y_verts - 1) as f32).round() as usize;
+    self.level_at(x, y).unwrap_or(self.water_height)
+  }
+
+  // Depth of the water over the cell whose lower left vertex is at the given grid coords.
+  pub fn cell_depth(&self, x: usize, y: usize) -> f32 {
+    self.cell_depths[y * (self.x_verts - 1) + x]
+  }
+
+  // Greatest depth of water over any cell that the footprint overlaps. Zero if it's
+  // entirely on dry land. Like Terrain::max_slope, a footprint with an edge exactly on a
+  // cell boundary doesn't overlap the cell on the other side.
+  pub fn max_depth_under(&self, footprint: &Footprint) -> f32 {
+    let x_cells = self.x_verts.saturating_sub(1);
+    let y_cells = self.y_verts.saturating_sub(1);
+    if x_cells == 0 || y_cells == 0 {
+      return 0.0;
+    }
+    let to_range = |min: f32, max: f32, cells: usize| -> (usize, usize) {
+      let start = (min.floor().max(0.0) as usize).min(cells - 1);
+      let end = (max.ceil().max(0.0) as usize).min(cells);
+      (start, end.max(start + 1))
+    };
+    let (min_x, end_x) = to_range(footprint.min.x, footprint.max.x, x_cells);
+    let (min_y, end_y) = to_range(footprint.min.y, footprint.max.y, y_cells);
+    let mut depth: f32 = 0.0;
+    for y in min_y..end_y {
+      for x in min_x..end_x {
+        depth = depth.max(self.cell_depth(x, y));
+      }
+    }
+    depth
+  }
+
+  // Marching squares over the cells touching the body. The field is the water depth,
+  // which is positive inside the body and at most zero on the dry land around it. A
+  // terrain without any cells has no shorelines.
+  fn trace_shorelines(&self, terrain: &Terrain, body: usize) -> Vec<Shoreline> {
+    if self.x_verts < 2 || self.y_verts < 2 {
+      return Vec::new();
+    }
+    let level = self.bodies[body].level;
+    let region = self.bodies[body].region;
+    let field = |i: usize| -> f32 {
+      let depth = level - terrain.heights[i];
+      if self.body_ids[i] == Some(body) { depth } else { depth.min(0.0) }
+    };
+
+    // Each crossing point is identified by the edge it's on, as a pair of vertex indices,
+    // so that the two cells sharing an edge agree on the point.
+    let mut points: HashMap<(usize, usize), Point3<f32>> = HashMap::new();
+    let mut segments: Vec<((usize, usize), (usize, usize))> = Vec::new();
+    let min_x = region.min_x.saturating_sub(1);
+    let min_y = region.min_y.saturating_sub(1);
+    let max_x = region.max_x.min(self.x_verts.saturating_sub(2));
+    let max_y = region.max_y.min(self.y_verts.saturating_sub(2));
+    for y in min_y..(max_y + 1) {
+      for x in min_x..(max_x + 1) {
+        let corners = [
+          y * self.x_verts + x, y * self.x_verts + x + 1,
+          (y + 1) * self.x_verts + x + 1, (y + 1) * self.x_verts + x
+        ];
+        let values: Vec<f32> = corners.iter().map(|&i| field(i)).collect();
+        let inside: Vec<bool> = values.iter().map(|&v| v > 0.0).collect();
+        // Edge n runs from corner n to corner n + 1.
+        let key = |edge: usize| {
+          let (a, b) = (corners[edge], corners[(edge + 1) % 4]);
+          (a.min(b), a.max(b))
+        };
+        let mut crossings: Vec<usize> = Vec::new();
+        for edge in 0..4 {
+          let (a, b) = (edge, (edge + 1) % 4);
+          if inside[a] != inside[b] {
+            let t = values[a] / (values[a] - values[b]);
+            let (ax, ay) = ((corners[a] % self.x_verts) as f32, (corners[a] / self.x_verts) as f32);
+            let (bx, by) = ((corners[b] % self.x_verts) as f32, (corners[b] / self.x_verts) as f32);
+            points.insert(key(edge), Point3::new(ax + (bx - ax) * t, ay + (by - ay) * t, level));
+            crossings.push(edge);
+          }
+        }
+        match crossings.len() {
+          2 => segments.push((key(crossings[0]), key(crossings[1]))),
+          4 => {
+            // A saddle. Cut off the two corners that are on the other side from the
+            // cell's center, each with a segment between its two edges.
+            let center_inside = values.iter().fold(0.0, |sum, &v| sum + v) > 0.0;
+            for corner in 0..4 {
+              if inside[corner] != center_inside {
+                segments.push((key((corner + 3) % 4), key(corner)));
+              }
+            }
+          },
+          _ => {}
+        }
+      }
+    }
+    chain_segments(body, &segments, &points)
+  }
+}
+
+// Joins segments that share an end point into polylines.
+fn chain_segments(
+  body: usize,
+  segments: &Vec<((usize, usize), (usize, usize))>,
+  points: &HashMap<(usize, usize), Point3<f32>>
+) -> Vec<Shoreline> {
+  let mut by_point: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+  for (i, &(a, b)) in segments.iter().enumerate() {
+    by_point.entry(a).or_insert_with(Vec::new).push(i);
+    by_point.entry(b).or_insert_with(Vec::new).push(i);
+  }
+
+  // Start with the open ends, so each open shoreline is walked from one end to the
+  // other. Whatever is left afterward forms loops. Keys are sorted so the result doesn't
+  // depend on hash order.
+  let mut starts: Vec<(usize, usize)> = by_point.iter()
+    .filter(|&(_, ids)| ids.len() == 1)
+    .map(|(&key, _)| key)
+    .collect();
+  starts.sort();
+  let mut all: Vec<(usize, usize)> = segments.iter().map(|&(a, _)| a).collect();
+  all.sort();
+  starts.extend(all);
+
+  let mut used: Vec<bool> = vec![false; segments.len()];
+  let mut shorelines: Vec<Shoreline> = Vec::new();
+  for start in starts {
+    let mut key = start;
+    let mut keys: Vec<(usize, usize)> = vec![key];
+    loop {
+      let next = by_point[&key].iter().cloned().find(|&i| !used[i]);
+      let segment = match next {
+        Some(segment) => segment,
+        None => break
+      };
+      used[segment] = true;
+      let (a, b) = segments[segment];
+      key = if a == key { b } else { a };
+      keys.push(key);
+    }
+    if keys.len() < 2 {
+      continue;
+    }
+    let closed = keys.len() > 2 && keys[0] == keys[keys.len() - 1];
+    if closed {
+      keys.pop();
+    }
+    shorelines.push(Shoreline {
+      body: body, points: keys.iter().map(|key| points[key]).collect(), closed: closed
+    });
+  }
+  shorelines
+}
+
+// The water level at each vertex. A vertex is under water if its level is above its
+// height.
+fn flood_levels(terrain: &Terrain, water_height: f32) -> Vec<f32> {
+  let (x_verts, y_verts) = (terrain.x_verts, terrain.y_verts);
+  let mut levels: Vec<f32> = vec![::std::f32::NAN; terrain.heights.len()];
+  let mut queue: BinaryHeap<Entry> = BinaryHeap::new();
+  for y in 0..y_verts {
+    for x in 0..x_verts {
+      if x == 0 || y == 0 || x == x_verts - 1 || y == y_verts - 1 {
+        let i = y * x_verts + x;
+        levels[i] = terrain.heights[i].max(water_height);
+        queue.push(Entry {level: levels[i], index: i});
+      }
+    }
+  }
+  while let Some(Entry {level, index}) = queue.pop() {
+    let (x, y) = (index % x_verts, index / x_verts);
+    for &(nx, ny) in neighbors(x, y, x_verts, y_verts).iter() {
+      let n = ny * x_verts + nx;
+      if levels[n].is_nan() {
+        levels[n] = terrain.heights[n].max(level);
+        queue.push(Entry {level: levels[n], index: n});
+      }
+    }
+  }
+  levels
+}
+
+// Labels connected vertices under water as bodies, dropping lakes that are too shallow.
+fn find_bodies(terrain: &Terrain, levels: &Vec<f32>) -> (Vec<WaterBody>, Vec<Option<usize>>) {
+  let (x_verts, y_verts) = (terrain.x_verts, terrain.y_verts);
+  let mut bodies: Vec<WaterBody> = Vec::new();
+  let mut body_ids: Vec<Option<usize>> = vec![None; terrain.heights.len()];
+  let mut visited: Vec<bool> = vec![false; terrain.heights.len()];
+  for start in 0..terrain.heights.len() {
+    if visited[start] || levels[start] <= terrain.heights[start] {
+      continue;
+    }
+    // Neighboring vertices under water always share a level, so a flood fill over wet
+    // vertices finds exactly one body.
+    let mut members: Vec<usize> = Vec::new();
+    let mut stack: Vec<usize> = vec![start];
+    visited[start] = true;
+    while let Some(i) = stack.pop() {
+      members.push(i);
+      for &(nx, ny) in neighbors(i % x_verts, i / x_verts, x_verts, y_verts).iter() {
+        let n = ny * x_verts + nx;
+        if !visited[n] && levels[n] > terrain.heights[n] {
+          visited[n] = true;
+          stack.push(n);
+        }
+      }
+    }
+
+    let level = levels[start];
+    let mut region = Region {
+      min_x: start % x_verts, min_y: start / x_verts,
+      max_x: start % x_verts, max_y: start / x_verts
+    };
+    let mut max_depth: f32 = 0.0;
+    let mut on_edge = false;
+    for &i in members.iter() {
+      let (x, y) = (i % x_verts, i / x_verts);
+      region = region.union(&Region {min_x: x, min_y: y, max_x: x, max_y: y});
+      max_depth = max_depth.max(level - terrain.heights[i]);
+      on_edge |= x == 0 || y == 0 || x == x_verts - 1 || y == y_verts - 1;
+    }
+    let kind = if on_edge { WaterKind::Sea } else { WaterKind::Lake };
+    if kind == WaterKind::Lake && max_depth < MIN_LAKE_DEPTH {
+      continue;
+    }
+    for &i in members.iter() {
+      body_ids[i] = Some(bodies.len());
+    }
+    bodies.push(WaterBody {
+      kind: kind, level: level, vertex_count: members.len(), max_depth: max_depth,
+      region: region
+    });
+  }
+  (bodies, body_ids)
+}
+
+// The up to four vertices sharing an edge with the vertex at (x, y).
+fn neighbors(x: usize, y: usize, x_verts: usize, y_verts: usize) -> Vec<(usize, usize)> {
+  let mut neighbors: Vec<(usize, usize)> = Vec::with_capacity(4);
+  if x > 0 { neighbors.push((x - 1, y)); }
+  if x + 1 < x_verts { neighbors.push((x + 1, y)); }
+  if y > 0 { neighbors.push((x, y - 1)); }
+  if y + 1 < y_verts { neighbors.push((x, y + 1)); }
+  neighbors
+}
+
+// A vertex waiting in the priority flood's queue. BinaryHeap is a max-heap, so the
+// ordering is reversed to pop the lowest level first. Ties go to the lowest index so the
+// result never depends on the heap's internals.
+struct Entry {
+  level: f32,
+  index: usize
+}
+
+impl PartialEq for Entry {
+  fn eq(&self, other: &Entry) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+  fn partial_cmp(&self, other: &Entry) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Entry {
+  fn cmp(&self, other: &Entry) -> Ordering {
+    other.level.partial_cmp(&self.level).unwrap_or(Ordering::Equal)
+      .then_with(|| other.index.cmp(&self.index))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use terrain::{Footprint, Terrain};
+  use super::*;
+
+  // 7 x 5 vertices. The left column is low enough to be sea at water height 1. There's a
+  // hollow in the middle of the right half that spills over a gap at height 4.
+  fn coast_and_lake() -> Terrain {
+    Terrain::new(7, 5, vec![
+      0.0, 5.0, 5.0, 4.0, 5.0, 5.0, 5.0,
+      0.0, 5.0, 5.0, 4.0, 5.0, 5.0, 5.0,
+      0.0, 5.0, 5.0, 2.0, 1.0, 5.0, 5.0,
+      0.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0,
+      0.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0
+    ])
+  }
+
+  #[test]
+  fn sea_and_lake() {
+    let hydrology = Hydrology::new(&coast_and_lake(), 1.0);
+    assert_eq!(2, hydrology.bodies.len());
+
+    let sea = hydrology.body_at(0, 2).unwrap();
+    assert_eq!(WaterKind::Sea, sea.kind);
+    assert_eq!(1.0, sea.level);
+    assert_eq!(5, sea.vertex_count);
+
+    // The lake fills up to the gap at (3, 1), which stays dry.
+    let lake = hydrology.body_at(4, 2).unwrap();
+    assert_eq!(WaterKind::Lake, lake.kind);
+    assert_eq!(4.0, lake.level);
+    assert_eq!(2, lake.vertex_count);
+    assert_eq!(3.0, lake.max_depth);
+    assert_eq!(None, hydrology.body_at(3, 1));
+
+    assert_eq!(4.0, hydrology.water_table(4.2, 1.9));
+    assert_eq!(1.0, hydrology.water_table(6.0, 4.0));
+    assert_eq!(1.25, hydrology.cell_depth(3, 2));
+    assert_eq!(0.5, hydrology.cell_depth(2, 2));
+    assert_eq!(0.0, hydrology.max_depth_under(&Footprint::new(5.0, 0.0, 6.0, 4.0)));
+    assert_eq!(1.25, hydrology.max_depth_under(&Footprint::new(2.5, 1.5, 4.5, 2.5)));
+  }
+
+  #[test]
+  fn shorelines() {
+    let hydrology = Hydrology::new(&coast_and_lake(), 1.0);
+    let sea: Vec<&Shoreline> = hydrology.shorelines.iter().filter(|s| s.body == 0).collect();
+    assert_eq!(1, sea.len());
+    assert!(!sea[0].closed);
+    assert_eq!(5, sea[0].points.len());
+    assert!(sea[0].points.iter().all(|p| (p.x - 0.2).abs() < 0.0001 && p.z == 1.0));
+
+    let lake: Vec<&Shoreline> = hydrology.shorelines.iter().filter(|s| s.body == 1).collect();
+    assert_eq!(1, lake.len());
+    assert!(lake[0].closed);
+    assert_eq!(6, lake[0].points.len());
+    assert!(lake[0].points.iter().all(|p| p.z == 4.0));
+  }
+
+  #[test]
+  fn no_cells() {
+    // A single vertex under water, like the oldest save files can hold.
+    let hydrology = Hydrology::new(&Terrain::new(1, 1, vec![1.0]), 5.0);
+    assert_eq!(1, hydrology.bodies.len());
+    assert!(hydrology.shorelines.is_empty());
+    assert_eq!(5.0, hydrology.water_table(3.0, 3.0));
+    let empty = Hydrology::new(&Terrain::new(0, 0, vec![]), 5.0);
+    assert_eq!(5.0, empty.water_table(0.0, 0.0));
+  }
+
+  #[test]
+  fn shallow_lakes_stay_dry() {
+    let mut terrain = coast_and_lake();
+    terrain.heights[3 * 7 + 3] = 5.0;
+    terrain.heights[1 * 7 + 3] = 5.0;
+    terrain.heights[2 * 7 + 3] = 4.9;
+    terrain.heights[2 * 7 + 4] = 4.9;
+    let hydrology = Hydrology::new(&terrain, 1.0);
+    assert_eq!(1, hydrology.bodies.len());
+    assert_eq!(None, hydrology.body_at(4, 2));
+  }
+}
\ No newline at end of file
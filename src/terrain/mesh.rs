@@ -0,0 +1,178 @@
+use super::Terrain;
+
+// Terrain is drawn in square chunks of this many cells on a side, so that each chunk's
+// vertices fit in a u16 index buffer and an edit only has to re-upload the chunks it
+// touches. Must be a multiple of 1 << (LOD_LEVELS - 1), and no more than 255.
+pub const CHUNK_SIZE: usize = 64;
+
+// Number of levels of detail. Level n uses every 2^n-th vertex along each axis.
+pub const LOD_LEVELS: usize = 4;
+
+// Below this many pixels per cell, we switch to a coarser level of detail.
+const MIN_PIXELS_PER_CELL: f32 = 4.0;
+
+// The cells covered by one chunk. Chunks along the far edges of the terrain may be
+// smaller than CHUNK_SIZE.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChunkRect {
+  pub x: usize,
+  pub y: usize,
+  pub x_cells: usize,
+  pub y_cells: usize
+}
+
+// Vertex data for one chunk, ready to upload to OpenGL. There's one vertex per
+// heightfield vertex in the chunk, including those on its edges, which it shares with
+// its neighbors.
+pub struct Mesh {
+  // x, y, z for each vertex.
+  pub positions: Vec<f32>,
+
+  // x, y, z of the unit normal for each vertex.
+  pub normals: Vec<f32>
+}
+
+impl Mesh {
+  pub fn new(terrain: &Terrain, rect: &ChunkRect) -> Mesh {
+    let vert_count = (rect.x_cells + 1) * (rect.y_cells + 1);
+    let mut positions: Vec<f32> = Vec::with_capacity(vert_count * 3);
+    let mut normals: Vec<f32> = Vec::with_capacity(vert_count * 3);
+    for y in rect.y..(rect.y + rect.y_cells + 1) {
+      for x in rect.x..(rect.x + rect.x_cells + 1) {
+        positions.push(x as f32);
+        positions.push(y as f32);
+        positions.push(terrain.height_at(x, y));
+        // Normals come from the whole terrain, not just the chunk, so that lighting is
+        // continuous across chunk edges.
+        let normal = terrain.normal_at(x, y);
+        normals.push(normal.x);
+        normals.push(normal.y);
+        normals.push(normal.z);
+      }
+    }
+    Mesh {positions: positions, normals: normals}
+  }
+}
+
+// Splits the terrain's cells into chunks, in row-major order.
+pub fn chunk_rects(terrain: &Terrain) -> Vec<ChunkRect> {
+  let x_cells = terrain.x_verts.saturating_sub(1);
+  let y_cells = terrain.y_verts.saturating_sub(1);
+  let mut rects: Vec<ChunkRect> = Vec::new();
+  let mut y = 0;
+  while y < y_cells {
+    let mut x = 0;
+    while x < x_cells {
+      rects.push(ChunkRect {
+        x: x, y: y,
+        x_cells: (x_cells - x).min(CHUNK_SIZE), y_cells: (y_cells - y).min(CHUNK_SIZE)
+      });
+      x += CHUNK_SIZE;
+    }
+    y += CHUNK_SIZE;
+  }
+  rects
+}
+
+// Triangle indices into a chunk's Mesh for the given level of detail. Each quad of the
+// coarser grid is split along the same diagonal as Terrain::cell_triangles.
+// 
+// Every chunk is drawn at the same level, and chunk edges fall on multiples of
+// CHUNK_SIZE, so neighboring chunks always use the same vertices along their shared edge.
+// That's what keeps the seams from cracking. The last row and column are always
+// included, even if they're not a multiple of the level's step, so the far edges of the
+// terrain stay put too.
+pub fn lod_indices(rect: &ChunkRect, level: usize) -> Vec<u16> {
+  let step = 1 << level;
+  let xs = lod_steps(rect.x_cells, step);
+  let ys = lod_steps(rect.y_cells, step);
+  let index = |x: usize, y: usize| (y * (rect.x_cells + 1) + x) as u16;
+  let mut indices: Vec<u16> = Vec::with_capacity(xs.len() * ys.len() * 6);
+  for j in 0..ys.len().saturating_sub(1) {
+    for i in 0..xs.len().saturating_sub(1) {
+      let (x0, x1, y0, y1) = (xs[i], xs[i + 1], ys[j], ys[j + 1]);
+      indices.extend_from_slice(&[
+        index(x0, y0), index(x1, y0), index(x1, y1),
+        index(x0, y0), index(x1, y1), index(x0, y1)
+      ]);
+    }
+  }
+  indices
+}
+
+// Picks a level of detail for a camera zoom. Coarser levels are used as the camera zooms
+// out, so that no triangle is much smaller than a few pixels. See Camera::rebuild_matrices
+// for how zoom relates to pixels.
+pub fn lod_for_zoom(zoom: f32) -> usize {
+  let pixels_per_unit = zoom / 2.0;
+  let mut level = 0;
+  while level < LOD_LEVELS - 1 && pixels_per_unit * ((1 << level) as f32) < MIN_PIXELS_PER_CELL {
+    level += 1;
+  }
+  level
+}
+
+// Vertex coords along one axis of a chunk at the given step, always ending at cells.
+fn lod_steps(cells: usize, step: usize) -> Vec<usize> {
+  let mut steps: Vec<usize> = Vec::new();
+  let mut i = 0;
+  while i < cells {
+    steps.push(i);
+    i += step;
+  }
+  steps.push(cells);
+  steps
+}
+
+#[cfg(test)]
+mod tests {
+  use terrain::Terrain;
+  use super::*;
+
+  #[test]
+  fn full_detail() {
+    let terrain = Terrain::new(3, 2, vec![0.0, 1.0, 2.0, 0.0, 1.0, 2.0]);
+    let rects = chunk_rects(&terrain);
+    assert_eq!(vec![ChunkRect {x: 0, y: 0, x_cells: 2, y_cells: 1}], rects);
+    let mesh = Mesh::new(&terrain, &rects[0]);
+    assert_eq!(18, mesh.positions.len());
+    assert_eq!(18, mesh.normals.len());
+    assert_eq!(vec![0, 1, 4, 0, 4, 3, 1, 2, 5, 1, 5, 4], lod_indices(&rects[0], 0));
+    // The indices must describe the same triangles as cell_triangles.
+    assert_eq!(mesh.positions[5 * 3 + 2], terrain.cell_triangles(1, 0)[0][2].z);
+  }
+
+  #[test]
+  fn seams_line_up() {
+    // 100 cells on each side: one full chunk and one partial chunk of 36 cells per axis.
+    let terrain = Terrain::new(101, 101, vec![0.0; 101 * 101]);
+    let rects = chunk_rects(&terrain);
+    assert_eq!(4, rects.len());
+    assert_eq!(36, rects[1].x_cells);
+    for level in 0..LOD_LEVELS {
+      // Y coords of the vertices each chunk uses along their shared edge, at x = 64.
+      let edge = |rect: &ChunkRect, local_x: usize| -> Vec<u16> {
+        lod_indices(rect, level).into_iter()
+          .filter(|&i| i as usize % (rect.x_cells + 1) == local_x)
+          .map(|i| (i as usize / (rect.x_cells + 1)) as u16)
+          .collect()
+      };
+      let mut left = edge(&rects[0], 64);
+      let mut right = edge(&rects[1], 0);
+      left.sort();
+      left.dedup();
+      right.sort();
+      right.dedup();
+      assert_eq!(left, right);
+      // The far edge of the terrain is always included.
+      assert!(lod_indices(&rects[1], level).contains(&36));
+    }
+  }
+
+  #[test]
+  fn lod_levels() {
+    assert_eq!(0, lod_for_zoom(20.0));
+    assert_eq!(1, lod_for_zoom(6.0));
+    assert_eq!(LOD_LEVELS - 1, lod_for_zoom(0.01));
+  }
+}
@@ -0,0 +1,278 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+mod dem;
+mod erosion;
+mod generate;
+mod heightmap;
+mod hydrology;
+mod mesh;
+mod random;
+mod renderer;
+mod sculpt;
+mod slope;
+mod water;
+
+pub use self::dem::{ByteOrder, Dem, DemImport};
+pub use self::erosion::{Erosion, ErosionJob};
+pub use self::generate::Generator;
+pub use self::hydrology::{Hydrology, WaterBody, WaterKind, Shoreline};
+pub use self::mesh::{Mesh, ChunkRect, CHUNK_SIZE};
+pub use self::renderer::TerrainRenderer;
+pub use self::sculpt::{Brush, BrushKind, Sculptor};
+pub use self::slope::{Footprint, SlopeGrid, triangle_slope};
+pub use self::water::{WaterMesh, WaterRenderer};
+
+// The ground, represented as a grid of vertices with varying Z coords. Vertices are
+// spaced one world unit apart on the X and Y axes, so a Terrain with x_verts vertices
+// along the X axis is x_verts - 1 world units wide.
+pub struct Terrain {
+  pub x_verts: usize,
+  pub y_verts: usize,
+
+  // Z coord of each vertex. Indexed by y * x_verts + x.
+  pub heights: Vec<f32>
+}
+
+// A rectangle of vertices, inclusive on all sides, e.g. the vertices changed by an edit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+  pub min_x: usize,
+  pub min_y: usize,
+  pub max_x: usize,
+  pub max_y: usize
+}
+
+impl Region {
+  // The smallest Region containing both.
+  pub fn union(&self, other: &Region) -> Region {
+    Region {
+      min_x: self.min_x.min(other.min_x), min_y: self.min_y.min(other.min_y),
+      max_x: self.max_x.max(other.max_x), max_y: self.max_y.max(other.max_y)
+    }
+  }
+}
+
+impl Terrain {
+  pub fn new(x_verts: usize, y_verts: usize, heights: Vec<f32>) -> Terrain {
+    if heights.len() != x_verts * y_verts {
+      panic!(
+        "Terrain of {} x {} vertices needs {} heights, but got {}",
+        x_verts, y_verts, x_verts * y_verts, heights.len()
+      );
+    }
+    Terrain {x_verts: x_verts, y_verts: y_verts, heights: heights}
+  }
+
+  pub fn height_at(&self, x: usize, y: usize) -> f32 {
+    self.heights[y * self.x_verts + x]
+  }
+
+  // Size of the terrain in world units along the X axis. Zero if there are no vertices.
+  pub fn x_size(&self) -> f32 {
+    self.x_verts.saturating_sub(1) as f32
+  }
+
+  // Size of the terrain in world units along the Y axis. Zero if there are no vertices.
+  pub fn y_size(&self) -> f32 {
+    self.y_verts.saturating_sub(1) as f32
+  }
+
+  // Lowest and highest Z coords of any vertex.
+  pub fn z_range(&self) -> (f32, f32) {
+    self.heights.iter().fold((::std::f32::INFINITY, ::std::f32::NEG_INFINITY), |(min, max), &z|
+      (min.min(z), max.max(z))
+    )
+  }
+
+  // Height of the vertex closest to the given world coords. Coords outside the terrain
+  // are clamped to the nearest edge. Zero if there are no vertices.
+  pub fn sample_nearest(&self, x: f32, y: f32) -> f32 {
+    if self.x_verts == 0 || self.y_verts == 0 {
+      return 0.0;
+    }
+    let (x, y) = self.clamp(x, y);
+    self.height_at(x.round() as usize, y.round() as usize)
+  }
+
+  // Height at the given world coords, interpolated from the four surrounding vertices.
+  // Coords outside the terrain are clamped to the nearest edge. Zero if there are no
+  // vertices.
+  // 
+  // Note this doesn't exactly match the rendered surface, which is made of triangles. Use
+  // it for smooth sampling, e.g. when resampling a heightmap, not for placing Things.
+  pub fn sample_bilinear(&self, x: f32, y: f32) -> f32 {
+    if self.x_verts == 0 || self.y_verts == 0 {
+      return 0.0;
+    }
+    let (x, y) = self.clamp(x, y);
+    let x0 = (x.floor() as usize).min(self.x_verts.saturating_sub(2));
+    let y0 = (y.floor() as usize).min(self.y_verts.saturating_sub(2));
+    let x1 = (x0 + 1).min(self.x_verts - 1);
+    let y1 = (y0 + 1).min(self.y_verts - 1);
+    let fx = x - x0 as f32;
+    let fy = y - y0 as f32;
+    let bottom = self.height_at(x0, y0) * (1.0 - fx) + self.height_at(x1, y0) * fx;
+    let top = self.height_at(x0, y1) * (1.0 - fx) + self.height_at(x1, y1) * fx;
+    bottom * (1.0 - fy) + top * fy
+  }
+
+  // Height of the rendered surface at the given world coords, on the triangles from
+  // cell_triangles. Coords outside the terrain are clamped to the nearest edge. Zero if
+  // there are no vertices.
+  pub fn surface_height(&self, x: f32, y: f32) -> f32 {
+    if self.x_verts == 0 || self.y_verts == 0 {
+      return 0.0;
+    }
+    let (x, y) = self.clamp(x, y);
+    let x0 = (x.floor() as usize).min(self.x_verts.saturating_sub(2));
+    let y0 = (y.floor() as usize).min(self.y_verts.saturating_sub(2));
+    let x1 = (x0 + 1).min(self.x_verts - 1);
+    let y1 = (y0 + 1).min(self.y_verts - 1);
+    let fx = x - x0 as f32;
+    let fy = y - y0 as f32;
+    let (z00, z11) = (self.height_at(x0, y0), self.height_at(x1, y1));
+    if fx >= fy {
+      z00 + fx * (self.height_at(x1, y0) - z00) + fy * (z11 - self.height_at(x1, y0))
+    } else {
+      z00 + fy * (self.height_at(x0, y1) - z00) + fx * (z11 - self.height_at(x0, y1))
+    }
+  }
+
+  // Unit normal of the vertex at the given grid coords, estimated from the slopes to its
+  // neighbors. Edge vertices use their one-sided slopes.
+  pub fn normal_at(&self, x: usize, y: usize) -> Vector3<f32> {
+    let left = x.saturating_sub(1);
+    let right = (x + 1).min(self.x_verts.saturating_sub(1));
+    let down = y.saturating_sub(1);
+    let up = (y + 1).min(self.y_verts.saturating_sub(1));
+    let dz_dx = if right > left {
+      (self.height_at(right, y) - self.height_at(left, y)) / (right - left) as f32
+    } else {
+      0.0
+    };
+    let dz_dy = if up > down {
+      (self.height_at(x, up) - self.height_at(x, down)) / (up - down) as f32
+    } else {
+      0.0
+    };
+    Vector3::new(-dz_dx, -dz_dy, 1.0).normalize()
+  }
+
+  // Normals for every vertex, indexed the same way as heights. This is the normal
+  // attribute of glsl/terrain.vert.glsl.
+  pub fn normals(&self) -> Vec<Vector3<f32>> {
+    let mut normals: Vec<Vector3<f32>> = Vec::with_capacity(self.heights.len());
+    for y in 0..self.y_verts {
+      for x in 0..self.x_verts {
+        normals.push(self.normal_at(x, y));
+      }
+    }
+    normals
+  }
+
+  // The two triangles covering the one-unit cell whose lower left vertex is (x, y). Each
+  // cell is split along the diagonal from (x, y) to (x + 1, y + 1), and both triangles
+  // wind counter-clockwise seen from above. Rendering, picking, and slope calculations all
+  // use this same split, so they agree about the shape of the ground.
+  pub fn cell_triangles(&self, x: usize, y: usize) -> [[Point3<f32>; 3]; 2] {
+    let p = |x: usize, y: usize| Point3::new(x as f32, y as f32, self.height_at(x, y));
+    [
+      [p(x, y), p(x + 1, y), p(x + 1, y + 1)],
+      [p(x, y), p(x + 1, y + 1), p(x, y + 1)]
+    ]
+  }
+
+  // Finds where the line segment from a to b first meets the ground, e.g. for the ray
+  // under the mouse cursor from Camera::unproject. We march along the segment from
+  // whichever end is higher, then refine by bisection. Returns None if the segment never
+  // passes below the surface over the terrain.
+  pub fn ray_hit(&self, a: Point3<f32>, b: Point3<f32>) -> Option<Point3<f32>> {
+    let (start, end) = if a.z >= b.z { (a, b) } else { (b, a) };
+    let delta = end - start;
+    let horizontal = (delta.x * delta.x + delta.y * delta.y).sqrt();
+    // Quarter-unit steps over the ground, with at least a few steps for a vertical ray.
+    let steps = ((horizontal * 4.0).ceil() as usize).max(4);
+    let below = |t: f32| -> bool {
+      let p = start + delta * t;
+      p.x >= 0.0 && p.y >= 0.0 && p.x <= self.x_size() && p.y <= self.y_size() &&
+        p.z <= self.sample_bilinear(p.x, p.y)
+    };
+    let mut prev = 0.0;
+    for i in 1..(steps + 1) {
+      let t = i as f32 / steps as f32;
+      if below(t) {
+        let (mut lo, mut hi) = (prev, t);
+        for _ in 0..16 {
+          let mid = (lo + hi) / 2.0;
+          if below(mid) { hi = mid; } else { lo = mid; }
+        }
+        return Some(start + delta * hi);
+      }
+      prev = t;
+    }
+    None
+  }
+
+  fn clamp(&self, x: f32, y: f32) -> (f32, f32) {
+    (x.max(0.0).min(self.x_size()), y.max(0.0).min(self.y_size()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // A 3 x 2 terrain that rises by 1 per unit along X.
+  fn ramp() -> Terrain {
+    Terrain::new(3, 2, vec![0.0, 1.0, 2.0, 0.0, 1.0, 2.0])
+  }
+
+  #[test]
+  fn sampling() {
+    let terrain = ramp();
+    assert_eq!(1.0, terrain.sample_nearest(0.6, 0.2));
+    assert_eq!(2.0, terrain.sample_nearest(5.0, -1.0));
+    assert_eq!(1.5, terrain.sample_bilinear(1.5, 0.5));
+    assert_eq!(2.0, terrain.sample_bilinear(2.0, 1.0));
+    assert_eq!((2.0, 1.0), (terrain.x_size(), terrain.y_size()));
+    assert_eq!((0.0, 2.0), terrain.z_range());
+    let empty = Terrain::new(0, 0, vec![]);
+    assert_eq!((0.0, 0.0), (empty.x_size(), empty.y_size()));
+    assert_eq!(0.0, empty.sample_nearest(1.0, 1.0));
+    assert_eq!(0.0, empty.sample_bilinear(1.0, 1.0));
+    assert_eq!(0.0, empty.surface_height(1.0, 1.0));
+    // A single row still samples along it.
+    let row = Terrain::new(3, 1, vec![0.0, 1.0, 2.0]);
+    assert_eq!(2.0, row.sample_nearest(2.0, 5.0));
+    assert_eq!(1.5, row.sample_bilinear(1.5, 0.0));
+    assert_eq!(1.5, row.surface_height(1.5, 0.0));
+    assert_eq!(Vector3::new(-1.0, 0.0, 1.0).normalize(), row.normal_at(1, 0));
+  }
+
+  #[test]
+  fn surface() {
+    // One cell with only the far corner raised. The surface follows the diagonal, where
+    // bilinear sampling would round it off.
+    let terrain = Terrain::new(2, 2, vec![0.0, 0.0, 0.0, 2.0]);
+    assert_eq!(1.0, terrain.surface_height(0.5, 0.5));
+    assert_eq!(0.5, terrain.sample_bilinear(0.5, 0.5));
+    assert_eq!(0.5, terrain.surface_height(0.75, 0.25));
+    assert_eq!(0.5, terrain.surface_height(0.25, 0.75));
+    assert_eq!(1.5, ramp().surface_height(1.5, 0.9));
+  }
+
+  #[test]
+  fn ray_hit() {
+    let terrain = ramp();
+    let hit = terrain.ray_hit(Point3::new(1.5, 0.5, 10.0), Point3::new(1.5, 0.5, -10.0)).unwrap();
+    assert!((hit.z - 1.5).abs() < 0.001);
+    assert!(terrain.ray_hit(Point3::new(9.0, 9.0, 10.0), Point3::new(9.0, 9.0, -10.0)).is_none());
+  }
+
+  #[test]
+  fn normals() {
+    let normal = ramp().normal_at(1, 0);
+    let expected = Vector3::new(-1.0, 0.0, 1.0).normalize();
+    assert!((normal - expected).magnitude() < 0.0001);
+  }
+}
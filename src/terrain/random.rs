@@ -0,0 +1,42 @@
+// Deterministic random numbers for terrain generation and erosion. We use our own rather
+// than a library so that a given seed produces the same terrain on every platform and in
+// every future version of the game.
+
+// The SplitMix64 finalizer. Mixes the bits of x into a well distributed 64-bit value.
+pub fn splitmix(x: u64) -> u64 {
+  let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+  z ^ (z >> 31)
+}
+
+// Maps a 64-bit value to [0, 1).
+pub fn unit(bits: u64) -> f32 {
+  (bits >> 40) as f32 / (1u64 << 24) as f32
+}
+
+// A SplitMix64 random number generator.
+pub struct Rng {
+  state: u64
+}
+
+impl Rng {
+  pub fn new(seed: u64) -> Rng {
+    Rng {state: seed}
+  }
+
+  pub fn next(&mut self) -> u64 {
+    self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+    splitmix(self.state)
+  }
+
+  // A random integer in [0, n).
+  pub fn below(&mut self, n: usize) -> usize {
+    (self.next() % n as u64) as usize
+  }
+
+  // A random float in [0, 1).
+  pub fn unit(&mut self) -> f32 {
+    unit(self.next())
+  }
+}
\ No newline at end of file
@@ -0,0 +1,202 @@
+use std::mem::size_of;
+use std::path::Path;
+use std::ptr;
+use cgmath::{Matrix, Point3};
+use gl;
+use gl::types::*;
+
+use camera::Camera;
+use errors::GameError;
+use opengl::{Attributes, Indices, Program, Texture2d, TextureConfig, Vao, Vbo};
+use super::{Region, Terrain};
+use super::mesh::{Mesh, ChunkRect, LOD_LEVELS, chunk_rects, lod_indices, lod_for_zoom};
+
+// Draws a Terrain with glsl/terrain.vert.glsl and glsl/terrain.frag.glsl. The fragment
+// shader picks between four textures based on height relative to the water and slope.
+// See the constants at the top of the shader.
+// 
+// The terrain is split into chunks, each with its own buffers. See mesh.rs.
+pub struct TerrainRenderer {
+  program: Program,
+  chunks: Vec<Chunk>,
+
+  // In the order of TEXTURE_NAMES.
+  textures: Vec<Texture2d>,
+
+  // Where to draw the cursor ring, and its radius. See set_cursor.
+  cursor: Option<(Point3<f32>, f32)>,
+
+  water_height: f32,
+
+  // Uniform locations.
+  camera_idx: GLint,
+  water_height_idx: GLint,
+  mouse_in_idx: GLint,
+  mouse_position_idx: GLint,
+  cursor_radius_idx: GLint,
+  texture_idxs: Vec<GLint>,
+
+  // Attribute locations.
+  position_idx: GLuint,
+  normal_idx: GLuint
+}
+
+struct Chunk {
+  rect: ChunkRect,
+  vao: Vao,
+  position_buffer: Vbo<Attributes>,
+  normal_buffer: Vbo<Attributes>,
+
+  // One index buffer per level of detail, with its index count. These depend only on the
+  // chunk's size, so they never need to be re-uploaded.
+  lods: Vec<(Vbo<Indices>, usize)>,
+
+  // True if the terrain under this chunk has changed since it was last uploaded.
+  dirty: bool
+}
+
+// Each texture is loaded from assets/textures/<name>.jpg and bound to the sampler uniform
+// of the same name.
+const TEXTURE_NAMES: [&'static str; 4] = ["underwater", "plain", "slope", "cliff"];
+
+impl TerrainRenderer {
+  pub fn new(terrain: &Terrain, water_height: f32) -> Result<TerrainRenderer, GameError> {
+    let program = Program::new(
+      &Path::new("glsl/terrain.vert.glsl"),
+      &Path::new("glsl/terrain.frag.glsl")
+    );
+    let mut textures: Vec<Texture2d> = Vec::with_capacity(TEXTURE_NAMES.len());
+    for name in TEXTURE_NAMES.iter() {
+      let path = format!("assets/textures/{}.jpg", name);
+      textures.push(try!(Texture2d::from_file(&Path::new(&path), &TextureConfig::default())));
+    }
+    let texture_idxs: Vec<GLint> = TEXTURE_NAMES.iter()
+      .map(|name| program.get_uniform_location(name))
+      .collect();
+
+    let mut renderer = TerrainRenderer {
+      camera_idx: program.get_uniform_location("camera"),
+      water_height_idx: program.get_uniform_location("waterHeight"),
+      mouse_in_idx: program.get_uniform_location("mouseIn"),
+      mouse_position_idx: program.get_uniform_location("mousePosition"),
+      cursor_radius_idx: program.get_uniform_location("cursorRadius"),
+      texture_idxs: texture_idxs,
+      position_idx: program.get_attrib_location("position"),
+      normal_idx: program.get_attrib_location("normal"),
+      program: program,
+      chunks: Vec::new(),
+      textures: textures,
+      cursor: None,
+      water_height: water_height
+    };
+    for rect in chunk_rects(terrain) {
+      let chunk = renderer.new_chunk(terrain, rect);
+      renderer.chunks.push(chunk);
+    }
+    Ok(renderer)
+  }
+
+  // Marks the chunks containing any vertex in the region as needing to be re-uploaded.
+  // Chunks one vertex beyond the region are included too, because their normals depend
+  // on the region's heights. Call after editing the terrain, then call flush before the
+  // next draw.
+  pub fn mark_dirty(&mut self, region: &Region) {
+    let min_x = region.min_x.saturating_sub(1);
+    let min_y = region.min_y.saturating_sub(1);
+    let max_x = region.max_x + 1;
+    let max_y = region.max_y + 1;
+    for chunk in self.chunks.iter_mut() {
+      let rect = chunk.rect;
+      if rect.x <= max_x && rect.x + rect.x_cells >= min_x &&
+         rect.y <= max_y && rect.y + rect.y_cells >= min_y {
+        chunk.dirty = true;
+      }
+    }
+  }
+
+  // Re-uploads every dirty chunk. Untouched chunks keep their buffers, so small edits
+  // stay cheap on large maps.
+  pub fn flush(&mut self, terrain: &Terrain) {
+    for chunk in self.chunks.iter_mut() {
+      if chunk.dirty {
+        chunk.buffer(terrain);
+      }
+    }
+  }
+
+  // Shows a ring of the given radius around a point on the terrain, e.g. the area a
+  // sculpting brush will affect. Pass None to hide it.
+  pub fn set_cursor(&mut self, cursor: Option<(Point3<f32>, f32)>) {
+    self.cursor = cursor;
+  }
+
+  pub fn set_water_height(&mut self, water_height: f32) {
+    self.water_height = water_height;
+  }
+
+  pub fn draw(&self, camera: &Camera) {
+    let level = lod_for_zoom(camera.zoom);
+    unsafe {
+      gl::UseProgram(self.program.id);
+      gl::UniformMatrix4fv(self.camera_idx, 1, gl::FALSE, camera.transform.as_ptr());
+      gl::Uniform1f(self.water_height_idx, self.water_height);
+      match self.cursor {
+        Some((position, radius)) => {
+          gl::Uniform1ui(self.mouse_in_idx, 1);
+          gl::Uniform3f(self.mouse_position_idx, position.x, position.y, position.z);
+          gl::Uniform1f(self.cursor_radius_idx, radius);
+        },
+        None => gl::Uniform1ui(self.mouse_in_idx, 0)
+      }
+      for (slot, texture) in self.textures.iter().enumerate() {
+        texture.bind(self.texture_idxs[slot], slot as u8);
+      }
+      for chunk in self.chunks.iter() {
+        let (ref index_buffer, index_count) = chunk.lods[level];
+        chunk.vao.bind();
+        index_buffer.bind();
+        gl::DrawElements(gl::TRIANGLES, index_count as GLsizei, gl::UNSIGNED_SHORT, ptr::null());
+        index_buffer.unbind();
+        chunk.vao.unbind();
+      }
+      gl::UseProgram(0);
+    }
+  }
+
+  fn new_chunk(&self, terrain: &Terrain, rect: ChunkRect) -> Chunk {
+    let mut chunk = Chunk {
+      rect: rect,
+      vao: Vao::new(),
+      position_buffer: Vbo::new(),
+      normal_buffer: Vbo::new(),
+      lods: Vec::with_capacity(LOD_LEVELS),
+      dirty: true
+    };
+    unsafe { chunk.vao.bind(); }
+    chunk.vao.attrib(&chunk.position_buffer, self.position_idx, 3, gl::FLOAT, 0, 0);
+    chunk.vao.attrib(&chunk.normal_buffer, self.normal_idx, 3, gl::FLOAT, 0, 0);
+    unsafe { chunk.vao.unbind(); }
+
+    for level in 0..LOD_LEVELS {
+      let indices = lod_indices(&rect, level);
+      let mut index_buffer: Vbo<Indices> = Vbo::new();
+      index_buffer.buffer_data(indices.len() * size_of::<GLushort>(), &indices, gl::STATIC_DRAW);
+      chunk.lods.push((index_buffer, indices.len()));
+    }
+    chunk.buffer(terrain);
+    chunk
+  }
+}
+
+impl Chunk {
+  fn buffer(&mut self, terrain: &Terrain) {
+    let mesh = Mesh::new(terrain, &self.rect);
+    self.position_buffer.buffer_data(
+      mesh.positions.len() * size_of::<GLfloat>(), &mesh.positions, gl::DYNAMIC_DRAW
+    );
+    self.normal_buffer.buffer_data(
+      mesh.normals.len() * size_of::<GLfloat>(), &mesh.normals, gl::DYNAMIC_DRAW
+    );
+    self.dirty = false;
+  }
+}
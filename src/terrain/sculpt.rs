@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use cgmath::Point2;
+
+use super::{Region, Terrain};
+
+// Terrain editing with brushes. A stroke is everything from pressing the mouse button to
+// releasing it. During a stroke, the brush is applied once per frame (a "dab") wherever
+// the cursor is. Each finished stroke can be undone as a whole.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrushKind {
+  Raise,
+  Lower,
+
+  // Pulls the ground toward the height under the cursor when the stroke began.
+  Flatten,
+
+  // Pulls each vertex toward the average of its neighbors.
+  Smooth,
+
+  // Pulls the ground toward Brush::target.
+  Level
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Brush {
+  pub kind: BrushKind,
+
+  // In world units. The brush has full effect at its center and fades to none at this
+  // distance.
+  pub radius: f32,
+
+  // For Raise and Lower, how far the center moves per dab, in world units. For the other
+  // kinds, what fraction of the way to the goal height the center moves per dab, from 0
+  // to 1.
+  pub strength: f32,
+
+  // Goal height for BrushKind::Level.
+  pub target: f32
+}
+
+// Applies brushes to a Terrain and keeps the undo history.
+pub struct Sculptor {
+  pub brush: Brush,
+  stroke: Option<Stroke>,
+  history: Vec<Stroke>
+}
+
+struct Stroke {
+  // Height of each vertex the stroke has changed, from before the stroke began. Keyed by
+  // index into Terrain::heights.
+  original: BTreeMap<usize, f32>,
+
+  // Goal height for BrushKind::Flatten.
+  flatten_height: f32
+}
+
+// Undo history is capped so a long editing session can't grow without bound.
+const MAX_HISTORY: usize = 100;
+
+impl Default for Brush {
+  fn default() -> Brush {
+    Brush {kind: BrushKind::Raise, radius: 4.0, strength: 0.1, target: 0.0}
+  }
+}
+
+impl Sculptor {
+  pub fn new(brush: Brush) -> Sculptor {
+    Sculptor {brush: brush, stroke: None, history: Vec::new()}
+  }
+
+  pub fn in_stroke(&self) -> bool {
+    self.stroke.is_some()
+  }
+
+  pub fn begin_stroke(&mut self, terrain: &Terrain, at: Point2<f32>) {
+    self.stroke = Some(Stroke {
+      original: BTreeMap::new(),
+      flatten_height: terrain.sample_bilinear(at.x, at.y)
+    });
+  }
+
+  // Applies the brush once, centered at the given world coords. Returns the vertices
+  // that changed, if any. Does nothing outside a stroke.
+  pub fn dab(&mut self, terrain: &mut Terrain, at: Point2<f32>) -> Option<Region> {
+    let brush = self.brush;
+    let stroke = match self.stroke {
+      Some(ref mut stroke) => stroke,
+      None => { return None; }
+    };
+    if terrain.x_verts == 0 || terrain.y_verts == 0 || brush.radius <= 0.0 {
+      return None;
+    }
+    let min_x = (at.x - brush.radius).ceil().max(0.0) as usize;
+    let min_y = (at.y - brush.radius).ceil().max(0.0) as usize;
+    let max_x = ((at.x + brush.radius).floor().max(0.0) as usize).min(terrain.x_verts - 1);
+    let max_y = ((at.y + brush.radius).floor().max(0.0) as usize).min(terrain.y_verts - 1);
+    if min_x > max_x || min_y > max_y {
+      return None;
+    }
+
+    // Compute every new height before writing any, so that Smooth sees the heights from
+    // before this dab.
+    let mut changes: Vec<(usize, f32)> = Vec::new();
+    for y in min_y..(max_y + 1) {
+      for x in min_x..(max_x + 1) {
+        let distance = ((x as f32 - at.x).powi(2) + (y as f32 - at.y).powi(2)).sqrt();
+        if distance >= brush.radius {
+          continue;
+        }
+        let falloff = (1.0 - (distance / brush.radius).powi(2)).powi(2);
+        let z = terrain.height_at(x, y);
+        let pull = |goal: f32| z + (goal - z) * (brush.strength * falloff).min(1.0);
+        let new_z = match brush.kind {
+          BrushKind::Raise => z + brush.strength * falloff,
+          BrushKind::Lower => z - brush.strength * falloff,
+          BrushKind::Flatten => pull(stroke.flatten_height),
+          BrushKind::Smooth => pull(neighbor_average(terrain, x, y)),
+          BrushKind::Level => pull(brush.target)
+        };
+        if new_z != z {
+          changes.push((y * terrain.x_verts + x, new_z));
+        }
+      }
+    }
+    if changes.is_empty() {
+      return None;
+    }
+    for &(index, new_z) in changes.iter() {
+      let old_z = terrain.heights[index];
+      stroke.original.entry(index).or_insert(old_z);
+      terrain.heights[index] = new_z;
+    }
+    Some(Region {min_x: min_x, min_y: min_y, max_x: max_x, max_y: max_y})
+  }
+
+  // Finishes the current stroke, adding it to the undo history if it changed anything.
+  pub fn end_stroke(&mut self) {
+    if let Some(stroke) = self.stroke.take() {
+      if !stroke.original.is_empty() {
+        self.history.push(stroke);
+        if self.history.len() > MAX_HISTORY {
+          self.history.remove(0);
+        }
+      }
+    }
+  }
+
+  // Replaces every height at once, e.g. with the result of erosion, as a single step in
+  // the undo history. Returns the vertices that changed, if any. heights must be the same
+  // length as the terrain's.
+  pub fn replace_heights(&mut self, terrain: &mut Terrain, heights: Vec<f32>) -> Option<Region> {
+    self.end_stroke();
+    let mut stroke = Stroke {original: BTreeMap::new(), flatten_height: 0.0};
+    let mut region: Option<Region> = None;
+    for (index, (z, new_z)) in terrain.heights.iter_mut().zip(heights.into_iter()).enumerate() {
+      if *z != new_z {
+        stroke.original.insert(index, *z);
+        *z = new_z;
+        let (x, y) = (index % terrain.x_verts, index / terrain.x_verts);
+        let vertex = Region {min_x: x, min_y: y, max_x: x, max_y: y};
+        region = Some(region.map_or(vertex, |r| r.union(&vertex)));
+      }
+    }
+    self.stroke = Some(stroke);
+    self.end_stroke();
+    region
+  }
+
+  // Reverts the most recent finished stroke. Returns the vertices that changed. An
+  // unfinished stroke is ended first, so it's the one that gets undone.
+  pub fn undo(&mut self, terrain: &mut Terrain) -> Option<Region> {
+    self.end_stroke();
+    let stroke = match self.history.pop() {
+      Some(stroke) => stroke,
+      None => { return None; }
+    };
+    let mut region: Option<Region> = None;
+    for (&index, &z) in stroke.original.iter() {
+      terrain.heights[index] = z;
+      let (x, y) = (index % terrain.x_verts, index / terrain.x_verts);
+      let vertex = Region {min_x: x, min_y: y, max_x: x, max_y: y};
+      region = Some(region.map_or(vertex, |r| r.union(&vertex)));
+    }
+    region
+  }
+}
+
+fn neighbor_average(terrain: &Terrain, x: usize, y: usize) -> f32 {
+  let mut sum = 0.0;
+  let mut count = 0;
+  for &(dx, dy) in [(-1i32, 0i32), (1, 0), (0, -1), (0, 1)].iter() {
+    let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+    if nx >= 0 && ny >= 0 && (nx as usize) < terrain.x_verts && (ny as usize) < terrain.y_verts {
+      sum += terrain.height_at(nx as usize, ny as usize);
+      count += 1;
+    }
+  }
+  if count == 0 { terrain.height_at(x, y) } else { sum / count as f32 }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::Point2;
+  use terrain::Terrain;
+  use super::*;
+
+  fn flat() -> Terrain {
+    Terrain::new(9, 9, vec![1.0; 81])
+  }
+
+  #[test]
+  fn raise_and_undo() {
+    let mut terrain = flat();
+    let mut sculptor = Sculptor::new(Brush {kind: BrushKind::Raise, radius: 2.0, strength: 0.5, target: 0.0});
+    let center = Point2::new(4.0, 4.0);
+    sculptor.begin_stroke(&terrain, center);
+    let region = sculptor.dab(&mut terrain, center).unwrap();
+    sculptor.dab(&mut terrain, center);
+    sculptor.end_stroke();
+    assert_eq!(Region {min_x: 2, min_y: 2, max_x: 6, max_y: 6}, region);
+    assert_eq!(2.0, terrain.height_at(4, 4));
+    assert!(terrain.height_at(5, 4) > 1.0 && terrain.height_at(5, 4) < 2.0);
+    assert_eq!(1.0, terrain.height_at(6, 4));
+
+    // Both dabs were one stroke, so one undo reverts them.
+    sculptor.undo(&mut terrain).unwrap();
+    assert_eq!(vec![1.0; 81], terrain.heights);
+    assert!(sculptor.undo(&mut terrain).is_none());
+  }
+
+  #[test]
+  fn replace_heights() {
+    let mut terrain = flat();
+    let mut sculptor = Sculptor::new(Brush::default());
+    let mut heights = vec![1.0; 81];
+    heights[2 * 9 + 3] = 0.0;
+    heights[5 * 9 + 1] = 2.0;
+    let region = sculptor.replace_heights(&mut terrain, heights.clone()).unwrap();
+    assert_eq!(Region {min_x: 1, min_y: 2, max_x: 3, max_y: 5}, region);
+    assert_eq!(heights, terrain.heights);
+    sculptor.undo(&mut terrain).unwrap();
+    assert_eq!(vec![1.0; 81], terrain.heights);
+  }
+
+  #[test]
+  fn level_and_flatten() {
+    let mut terrain = flat();
+    terrain.heights[4 * 9 + 4] = 3.0;
+    let mut sculptor = Sculptor::new(Brush {kind: BrushKind::Level, radius: 1.5, strength: 1.0, target: 0.0});
+    sculptor.begin_stroke(&terrain, Point2::new(4.0, 4.0));
+    sculptor.dab(&mut terrain, Point2::new(4.0, 4.0));
+    sculptor.end_stroke();
+    assert_eq!(0.0, terrain.height_at(4, 4));
+
+    // Flatten toward the height where the stroke started, at (0, 0).
+    sculptor.brush.kind = BrushKind::Flatten;
+    sculptor.begin_stroke(&terrain, Point2::new(0.0, 0.0));
+    sculptor.dab(&mut terrain, Point2::new(4.0, 4.0));
+    sculptor.end_stroke();
+    assert_eq!(1.0, terrain.height_at(4, 4));
+  }
+
+  #[test]
+  fn smooth() {
+    let mut terrain = flat();
+    terrain.heights[4 * 9 + 4] = 5.0;
+    let mut sculptor = Sculptor::new(Brush {kind: BrushKind::Smooth, radius: 1.0, strength: 1.0, target: 0.0});
+    sculptor.begin_stroke(&terrain, Point2::new(4.0, 4.0));
+    sculptor.dab(&mut terrain, Point2::new(4.0, 4.0));
+    assert_eq!(1.0, terrain.height_at(4, 4));
+  }
+}
\ No newline at end of file
@@ -0,0 +1,251 @@
+use std::path::Path;
+use cgmath::{Point2, Point3};
+use image;
+
+use errors::GameError;
+use super::Terrain;
+
+// Slope queries, using the definition in doc/thing-file-format.md: the slope of a terrain
+// triangle is rise over run of its side with the greatest Z difference.
+
+// An axis-aligned rectangle on the XY plane, in world units, e.g. the base of a Thing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Footprint {
+  pub min: Point2<f32>,
+  pub max: Point2<f32>
+}
+
+// The slope of every cell of a Terrain, where a cell's slope is the greater of its two
+// triangles' slopes.
+pub struct SlopeGrid {
+  pub x_cells: usize,
+  pub y_cells: usize,
+
+  // Indexed by y * x_cells + x.
+  pub slopes: Vec<f32>
+}
+
+// Rise over run of the side with the greatest Z difference. Of sides with equal Z
+// differences, the steepest is measured, so the order of the vertices doesn't matter.
+pub fn triangle_slope(triangle: &[Point3<f32>; 3]) -> f32 {
+  let mut steepest = (0.0, 0.0); // (rise, rise / run) of the side measured.
+  for &(a, b) in [(0, 1), (1, 2), (2, 0)].iter() {
+    let (p, q) = (triangle[a], triangle[b]);
+    let rise = (q.z - p.z).abs();
+    let run = ((q.x - p.x).powi(2) + (q.y - p.y).powi(2)).sqrt();
+    let slope = if rise == 0.0 {
+      0.0
+    } else if run == 0.0 {
+      ::std::f32::INFINITY
+    } else {
+      rise / run
+    };
+    if rise > steepest.0 || (rise == steepest.0 && slope > steepest.1) {
+      steepest = (rise, slope);
+    }
+  }
+  steepest.1
+}
+
+impl Footprint {
+  pub fn new(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Footprint {
+    Footprint {min: Point2::new(min_x, min_y), max: Point2::new(max_x, max_y)}
+  }
+}
+
+impl Terrain {
+  // Slopes of the two triangles in the cell whose lower left vertex is (x, y), in the
+  // same order as cell_triangles.
+  pub fn cell_slopes(&self, x: usize, y: usize) -> [f32; 2] {
+    let triangles = self.cell_triangles(x, y);
+    [triangle_slope(&triangles[0]), triangle_slope(&triangles[1])]
+  }
+
+  // The greatest slope of any triangle in a cell the footprint overlaps.
+  pub fn max_slope(&self, footprint: &Footprint) -> f32 {
+    let mut max = 0.0f32;
+    self.each_cell(footprint, |x, y| {
+      let slopes = self.cell_slopes(x, y);
+      max = max.max(slopes[0]).max(slopes[1]);
+    });
+    max
+  }
+
+  // The lowest and highest vertices of the cells the footprint overlaps.
+  pub fn height_range_under(&self, footprint: &Footprint) -> (f32, f32) {
+    let mut range = (::std::f32::INFINITY, ::std::f32::NEG_INFINITY);
+    self.each_cell(footprint, |x, y| {
+      for &(vx, vy) in [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)].iter() {
+        let z = self.height_at(vx, vy);
+        range = (range.0.min(z), range.1.max(z));
+      }
+    });
+    range
+  }
+
+  // The lowest and highest points of the surface within the footprint, which is clamped
+  // to the terrain. Unlike height_range_under, this only counts the ground actually
+  // inside the footprint. The surface is flat on each triangle, so its extremes are on
+  // the footprint's edges or at the vertices inside it.
+  pub fn surface_range_under(&self, footprint: &Footprint) -> (f32, f32) {
+    let (min_x, min_y) = (footprint.min.x.max(0.0), footprint.min.y.max(0.0));
+    let (max_x, max_y) = (footprint.max.x.min(self.x_size()), footprint.max.y.min(self.y_size()));
+    let mut points: Vec<Point3<f32>> = Vec::new();
+    for y in integers(min_y, max_y) {
+      for x in integers(min_x, max_x) {
+        points.push(Point3::new(x as f32, y as f32, self.height_at(x as usize, y as usize)));
+      }
+    }
+    let corners = [
+      Point2::new(min_x, min_y), Point2::new(max_x, min_y),
+      Point2::new(max_x, max_y), Point2::new(min_x, max_y)
+    ];
+    for i in 0..4 {
+      points.extend(self.surface_profile(corners[i], corners[(i + 1) % 4]));
+    }
+    points.iter().fold((::std::f32::INFINITY, ::std::f32::NEG_INFINITY), |(min, max), p|
+      (min.min(p.z), max.max(p.z))
+    )
+  }
+
+  // Points on the surface along the line segment from a to b: both ends, and everywhere
+  // in between that the segment crosses a side of a triangle, in order from a. The
+  // surface is a straight line between consecutive points.
+  pub fn surface_profile(&self, a: Point2<f32>, b: Point2<f32>) -> Vec<Point3<f32>> {
+    let mut ts: Vec<f32> = vec![0.0, 1.0];
+    // Triangle sides lie where x, y, or x - y is an integer.
+    let lines = [(a.x, b.x), (a.y, b.y), (a.x - a.y, b.x - b.y)];
+    for &(from, to) in lines.iter() {
+      if from != to {
+        for k in integers(from.min(to), from.max(to)) {
+          ts.push((k as f32 - from) / (to - from));
+        }
+      }
+    }
+    ts.sort_by(|t, u| t.partial_cmp(u).unwrap());
+    ts.dedup_by(|t, u| (*t - *u).abs() < 0.00001);
+    ts.iter().map(|&t| {
+      let (x, y) = (a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t);
+      Point3::new(x, y, self.surface_height(x, y))
+    }).collect()
+  }
+
+  pub fn slope_grid(&self) -> SlopeGrid {
+    let x_cells = self.x_verts.saturating_sub(1);
+    let y_cells = self.y_verts.saturating_sub(1);
+    let mut slopes: Vec<f32> = Vec::with_capacity(x_cells * y_cells);
+    for y in 0..y_cells {
+      for x in 0..x_cells {
+        let cell = self.cell_slopes(x, y);
+        slopes.push(cell[0].max(cell[1]));
+      }
+    }
+    SlopeGrid {x_cells: x_cells, y_cells: y_cells, slopes: slopes}
+  }
+
+  // Calls f with the coords of each cell the footprint overlaps. The footprint is clamped
+  // to the terrain. A footprint with an edge exactly on a cell boundary doesn't overlap
+  // the cell on the other side.
+  fn each_cell<F: FnMut(usize, usize)>(&self, footprint: &Footprint, mut f: F) {
+    let x_cells = self.x_verts.saturating_sub(1);
+    let y_cells = self.y_verts.saturating_sub(1);
+    if x_cells == 0 || y_cells == 0 {
+      return;
+    }
+    let to_range = |min: f32, max: f32, cells: usize| -> (usize, usize) {
+      let start = (min.floor().max(0.0) as usize).min(cells - 1);
+      let end = (max.ceil().max(0.0) as usize).min(cells);
+      // A footprint with no width still overlaps the cell it's in.
+      (start, end.max(start + 1))
+    };
+    let (x0, x1) = to_range(footprint.min.x, footprint.max.x, x_cells);
+    let (y0, y1) = to_range(footprint.min.y, footprint.max.y, y_cells);
+    for y in y0..y1 {
+      for x in x0..x1 {
+        f(x, y);
+      }
+    }
+  }
+}
+
+// Integers from min to max, inclusive.
+fn integers(min: f32, max: f32) -> ::std::ops::Range<i32> {
+  (min.ceil() as i32)..(max.floor() as i32 + 1)
+}
+
+impl SlopeGrid {
+  pub fn slope_at(&self, x: usize, y: usize) -> f32 {
+    self.slopes[y * self.x_cells + x]
+  }
+
+  // Writes the grid as an 8-bit grayscale image with one pixel per cell. Slopes from 0 to
+  // full_scale map to black through white. Steeper slopes are also white.
+  pub fn write_image(&self, path: &Path, full_scale: f32) -> Result<(), GameError> {
+    let pixels: Vec<u8> = self.slopes.iter()
+      .map(|&slope| (slope / full_scale * 255.0).max(0.0).min(255.0).round() as u8)
+      .collect();
+    try!(image::save_buffer(
+      path, &pixels, self.x_cells as u32, self.y_cells as u32, image::ColorType::Gray(8)
+    ));
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use cgmath::Point3;
+  use terrain::Terrain;
+  use testing;
+  use super::*;
+
+  // 3 x 3 vertices. Flat except for one raised vertex at (2, 2).
+  fn bump() -> Terrain {
+    Terrain::new(3, 3, vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0])
+  }
+
+  #[test]
+  fn steepest_side() {
+    // The sides rise by 1.5 over 1, 0.5 over 1, and 2 over sqrt(2). The last has the
+    // greatest Z difference, so it's the one measured, even though the first is steeper.
+    let triangle = [
+      Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 1.5), Point3::new(1.0, 1.0, 2.0)
+    ];
+    assert!((triangle_slope(&triangle) - 2.0 / 2.0f32.sqrt()).abs() < 0.0001);
+  }
+
+  #[test]
+  fn equal_rises() {
+    // Two sides rise by 1, over 1 and over 2. The steeper one is measured whichever
+    // vertex comes first.
+    let (a, b, c) = (
+      Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 1.0), Point3::new(0.0, 2.0, 1.0)
+    );
+    for triangle in [[a, b, c], [b, c, a], [c, a, b]].iter() {
+      assert_eq!(1.0, triangle_slope(triangle));
+    }
+  }
+
+  #[test]
+  fn footprints() {
+    let terrain = bump();
+    assert_eq!(0.0, terrain.max_slope(&Footprint::new(0.0, 0.0, 1.0, 1.0)));
+    assert_eq!((0.0, 0.0), terrain.height_range_under(&Footprint::new(0.2, 0.2, 0.8, 0.8)));
+    assert_eq!(2.0, terrain.max_slope(&Footprint::new(0.5, 0.5, 1.5, 1.5)));
+    assert_eq!((0.0, 2.0), terrain.height_range_under(&Footprint::new(-5.0, -5.0, 5.0, 5.0)));
+    // The raised vertex is outside this footprint, but the surface rises toward it.
+    assert_eq!((0.0, 0.0), terrain.surface_range_under(&Footprint::new(0.2, 0.2, 0.8, 0.8)));
+    assert_eq!((0.0, 1.0), terrain.surface_range_under(&Footprint::new(0.5, 0.5, 1.5, 1.5)));
+  }
+
+  #[test]
+  fn grid_image() {
+    let grid = bump().slope_grid();
+    assert_eq!(4, grid.slopes.len());
+    assert_eq!(0.0, grid.slope_at(0, 0));
+    assert_eq!(2.0, grid.slope_at(1, 1));
+    let dir = testing::scratch_dir("slope-grid");
+    grid.write_image(&dir.join("slope.png"), 1.0).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
\ No newline at end of file
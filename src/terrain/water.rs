@@ -0,0 +1,260 @@
+use std::mem::size_of;
+use std::path::Path;
+use std::ptr;
+use cgmath::Matrix;
+use gl;
+use gl::types::*;
+
+use camera::Camera;
+use errors::GameError;
+use opengl::{Attributes, Indices, Program, Texture2d, TextureConfig, Vao, Vbo};
+use super::{Hydrology, Region, Terrain};
+use super::mesh::{ChunkRect, chunk_rects};
+
+// Draws the surface of each body of water in a Hydrology, with glsl/water.vert.glsl and
+// glsl/water.frag.glsl. Each vertex carries the depth of the water above the terrain
+// there, so the shader can fade out the shallows and add foam near the shore. Depth is
+// negative where the terrain is above the water, which puts the shoreline wherever the
+// interpolated depth crosses zero.
+//
+// Like TerrainRenderer, the surface is split into chunks, and only cells with some water
+// in them are drawn.
+pub struct WaterRenderer {
+  program: Program,
+  chunks: Vec<Chunk>,
+  foam: Texture2d,
+
+  // Uniform locations.
+  camera_idx: GLint,
+  foam_idx: GLint,
+
+  // Attribute locations.
+  position_idx: GLuint,
+  depth_idx: GLuint
+}
+
+struct Chunk {
+  rect: ChunkRect,
+  vao: Vao,
+  position_buffer: Vbo<Attributes>,
+  depth_buffer: Vbo<Attributes>,
+  index_buffer: Vbo<Indices>,
+  index_count: usize,
+
+  // True if the terrain under this chunk has changed since it was last uploaded.
+  dirty: bool
+}
+
+// Vertex data for one chunk of the water surface, ready to upload to OpenGL. Vertices
+// are laid out like terrain::Mesh.
+pub struct WaterMesh {
+  // x, y, z for each vertex. z is the level of the water over the vertex. Dry vertices
+  // take the highest level of the water next to them, so the surface stays flat out to
+  // the shore.
+  pub positions: Vec<f32>,
+
+  // Water level minus terrain height for each vertex. At most zero for dry vertices.
+  pub depths: Vec<f32>,
+
+  // Triangles for the cells with at least one vertex under water.
+  pub indices: Vec<u16>
+}
+
+impl WaterMesh {
+  pub fn new(terrain: &Terrain, rect: &ChunkRect, hydrology: &Hydrology) -> WaterMesh {
+    let vert_count = (rect.x_cells + 1) * (rect.y_cells + 1);
+    let mut positions: Vec<f32> = Vec::with_capacity(vert_count * 3);
+    let mut depths: Vec<f32> = Vec::with_capacity(vert_count);
+    let mut wet: Vec<bool> = Vec::with_capacity(vert_count);
+    for y in rect.y..(rect.y + rect.y_cells + 1) {
+      for x in rect.x..(rect.x + rect.x_cells + 1) {
+        let height = terrain.height_at(x, y);
+        let (level, depth) = match hydrology.level_at(x, y) {
+          Some(level) => (level, level - height),
+          None => {
+            let level = neighbor_level(hydrology, x, y).unwrap_or(hydrology.water_height);
+            (level, (level - height).min(0.0))
+          }
+        };
+        positions.push(x as f32);
+        positions.push(y as f32);
+        positions.push(level);
+        depths.push(depth);
+        wet.push(hydrology.level_at(x, y).is_some());
+      }
+    }
+
+    let index = |x: usize, y: usize| (y * (rect.x_cells + 1) + x) as u16;
+    let mut indices: Vec<u16> = Vec::new();
+    for y in 0..rect.y_cells {
+      for x in 0..rect.x_cells {
+        let corners = [index(x, y), index(x + 1, y), index(x + 1, y + 1), index(x, y + 1)];
+        if corners.iter().any(|&i| wet[i as usize]) {
+          // Same diagonal as Terrain::cell_triangles.
+          indices.extend_from_slice(&[
+            corners[0], corners[1], corners[2], corners[0], corners[2], corners[3]
+          ]);
+        }
+      }
+    }
+    WaterMesh {positions: positions, depths: depths, indices: indices}
+  }
+}
+
+// Highest water level over any of the eight vertices around (x, y).
+fn neighbor_level(hydrology: &Hydrology, x: usize, y: usize) -> Option<f32> {
+  let mut level: Option<f32> = None;
+  for ny in y.saturating_sub(1)..(y + 2).min(hydrology.y_verts) {
+    for nx in x.saturating_sub(1)..(x + 2).min(hydrology.x_verts) {
+      if let Some(l) = hydrology.level_at(nx, ny) {
+        level = Some(level.map_or(l, |level: f32| level.max(l)));
+      }
+    }
+  }
+  level
+}
+
+impl WaterRenderer {
+  pub fn new(terrain: &Terrain, hydrology: &Hydrology) -> Result<WaterRenderer, GameError> {
+    let program = Program::new(
+      &Path::new("glsl/water.vert.glsl"),
+      &Path::new("glsl/water.frag.glsl")
+    );
+    let foam = try!(Texture2d::from_file(
+      &Path::new("assets/textures/foam.jpg"), &TextureConfig::default()
+    ));
+    let mut renderer = WaterRenderer {
+      camera_idx: program.get_uniform_location("camera"),
+      foam_idx: program.get_uniform_location("foam"),
+      position_idx: program.get_attrib_location("position"),
+      depth_idx: program.get_attrib_location("depth"),
+      program: program,
+      chunks: Vec::new(),
+      foam: foam
+    };
+    for rect in chunk_rects(terrain) {
+      let chunk = renderer.new_chunk(terrain, hydrology, rect);
+      renderer.chunks.push(chunk);
+    }
+    Ok(renderer)
+  }
+
+  // Marks the chunks containing any vertex in the region as needing to be re-uploaded.
+  // Call after editing the terrain, then call flush before the next draw.
+  pub fn mark_dirty(&mut self, region: &Region) {
+    for chunk in self.chunks.iter_mut() {
+      let rect = chunk.rect;
+      if rect.x <= region.max_x && rect.x + rect.x_cells >= region.min_x &&
+         rect.y <= region.max_y && rect.y + rect.y_cells >= region.min_y {
+        chunk.dirty = true;
+      }
+    }
+  }
+
+  // Marks every chunk as needing to be re-uploaded, e.g. after the Hydrology has been
+  // recomputed.
+  pub fn mark_all_dirty(&mut self) {
+    for chunk in self.chunks.iter_mut() {
+      chunk.dirty = true;
+    }
+  }
+
+  // Re-uploads every dirty chunk.
+  pub fn flush(&mut self, terrain: &Terrain, hydrology: &Hydrology) {
+    for chunk in self.chunks.iter_mut() {
+      if chunk.dirty {
+        chunk.buffer(terrain, hydrology);
+      }
+    }
+  }
+
+  // Call after drawing the terrain, with blending enabled, so the ground shows through.
+  pub fn draw(&self, camera: &Camera) {
+    unsafe {
+      gl::UseProgram(self.program.id);
+      gl::UniformMatrix4fv(self.camera_idx, 1, gl::FALSE, camera.transform.as_ptr());
+      self.foam.bind(self.foam_idx, 0);
+      for chunk in self.chunks.iter().filter(|chunk| chunk.index_count > 0) {
+        chunk.vao.bind();
+        chunk.index_buffer.bind();
+        gl::DrawElements(
+          gl::TRIANGLES, chunk.index_count as GLsizei, gl::UNSIGNED_SHORT, ptr::null()
+        );
+        chunk.index_buffer.unbind();
+        chunk.vao.unbind();
+      }
+      gl::UseProgram(0);
+    }
+  }
+
+  fn new_chunk(&self, terrain: &Terrain, hydrology: &Hydrology, rect: ChunkRect) -> Chunk {
+    let mut chunk = Chunk {
+      rect: rect,
+      vao: Vao::new(),
+      position_buffer: Vbo::new(),
+      depth_buffer: Vbo::new(),
+      index_buffer: Vbo::new(),
+      index_count: 0,
+      dirty: true
+    };
+    unsafe { chunk.vao.bind(); }
+    chunk.vao.attrib(&chunk.position_buffer, self.position_idx, 3, gl::FLOAT, 0, 0);
+    chunk.vao.attrib(&chunk.depth_buffer, self.depth_idx, 1, gl::FLOAT, 0, 0);
+    unsafe { chunk.vao.unbind(); }
+    chunk.buffer(terrain, hydrology);
+    chunk
+  }
+}
+
+impl Chunk {
+  fn buffer(&mut self, terrain: &Terrain, hydrology: &Hydrology) {
+    let mesh = WaterMesh::new(terrain, &self.rect, hydrology);
+    self.position_buffer.buffer_data(
+      mesh.positions.len() * size_of::<GLfloat>(), &mesh.positions, gl::DYNAMIC_DRAW
+    );
+    self.depth_buffer.buffer_data(
+      mesh.depths.len() * size_of::<GLfloat>(), &mesh.depths, gl::DYNAMIC_DRAW
+    );
+    self.index_buffer.buffer_data(
+      mesh.indices.len() * size_of::<GLushort>(), &mesh.indices, gl::DYNAMIC_DRAW
+    );
+    self.index_count = mesh.indices.len();
+    self.dirty = false;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use terrain::{Hydrology, Terrain};
+  use super::*;
+
+  #[test]
+  fn only_wet_cells() {
+    // 3 x 2 vertices. The left column is sea, the rest is dry.
+    let terrain = Terrain::new(3, 2, vec![0.0, 2.0, 2.0, 0.0, 2.0, 2.0]);
+    let rect = ChunkRect {x: 0, y: 0, x_cells: 2, y_cells: 1};
+    let mesh = WaterMesh::new(&terrain, &rect, &Hydrology::new(&terrain, 1.0));
+    assert_eq!(vec![1.0, -1.0, -1.0, 1.0, -1.0, -1.0], mesh.depths);
+    assert_eq!(vec![0, 1, 4, 0, 4, 3], mesh.indices);
+    assert!(mesh.positions.chunks(3).all(|p| p[2] == 1.0));
+  }
+
+  #[test]
+  fn lake_level() {
+    // 4 x 3 vertices with a hollow at (1, 1) that fills to 3, the height of the gap at
+    // (1, 0).
+    let terrain = Terrain::new(4, 3, vec![
+      5.0, 3.0, 5.0, 5.0,
+      5.0, 1.0, 5.0, 5.0,
+      5.0, 5.0, 5.0, 5.0
+    ]);
+    let rect = ChunkRect {x: 0, y: 0, x_cells: 3, y_cells: 2};
+    let mesh = WaterMesh::new(&terrain, &rect, &Hydrology::new(&terrain, 0.0));
+    assert_eq!(2.0, mesh.depths[5]);
+    assert_eq!(3.0, mesh.positions[5 * 3 + 2]);
+    // The far right column isn't next to any water, so it's at sea level.
+    assert_eq!(0.0, mesh.positions[3 * 3 + 2]);
+    // Only the four cells around the hollow are drawn.
+    assert_eq!(4 * 6, mesh.indices.len());
+  }
+}
\ No newline at end of file
@@ -0,0 +1,15 @@
+// Helpers shared by the unit tests.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+// A fresh, empty directory for one test to write into. name should be unique among tests;
+// the process ID keeps concurrent test runs apart.
+pub fn scratch_dir(name: &str) -> PathBuf {
+  let dir = env::temp_dir().join(format!("cities-test-{}-{}", name, process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
\ No newline at end of file
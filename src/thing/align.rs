@@ -0,0 +1,311 @@
+use cgmath::Point2;
+
+use assets::AssetRegistry;
+use terrain::{Footprint, Terrain};
+use world::World;
+use super::config::Config;
+use super::foundation::{Foundation, FoundationKind};
+use super::placement::footprint;
+
+// Lines up the bases of neighboring Things, e.g. so a row of retaining walls forms one
+// level wall. Each aligned side of a Thing's base, as configured with align_front,
+// align_back, align_left, and align_right, must be at the same Z as the base of any Thing
+// whose base touches that side. Things joined by aligned sides share one Z, which has to
+// be within reach of every one of them:
+//
+// * Without a foundation, a base sinks to the lowest ground under it and can't move.
+// * A Thing that builds foundations can be raised by up to foundation_max_height.
+// * A Thing that digs foundations can be lowered until the pit is foundation_max_height
+//   deep at its deepest.
+//
+// A base can always stay where it was placed, even if that's beyond the limits. Things
+// facing one of the diagonal directions are never aligned.
+
+// One placed Thing, as the solver sees it.
+pub struct Site<'a> {
+  pub config: &'a Config,
+  pub footprint: Footprint,
+
+  // In the range 0-7.
+  pub direction: u8,
+
+  // Lowest and highest ground under the footprint.
+  pub ground: (f32, f32),
+
+  // Z coord of the base in the world, as placed.
+  pub base_z: f32
+}
+
+// Things that have to share a Z, but can't.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+  // Indices of the Sites, or of World::things for align_world.
+  pub things: Vec<usize>,
+
+  // The lowest Z every Thing can reach, and the highest. low is above high, or there'd
+  // be no conflict.
+  pub low: f32,
+  pub high: f32
+}
+
+// Sides touch if they're this close.
+const TOUCHING: f32 = 0.001;
+
+impl<'a> Site<'a> {
+  // The range of Z coords the base can move to.
+  pub fn reach(&self) -> (f32, f32) {
+    let (lowest, highest) = self.ground;
+    let limit = self.config.foundation_max_height;
+    let low = if self.config.digs_foundation { lowest.min(highest - limit) } else { lowest };
+    let high = if self.config.builds_foundation { lowest + limit } else { lowest };
+    (low.min(self.base_z), high.max(self.base_z))
+  }
+
+  // The foundation the Thing needs with its base at the given Z. At the Z it would be
+  // placed at, that's the one Foundation::for_site picks. Raised above the lowest ground,
+  // it needs a built foundation so it doesn't float. Otherwise, a Thing that digs
+  // foundations sits in a pit, and one that doesn't is sunk into the ground.
+  pub fn foundation_at(&self, terrain: &Terrain, z: f32) -> Option<Foundation> {
+    let natural = Foundation::for_site(self.config, terrain, &self.footprint);
+    let natural_z = natural.as_ref().map_or(self.ground.0, |foundation| foundation.z);
+    if (z - natural_z).abs() < TOUCHING {
+      natural
+    } else if z > self.ground.0 {
+      if self.config.builds_foundation {
+        Some(Foundation::at(FoundationKind::Built, terrain, &self.footprint, z))
+      } else {
+        None
+      }
+    } else if self.config.digs_foundation && z < self.ground.1 {
+      Some(Foundation::at(FoundationKind::Dug, terrain, &self.footprint, z))
+    } else {
+      None
+    }
+  }
+
+  // Whether the side of the base facing the given direction must be aligned. Directions
+  // are counted the same way as Thing directions, so the front faces the Thing's own
+  // direction.
+  fn aligns(&self, direction: u8) -> bool {
+    match (direction + 8 - self.direction % 8) % 8 {
+      0 => self.config.align_front,
+      2 => self.config.align_left,
+      4 => self.config.align_back,
+      6 => self.config.align_right,
+      _ => false
+    }
+  }
+}
+
+// Finds a base Z for every Site that satisfies the alignment constraints, keeping each
+// group of aligned Things as close as it can to where they were placed. Groups that
+// can't be aligned keep their placed Zs and are reported as conflicts.
+pub fn align(sites: &[Site]) -> (Vec<f32>, Vec<Conflict>) {
+  // Union-find over the Sites, joining each pair with an aligned side between them.
+  let mut groups: Vec<usize> = (0..sites.len()).collect();
+  for i in 0..sites.len() {
+    for j in (i + 1)..sites.len() {
+      // Things facing diagonally don't line up with anything, whatever their neighbors
+      // ask for.
+      if sites[i].direction % 2 == 1 || sites[j].direction % 2 == 1 {
+        continue;
+      }
+      let joined = match touching_side(&sites[i].footprint, &sites[j].footprint) {
+        Some(side) => sites[i].aligns(side) || sites[j].aligns((side + 4) % 8),
+        None => false
+      };
+      if joined {
+        let (a, b) = (root(&mut groups, i), root(&mut groups, j));
+        groups[a] = b;
+      }
+    }
+  }
+
+  let mut zs: Vec<f32> = sites.iter().map(|site| site.base_z).collect();
+  let mut conflicts: Vec<Conflict> = Vec::new();
+  for group in 0..sites.len() {
+    if root(&mut groups, group) != group {
+      continue;
+    }
+    let members: Vec<usize> = (0..sites.len())
+      .filter(|&i| root(&mut groups, i) == group)
+      .collect();
+    if members.len() < 2 {
+      continue;
+    }
+    let (low, high) = members.iter().fold(
+      (::std::f32::NEG_INFINITY, ::std::f32::INFINITY),
+      |(low, high), &i| {
+        let (l, h) = sites[i].reach();
+        (low.max(l), high.min(h))
+      }
+    );
+    if low > high {
+      conflicts.push(Conflict {things: members, low: low, high: high});
+      continue;
+    }
+    // The median moves the bases the least in total.
+    let mut placed: Vec<f32> = members.iter().map(|&i| sites[i].base_z).collect();
+    placed.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let z = placed[placed.len() / 2].max(low).min(high);
+    for &i in members.iter() {
+      zs[i] = z;
+    }
+  }
+  (zs, conflicts)
+}
+
+// Aligns the Things in the world, moving their origins to match, and returns the
+// foundation each Thing needs at its new Z, indexed like World::things. Things whose
+// MetaThing isn't in the registry aren't moved, don't constrain anything, and have no
+// foundation. Call after adding, moving, or removing Things, or editing the terrain under
+// them.
+pub fn align_world(
+  world: &mut World, assets: &AssetRegistry
+) -> (Vec<Option<Foundation>>, Vec<Conflict>) {
+  let mut foundations: Vec<Option<Foundation>> = world.things.iter().map(|_| None).collect();
+  let (zs, indices, mut conflicts) = {
+    let terrain = &world.terrain;
+    let mut sites: Vec<Site> = Vec::new();
+    let mut indices: Vec<usize> = Vec::new();
+    for (i, thing) in world.things.iter().enumerate() {
+      if let Some(meta_thing) = assets.thing(&thing.meta_thing) {
+        let footprint = footprint(
+          &meta_thing.config, Point2::new(thing.origin.x, thing.origin.y), thing.direction
+        );
+        sites.push(Site {
+          config: &meta_thing.config,
+          footprint: footprint,
+          direction: thing.direction,
+          ground: terrain.surface_range_under(&footprint),
+          base_z: terrain.surface_height(thing.origin.x, thing.origin.y) + thing.origin.z
+        });
+        indices.push(i);
+      }
+    }
+    let (zs, conflicts) = align(&sites);
+    for ((site, &i), &z) in sites.iter().zip(indices.iter()).zip(zs.iter()) {
+      foundations[i] = site.foundation_at(terrain, z);
+    }
+    (zs, indices, conflicts)
+  };
+  for (&i, &z) in indices.iter().zip(zs.iter()) {
+    let thing = &mut world.things[i];
+    thing.origin.z = z - world.terrain.surface_height(thing.origin.x, thing.origin.y);
+  }
+  for conflict in conflicts.iter_mut() {
+    conflict.things = conflict.things.iter().map(|&site| indices[site]).collect();
+  }
+  (foundations, conflicts)
+}
+
+// If b's footprint touches a side of a's, the direction from a to b, counted like Thing
+// directions. Footprints that only meet at a corner don't touch.
+fn touching_side(a: &Footprint, b: &Footprint) -> Option<u8> {
+  let near = |u: f32, v: f32| (u - v).abs() < TOUCHING;
+  let x_overlap = a.min.x.max(b.min.x) + TOUCHING < a.max.x.min(b.max.x);
+  let y_overlap = a.min.y.max(b.min.y) + TOUCHING < a.max.y.min(b.max.y);
+  if y_overlap && near(a.max.x, b.min.x) {
+    Some(0)
+  } else if x_overlap && near(a.max.y, b.min.y) {
+    Some(2)
+  } else if y_overlap && near(a.min.x, b.max.x) {
+    Some(4)
+  } else if x_overlap && near(a.min.y, b.max.y) {
+    Some(6)
+  } else {
+    None
+  }
+}
+
+fn root(groups: &mut Vec<usize>, mut i: usize) -> usize {
+  while groups[i] != i {
+    groups[i] = groups[groups[i]];
+    i = groups[i];
+  }
+  i
+}
+
+#[cfg(test)]
+mod tests {
+  use terrain::{Footprint, Terrain};
+  use thing::{Config, FoundationKind};
+  use super::*;
+
+  fn site<'a>(config: &'a Config, x: f32, direction: u8, ground: (f32, f32)) -> Site<'a> {
+    Site {
+      config: config, footprint: Footprint::new(x, 0.0, x + 1.0, 1.0), direction: direction,
+      ground: ground, base_z: ground.0
+    }
+  }
+
+  #[test]
+  fn retaining_walls() {
+    // A row of walls facing +Y, so their left and right sides face along the X axis.
+    let wall = Config {
+      align_left: true, align_right: true, builds_foundation: true, digs_foundation: true,
+      foundation_max_height: 2.0, ..Config::default()
+    };
+    let sites = vec![
+      site(&wall, 0.0, 2, (1.0, 1.5)),
+      site(&wall, 1.0, 2, (2.0, 2.5)),
+      site(&wall, 2.0, 2, (3.0, 3.5))
+    ];
+    let (zs, conflicts) = align(&sites);
+    assert!(conflicts.is_empty());
+    assert_eq!(vec![2.0, 2.0, 2.0], zs);
+
+    // Facing +X, the row is front to back, so nothing's aligned.
+    let sites = vec![site(&wall, 0.0, 0, (1.0, 1.5)), site(&wall, 1.0, 0, (2.0, 2.5))];
+    assert_eq!(vec![1.0, 2.0], align(&sites).0);
+
+    // Nor is a Thing facing diagonally, even next to one that asks.
+    let other = Config::default();
+    let sites = vec![site(&wall, 0.0, 2, (1.0, 1.5)), site(&other, 1.0, 3, (2.0, 2.5))];
+    assert_eq!(vec![1.0, 2.0], align(&sites).0);
+  }
+
+  #[test]
+  fn one_sided() {
+    // Only the first Thing asks to be aligned, but the second has to follow.
+    let wall = Config {align_front: true, builds_foundation: true, ..Config::default()};
+    let other = Config {digs_foundation: true, ..Config::default()};
+    let sites = vec![site(&wall, 0.0, 0, (1.0, 1.0)), site(&other, 1.0, 0, (2.0, 2.0))];
+    assert_eq!((vec![2.0, 2.0], Vec::new()), align(&sites));
+  }
+
+  #[test]
+  fn conflict() {
+    let wall = Config {
+      align_left: true, align_right: true, builds_foundation: true,
+      foundation_max_height: 0.5, ..Config::default()
+    };
+    let sites = vec![site(&wall, 0.0, 2, (1.0, 1.0)), site(&wall, 1.0, 2, (3.0, 3.0))];
+    let (zs, conflicts) = align(&sites);
+    assert_eq!(vec![1.0, 3.0], zs);
+    assert_eq!(vec![Conflict {things: vec![0, 1], low: 3.0, high: 1.5}], conflicts);
+  }
+
+  #[test]
+  fn foundations() {
+    // 3 x 2 vertices, rising by 1 per unit along X, under a base from X = 0 to 2.
+    let terrain = Terrain::new(3, 2, vec![0.0, 1.0, 2.0, 0.0, 1.0, 2.0]);
+    let both = Config {builds_foundation: true, digs_foundation: true, ..Config::default()};
+    let site = Site {
+      config: &both, footprint: Footprint::new(0.0, 0.0, 2.0, 1.0), direction: 0,
+      ground: (0.0, 2.0), base_z: 0.0
+    };
+
+    // Raised, or left where it would be placed, it's on a built foundation.
+    let raised = site.foundation_at(&terrain, 3.0).unwrap();
+    assert_eq!((FoundationKind::Built, 3.0), (raised.kind, raised.z));
+    let natural = Foundation::for_site(&both, &terrain, &site.footprint).unwrap();
+    assert_eq!(natural.kind, site.foundation_at(&terrain, natural.z).unwrap().kind);
+
+    // Lowered, it's in a pit, unless it can't dig.
+    let lowered = site.foundation_at(&terrain, -1.0).unwrap();
+    assert_eq!((FoundationKind::Dug, -1.0), (lowered.kind, lowered.z));
+    let builds = Config {builds_foundation: true, ..Config::default()};
+    assert!(Site {config: &builds, ..site}.foundation_at(&terrain, -1.0).is_none());
+  }
+}
\ No newline at end of file
@@ -0,0 +1,298 @@
+use std::str;
+
+use binary::{Reader, Writer};
+use errors::GameError;
+use super::keys::*;
+
+// Typed configuration for a MetaThing. See doc/thing-file-format.md for what each option
+// means. Any option not present in the .thing file takes its default value.
+pub struct Config {
+  // Name of the model that represents the Thing as a whole, e.g. "jarrett-test".
+  pub main_model: Option<String>,
+
+  // Footprint of the Thing's base in world units, and its offset from the Thing's origin.
+  pub base_x_size: f32,
+  pub base_y_size: f32,
+  pub base_x_offset: f32,
+  pub base_y_offset: f32,
+
+  pub builds_foundation: bool,
+  pub digs_foundation: bool,
+  pub foundation_min_slope: f32,
+  pub built_foundation_wall_texture: Option<String>,
+  pub built_foundation_cap_texture: Option<String>,
+  pub dug_foundation_wall_texture: Option<String>,
+  pub dug_foundation_floor_texture: Option<String>,
+
+  pub has_footer: bool,
+  pub footer_model: Option<String>,
+  pub has_pylon: bool,
+  pub pylon_model: Option<String>,
+  pub pylon_align: PylonAlign,
+  pub pylon_repeat: PylonRepeat,
+
+  // Which sides of the Thing's base must be at the same Z as any Thing next to them,
+  // relative to the direction the Thing faces. See thing::align.
+  pub align_front: bool,
+  pub align_back: bool,
+  pub align_left: bool,
+  pub align_right: bool,
+
+  // How far a built foundation may raise the base above the lowest ground under it, or
+  // a dug one lower it below the highest, whether to level it or line it up with
+  // neighbors.
+  pub foundation_max_height: f32,
+
+  // Key/value pairs whose keys this version of the game doesn't recognize. We keep them
+  // so that files written by newer tools survive a round trip.
+  pub unknown: Vec<(u8, Vec<u8>)>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PylonAlign {
+  // The top of the highest pylon is at the level of the water table.
+  WaterTable
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PylonRepeat {
+  // Pylons are repeated until they reach at least Z = 0.
+  Zero
+}
+
+impl Config {
+  // Reads the configs section of a .thing file. Duplicate keys are allowed; the last
+  // one wins.
+  pub fn read(section: &mut Reader) -> Result<Config, GameError> {
+    let mut config = Config::default();
+    let count = try!(section.u16());
+    for _ in 0..count {
+      let key = try!(section.u8());
+      let len = try!(section.u16()) as usize;
+      let value: &[u8] = try!(section.bytes(len));
+      try!(config.set(key, value));
+    }
+    Ok(config)
+  }
+
+  // Writes the configs section of a .thing file. Only options that differ from their
+  // defaults are written, in key order, followed by any unknown keys.
+  pub fn write(&self, w: &mut Writer) -> Result<(), GameError> {
+    let default = Config::default();
+    let mut pairs: Vec<(u8, Vec<u8>)> = Vec::new();
+    push_string(&mut pairs, THING_MAIN_MODEL, &self.main_model);
+    push_float(&mut pairs, THING_BASE_X_SIZE, self.base_x_size, default.base_x_size);
+    push_float(&mut pairs, THING_BASE_Y_SIZE, self.base_y_size, default.base_y_size);
+    push_float(&mut pairs, THING_BASE_X_OFFSET, self.base_x_offset, default.base_x_offset);
+    push_float(&mut pairs, THING_BASE_Y_OFFSET, self.base_y_offset, default.base_y_offset);
+    push_bool(&mut pairs, THING_BUILDS_FOUNDATION, self.builds_foundation);
+    push_bool(&mut pairs, THING_DIGS_FOUNDATION, self.digs_foundation);
+    push_float(
+      &mut pairs, THING_FOUNDATION_MIN_SLOPE,
+      self.foundation_min_slope, default.foundation_min_slope
+    );
+    push_string(&mut pairs, THING_BUILT_FOUNDATION_WALL_TEXTURE, &self.built_foundation_wall_texture);
+    push_string(&mut pairs, THING_BUILT_FOUNDATION_CAP_TEXTURE, &self.built_foundation_cap_texture);
+    push_string(&mut pairs, THING_DUG_FOUNDATION_WALL_TEXTURE, &self.dug_foundation_wall_texture);
+    push_string(&mut pairs, THING_DUG_FOUNDATION_FLOOR_TEXTURE, &self.dug_foundation_floor_texture);
+    push_bool(&mut pairs, THING_HAS_FOOTER, self.has_footer);
+    push_string(&mut pairs, THING_FOOTER_MODEL, &self.footer_model);
+    push_bool(&mut pairs, THING_HAS_PYLON, self.has_pylon);
+    push_string(&mut pairs, THING_PYLON_MODEL, &self.pylon_model);
+    if self.pylon_align != default.pylon_align {
+      pairs.push((THING_PYLON_ALIGN, self.pylon_align.name().as_bytes().to_vec()));
+    }
+    if self.pylon_repeat != default.pylon_repeat {
+      pairs.push((THING_PYLON_REPEAT, self.pylon_repeat.name().as_bytes().to_vec()));
+    }
+    push_bool(&mut pairs, THING_ALIGN_FRONT, self.align_front);
+    push_bool(&mut pairs, THING_ALIGN_BACK, self.align_back);
+    push_bool(&mut pairs, THING_ALIGN_LEFT, self.align_left);
+    push_bool(&mut pairs, THING_ALIGN_RIGHT, self.align_right);
+    push_float(
+      &mut pairs, THING_FOUNDATION_MAX_HEIGHT,
+      self.foundation_max_height, default.foundation_max_height
+    );
+    for &(key, ref value) in self.unknown.iter() {
+      pairs.push((key, value.clone()));
+    }
+
+    w.u16(pairs.len() as u16);
+    for &(key, ref value) in pairs.iter() {
+      if value.len() > 0xffff {
+        return Err(invalid(key, &format!("value of {} bytes is too long", value.len())));
+      }
+      w.u8(key);
+      w.u16(value.len() as u16);
+      w.bytes(value);
+    }
+    Ok(())
+  }
+
+  // Sets the option for key from its encoded value, exactly as if it had been read from a
+  // .thing file.
+  pub fn set(&mut self, key: u8, value: &[u8]) -> Result<(), GameError> {
+    match key {
+      THING_MAIN_MODEL =>
+        self.main_model = Some(try!(string_value(key, value))),
+      THING_BASE_X_SIZE =>
+        self.base_x_size = try!(float_value(key, value)),
+      THING_BASE_Y_SIZE =>
+        self.base_y_size = try!(float_value(key, value)),
+      THING_BASE_X_OFFSET =>
+        self.base_x_offset = try!(float_value(key, value)),
+      THING_BASE_Y_OFFSET =>
+        self.base_y_offset = try!(float_value(key, value)),
+      THING_BUILDS_FOUNDATION =>
+        self.builds_foundation = try!(bool_value(key, value)),
+      THING_DIGS_FOUNDATION =>
+        self.digs_foundation = try!(bool_value(key, value)),
+      THING_FOUNDATION_MIN_SLOPE =>
+        self.foundation_min_slope = try!(float_value(key, value)),
+      THING_BUILT_FOUNDATION_WALL_TEXTURE =>
+        self.built_foundation_wall_texture = Some(try!(string_value(key, value))),
+      THING_BUILT_FOUNDATION_CAP_TEXTURE =>
+        self.built_foundation_cap_texture = Some(try!(string_value(key, value))),
+      THING_DUG_FOUNDATION_WALL_TEXTURE =>
+        self.dug_foundation_wall_texture = Some(try!(string_value(key, value))),
+      THING_DUG_FOUNDATION_FLOOR_TEXTURE =>
+        self.dug_foundation_floor_texture = Some(try!(string_value(key, value))),
+      THING_HAS_FOOTER =>
+        self.has_footer = try!(bool_value(key, value)),
+      THING_FOOTER_MODEL =>
+        self.footer_model = Some(try!(string_value(key, value))),
+      THING_HAS_PYLON =>
+        self.has_pylon = try!(bool_value(key, value)),
+      THING_PYLON_MODEL =>
+        self.pylon_model = Some(try!(string_value(key, value))),
+      THING_PYLON_ALIGN => {
+        let name = try!(string_value(key, value));
+        self.pylon_align = match PylonAlign::from_name(&name) {
+          Some(align) => align,
+          None => { return Err(invalid(key, &format!("unknown option {}", name))); }
+        };
+      },
+      THING_PYLON_REPEAT => {
+        let name = try!(string_value(key, value));
+        self.pylon_repeat = match PylonRepeat::from_name(&name) {
+          Some(repeat) => repeat,
+          None => { return Err(invalid(key, &format!("unknown option {}", name))); }
+        };
+      },
+      THING_ALIGN_FRONT =>
+        self.align_front = try!(bool_value(key, value)),
+      THING_ALIGN_BACK =>
+        self.align_back = try!(bool_value(key, value)),
+      THING_ALIGN_LEFT =>
+        self.align_left = try!(bool_value(key, value)),
+      THING_ALIGN_RIGHT =>
+        self.align_right = try!(bool_value(key, value)),
+      THING_FOUNDATION_MAX_HEIGHT =>
+        self.foundation_max_height = try!(float_value(key, value)),
+      _ => {
+        self.unknown.retain(|&(k, _)| k != key);
+        self.unknown.push((key, value.to_vec()));
+      }
+    }
+    Ok(())
+  }
+}
+
+impl PylonAlign {
+  // Parses an option name as it appears in doc/thing-file-format.md.
+  pub fn from_name(name: &str) -> Option<PylonAlign> {
+    match name {
+      "PYLON_ALIGN_WATER_TABLE" => Some(PylonAlign::WaterTable),
+      _ => None
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match *self {
+      PylonAlign::WaterTable => "PYLON_ALIGN_WATER_TABLE"
+    }
+  }
+}
+
+impl PylonRepeat {
+  // Parses an option name as it appears in doc/thing-file-format.md.
+  pub fn from_name(name: &str) -> Option<PylonRepeat> {
+    match name {
+      "PYLON_REPEAT_ZERO" => Some(PylonRepeat::Zero),
+      _ => None
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match *self {
+      PylonRepeat::Zero => "PYLON_REPEAT_ZERO"
+    }
+  }
+}
+
+impl Default for Config {
+  fn default() -> Config {
+    Config {
+      main_model: None,
+      base_x_size: 1.0, base_y_size: 1.0, base_x_offset: 0.0, base_y_offset: 0.0,
+      builds_foundation: false, digs_foundation: false,
+      // With no threshold configured, any slope at all gets a foundation.
+      foundation_min_slope: 0.0,
+      built_foundation_wall_texture: None, built_foundation_cap_texture: None,
+      dug_foundation_wall_texture: None, dug_foundation_floor_texture: None,
+      has_footer: false, footer_model: None,
+      has_pylon: false, pylon_model: None,
+      pylon_align: PylonAlign::WaterTable, pylon_repeat: PylonRepeat::Zero,
+      align_front: false, align_back: false, align_left: false, align_right: false,
+      foundation_max_height: ::std::f32::INFINITY,
+      unknown: Vec::new()
+    }
+  }
+}
+
+fn invalid(key: u8, msg: &str) -> GameError {
+  GameError::Invalid(format!("Invalid value for Thing config key {}: {}", key, msg))
+}
+
+fn push_string(pairs: &mut Vec<(u8, Vec<u8>)>, key: u8, value: &Option<String>) {
+  if let Some(ref s) = *value {
+    pairs.push((key, s.as_bytes().to_vec()));
+  }
+}
+
+fn push_float(pairs: &mut Vec<(u8, Vec<u8>)>, key: u8, value: f32, default: f32) {
+  if value != default {
+    let mut w = Writer::new();
+    w.f32(value);
+    pairs.push((key, w.into_bytes()));
+  }
+}
+
+// We only ever write true, since false is the default for every boolean option.
+fn push_bool(pairs: &mut Vec<(u8, Vec<u8>)>, key: u8, value: bool) {
+  if value {
+    pairs.push((key, vec![1]));
+  }
+}
+
+// Booleans are integers of any width. Non-zero means true.
+fn bool_value(key: u8, value: &[u8]) -> Result<bool, GameError> {
+  match value.len() {
+    1 | 2 | 4 | 8 => Ok(value.iter().any(|&b| b != 0)),
+    len => Err(invalid(key, &format!("{} bytes is not a valid integer size", len)))
+  }
+}
+
+fn float_value(key: u8, value: &[u8]) -> Result<f32, GameError> {
+  if value.len() != 4 {
+    return Err(invalid(key, &format!("expected a 4 byte float, but got {} bytes", value.len())));
+  }
+  Reader::new(value).f32()
+}
+
+fn string_value(key: u8, value: &[u8]) -> Result<String, GameError> {
+  match str::from_utf8(value) {
+    Ok(s) if s.is_ascii() => Ok(s.to_string()),
+    _ => Err(invalid(key, "expected an ASCII string"))
+  }
+}
\ No newline at end of file
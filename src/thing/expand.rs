@@ -0,0 +1,255 @@
+use std::ops::Range;
+use cgmath::{Point3, Vector3};
+
+use assets::AssetRegistry;
+use terrain::{Hydrology, Terrain};
+use world::{Thing, World};
+use super::config::{PylonAlign, PylonRepeat};
+use super::meta_thing::MetaThing;
+use super::placement::rotate;
+
+// Every model drawn for every Thing in the world, after each Thing's footer and pylon
+// configs have been expanded into models of their own. The renderer draws these and
+// picking tests against them, so the two always agree about what's where.
+pub struct ModelList {
+  pub instances: Vec<ModelInstance>,
+
+  // Range of instances for each Thing, indexed like World::things.
+  by_thing: Vec<Range<usize>>
+}
+
+// One model drawn for a Thing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelInstance {
+  // Index into World::things.
+  pub thing: usize,
+
+  // ID in the AssetRegistry.
+  pub model_id: usize,
+
+  pub part: Part,
+
+  // World coords of the center of the model's box. See doc/model-rendering.md.
+  pub center: Point3<f32>,
+
+  // In the range 0-7, relative to the world.
+  pub direction: u8,
+
+  // Size of the model's box, from MetaModel::size.
+  pub size: Vector3<f32>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Part {
+  // One of the MetaThing's models.
+  Model,
+
+  // The footer_model, once at the Thing's base.
+  Footer,
+
+  // One of the copies of the pylon_model, counting down from the top.
+  Pylon(usize)
+}
+
+impl ModelList {
+  // Expands every Thing in the world. Things whose MetaThing isn't in the registry have
+  // no models.
+  pub fn new(world: &World, hydrology: &Hydrology, assets: &AssetRegistry) -> ModelList {
+    let mut list = ModelList {
+      instances: Vec::new(), by_thing: Vec::with_capacity(world.things.len())
+    };
+    for (i, thing) in world.things.iter().enumerate() {
+      let start = list.instances.len();
+      if let Some(meta_thing) = assets.thing(&thing.meta_thing) {
+        list.instances.extend(expand(i, thing, meta_thing, &world.terrain, hydrology, assets));
+      }
+      list.by_thing.push(start..list.instances.len());
+    }
+    list
+  }
+
+  // The models drawn for World::things[thing].
+  pub fn for_thing(&self, thing: usize) -> &[ModelInstance] {
+    &self.instances[self.by_thing[thing].clone()]
+  }
+
+  // The Thing with a model nearest to a along the line segment from a to b, e.g. the
+  // ray under the mouse cursor from Camera::unproject.
+  pub fn pick(&self, a: Point3<f32>, b: Point3<f32>) -> Option<usize> {
+    let mut nearest: Option<(f32, usize)> = None;
+    for instance in self.instances.iter() {
+      if let Some(t) = instance.hit(a, b) {
+        if nearest.map_or(true, |(best, _)| t < best) {
+          nearest = Some((t, instance.thing));
+        }
+      }
+    }
+    nearest.map(|(_, thing)| thing)
+  }
+}
+
+impl ModelInstance {
+  // Half the size of the axis-aligned box around the model. Models facing one of the
+  // diagonal directions are turned 45 degrees inside it.
+  pub fn half_extents(&self) -> Vector3<f32> {
+    let (hx, hy) = (self.size.x / 2.0, self.size.y / 2.0);
+    let (x0, y0) = rotate(hx, hy, self.direction);
+    let (x1, y1) = rotate(hx, -hy, self.direction);
+    Vector3::new(x0.abs().max(x1.abs()), y0.abs().max(y1.abs()), self.size.z / 2.0)
+  }
+
+  // Where along the segment from a to b, from 0 to 1, it first enters the model's box.
+  fn hit(&self, a: Point3<f32>, b: Point3<f32>) -> Option<f32> {
+    let half = self.half_extents();
+    let (mut enter, mut exit) = (0.0f32, 1.0f32);
+    for axis in 0..3 {
+      let (from, delta) = (a[axis], b[axis] - a[axis]);
+      let (min, max) = (self.center[axis] - half[axis], self.center[axis] + half[axis]);
+      if delta == 0.0 {
+        if from < min || from > max {
+          return None;
+        }
+      } else {
+        let (t0, t1) = ((min - from) / delta, (max - from) / delta);
+        enter = enter.max(t0.min(t1));
+        exit = exit.min(t0.max(t1));
+      }
+    }
+    if enter <= exit { Some(enter) } else { None }
+  }
+}
+
+// The models for one Thing, as described in doc/thing-file-format.md:
+//
+// * The footer, if any, sits on the Thing's base.
+// * Pylons, if any, are stacked down from pylon_align to pylon_repeat.
+// * The MetaThing's own models are placed relative to the Thing's origin, on top of the
+//   pylons if there are any. Their offsets and directions turn with the Thing.
+pub fn expand(
+  index: usize, thing: &Thing, meta_thing: &MetaThing,
+  terrain: &Terrain, hydrology: &Hydrology, assets: &AssetRegistry
+) -> Vec<ModelInstance> {
+  let config = &meta_thing.config;
+  let base = terrain.surface_height(thing.origin.x, thing.origin.y) + thing.origin.z;
+  let direction = thing.direction % 8;
+  let mut instances: Vec<ModelInstance> = Vec::new();
+  let instance = |model_id: usize, part: Part, center: Point3<f32>, direction: u8| {
+    ModelInstance {
+      thing: index, model_id: model_id, part: part, center: center, direction: direction,
+      size: assets.model(model_id).size()
+    }
+  };
+
+  let footer = if config.has_footer { config.footer_model.as_ref() } else { None };
+  if let Some(id) = footer.and_then(|name| assets.model_id(name)) {
+    let height = assets.model(id).size().z;
+    let center = Point3::new(thing.origin.x, thing.origin.y, base + height / 2.0);
+    instances.push(instance(id, Part::Footer, center, direction));
+  }
+
+  let mut deck = base;
+  let pylon = if config.has_pylon { config.pylon_model.as_ref() } else { None };
+  if let Some(id) = pylon.and_then(|name| assets.model_id(name)) {
+    let top = match config.pylon_align {
+      PylonAlign::WaterTable => hydrology.water_table(thing.origin.x, thing.origin.y)
+    };
+    let bottom = match config.pylon_repeat {
+      PylonRepeat::Zero => 0.0
+    };
+    let height = assets.model(id).size().z;
+    for (i, z) in pylon_stack(top, bottom, height).into_iter().enumerate() {
+      let center = Point3::new(thing.origin.x, thing.origin.y, z + height / 2.0);
+      instances.push(instance(id, Part::Pylon(i), center, direction));
+    }
+    deck = deck.max(top);
+  }
+
+  let model_ids = assets.thing_model_ids(&thing.meta_thing).unwrap_or(&[]);
+  for (model, &id) in meta_thing.models.iter().zip(model_ids.iter()) {
+    let (x, y) = rotate(model.offset.x, model.offset.y, direction);
+    let center = Point3::new(thing.origin.x + x, thing.origin.y + y, deck + model.offset.z);
+    instances.push(instance(id, Part::Model, center, (direction + model.direction) % 8));
+  }
+  instances
+}
+
+// Z coords of the bottoms of pylons of the given height, stacked down from top until they
+// reach bottom. The lowest pylon may extend below bottom. Empty if top is already at or
+// below bottom.
+pub fn pylon_stack(top: f32, bottom: f32, height: f32) -> Vec<f32> {
+  if height <= 0.0 || top <= bottom {
+    return Vec::new();
+  }
+  let count = ((top - bottom) / height).ceil() as usize;
+  (0..count).map(|i| top - (i + 1) as f32 * height).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+  use cgmath::{Point3, Vector3};
+  use assets::AssetRegistry;
+  use terrain::{Hydrology, Terrain};
+  use thing::{Config, MetaThing};
+  use world::{Extensions, Thing};
+  use super::*;
+
+  #[test]
+  fn stack() {
+    assert_eq!(vec![2.0, 0.0], pylon_stack(4.0, 0.0, 2.0));
+    assert_eq!(vec![1.5, -0.5], pylon_stack(3.5, 0.0, 2.0));
+    assert!(pylon_stack(-1.0, 0.0, 2.0).is_empty());
+    assert!(pylon_stack(4.0, 0.0, 0.0).is_empty());
+  }
+
+  #[test]
+  fn bridge() {
+    let assets = AssetRegistry::load(&Path::new("assets")).unwrap();
+    let model_id = assets.model_id("jarrett-test").unwrap();
+    let height = assets.model(model_id).size().z;
+    let path = Path::new("assets/things/jarrett-test.thing");
+    let mut meta_thing = MetaThing::from_file(&path).unwrap();
+    meta_thing.config = Config {
+      has_footer: true, footer_model: Some("jarrett-test".to_string()),
+      has_pylon: true, pylon_model: Some("jarrett-test".to_string()),
+      ..Config::default()
+    };
+
+    // Sea 2.5 * height deep over a floor at Z = 1.
+    let terrain = Terrain::new(2, 2, vec![1.0; 4]);
+    let hydrology = Hydrology::new(&terrain, height * 2.5);
+    let thing = Thing {
+      meta_thing: "jarrett-test".to_string(), direction: 2,
+      origin: Point3::new(0.5, 0.5, 0.0), extensions: Extensions::default()
+    };
+    let instances = expand(7, &thing, &meta_thing, &terrain, &hydrology, &assets);
+    let parts: Vec<Part> = instances.iter().map(|instance| instance.part).collect();
+    assert_eq!(
+      vec![Part::Footer, Part::Pylon(0), Part::Pylon(1), Part::Pylon(2), Part::Model], parts
+    );
+    assert!(instances.iter().all(|instance| instance.thing == 7 && instance.direction == 2));
+
+    // The footer sits on the sea floor, and the model sits on the water.
+    assert_eq!(1.0 + height / 2.0, instances[0].center.z);
+    assert_eq!(height * 2.0, instances[1].center.z);
+    assert_eq!(height * 2.5, instances[4].center.z);
+  }
+
+  #[test]
+  fn picking() {
+    let instance = |thing: usize, x: f32| ModelInstance {
+      thing: thing, model_id: 0, part: Part::Model, center: Point3::new(x, 0.0, 1.0),
+      direction: 0, size: Vector3::new(1.0, 1.0, 2.0)
+    };
+    let list = ModelList {
+      instances: vec![instance(0, 0.0), instance(1, 3.0)], by_thing: vec![0..1, 1..2]
+    };
+    let down = |x: f32| (Point3::new(x, 0.0, 10.0), Point3::new(x, 0.0, -10.0));
+    assert_eq!(Some(0), list.pick(down(0.2).0, down(0.2).1));
+    assert_eq!(Some(1), list.pick(down(3.4).0, down(3.4).1));
+    assert_eq!(None, list.pick(down(1.5).0, down(1.5).1));
+    // Nearest to the start of the segment wins.
+    assert_eq!(Some(1), list.pick(Point3::new(5.0, 0.0, 1.0), Point3::new(-5.0, 0.0, 1.0)));
+    assert_eq!(&list.instances[1..2], list.for_thing(1));
+  }
+}
\ No newline at end of file
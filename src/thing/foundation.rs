@@ -0,0 +1,284 @@
+use cgmath::{Point2, Point3};
+
+use terrain::{Footprint, Terrain};
+use super::config::Config;
+
+// A level platform or pit under a Thing on sloped ground. See "Base Alignment" in
+// doc/thing-file-format.md.
+//
+// A built foundation's cap is level with the highest ground under the Thing, and its
+// walls run down from the cap to the ground around the footprint's edges. A dug
+// foundation's floor is level with the lowest ground, and its walls run up from the floor
+// to the ground. Either way, the walls follow the surface of the terrain exactly, so
+// there are no gaps where they meet it. The terrain inside a dug foundation is still
+// drawn, so whatever draws the foundation has to hide it.
+pub struct Foundation {
+  pub kind: FoundationKind,
+  pub footprint: Footprint,
+
+  // Z coord of the cap of a built foundation or the floor of a dug one. The Thing sits
+  // on it.
+  pub z: f32,
+
+  // The vertical sides, around the edges of the footprint.
+  pub walls: FoundationMesh,
+
+  // The cap of a built foundation or the floor of a dug one.
+  pub cap: FoundationMesh,
+
+  // Total area of the walls, in square world units.
+  pub wall_area: f32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FoundationKind {
+  Built,
+  Dug
+}
+
+// Vertex data ready to upload to OpenGL, with one texture over the whole mesh. UVs are
+// in world units, so textures repeat once per unit: along the ground and up for walls,
+// and along X and Y for caps.
+pub struct FoundationMesh {
+  // x, y, z for each vertex.
+  pub positions: Vec<f32>,
+
+  // x, y, z for each vertex.
+  pub normals: Vec<f32>,
+
+  // u, v for each vertex.
+  pub uvs: Vec<f32>,
+
+  // Triangles, wound counter-clockwise seen from the side they face.
+  pub indices: Vec<u16>
+}
+
+impl Foundation {
+  // The foundation a Thing with the given config needs over the footprint, if any. When
+  // it can both build and dig, it does whichever shows less wall.
+  pub fn for_site(config: &Config, terrain: &Terrain, footprint: &Footprint) -> Option<Foundation> {
+    if !config.builds_foundation && !config.digs_foundation {
+      return None;
+    }
+    let slope = terrain.max_slope(footprint);
+    if slope == 0.0 || slope < config.foundation_min_slope {
+      return None;
+    }
+    let built = if config.builds_foundation {
+      Some(Foundation::new(FoundationKind::Built, terrain, footprint))
+    } else {
+      None
+    };
+    let dug = if config.digs_foundation {
+      Some(Foundation::new(FoundationKind::Dug, terrain, footprint))
+    } else {
+      None
+    };
+    match (built, dug) {
+      (Some(built), Some(dug)) => Some(if dug.wall_area < built.wall_area { dug } else { built }),
+      (built, dug) => built.or(dug)
+    }
+  }
+
+  // A foundation level with the highest ground, if built, or the lowest, if dug.
+  pub fn new(kind: FoundationKind, terrain: &Terrain, footprint: &Footprint) -> Foundation {
+    let (low, high) = terrain.surface_range_under(footprint);
+    let z = match kind {
+      FoundationKind::Built => high,
+      FoundationKind::Dug => low
+    };
+    Foundation::at(kind, terrain, footprint, z)
+  }
+
+  // A foundation level at the given Z, e.g. to line up with a neighbor. A built
+  // foundation only has walls where the ground is below z, and a dug one only where it's
+  // above.
+  pub fn at(kind: FoundationKind, terrain: &Terrain, footprint: &Footprint, z: f32) -> Foundation {
+    let (min, max) = (footprint.min, footprint.max);
+    let corners = [
+      Point2::new(min.x, min.y), Point2::new(max.x, min.y),
+      Point2::new(max.x, max.y), Point2::new(min.x, max.y)
+    ];
+    // Outward normals of the sides starting at each corner, counter-clockwise from
+    // above.
+    let outward = [(0.0, -1.0), (1.0, 0.0), (0.0, 1.0), (-1.0, 0.0)];
+    let wall_height = |p: &Point3<f32>| match kind {
+      FoundationKind::Built => z - p.z,
+      FoundationKind::Dug => p.z - z
+    };
+
+    let mut walls = FoundationMesh::new();
+    let mut wall_area = 0.0;
+    let mut along = 0.0; // Distance around the footprint, for the U coord.
+    for side in 0..4 {
+      let profile = terrain.surface_profile(corners[side], corners[(side + 1) % 4]);
+      for pair in profile.windows(2) {
+        let (mut p, mut q) = (pair[0], pair[1]);
+        let run = ((q.x - p.x).powi(2) + (q.y - p.y).powi(2)).sqrt();
+        let start = along;
+        along += run;
+        // Where the ground crosses z, only the part on the wall's side counts. The ground
+        // is a straight line in between, so the wall is a trapezoid, or a triangle if
+        // cut.
+        let (p_height, q_height) = (wall_height(&p), wall_height(&q));
+        if p_height <= 0.0 && q_height <= 0.0 {
+          continue;
+        }
+        let (mut u0, mut u1) = (start, along);
+        if p_height < 0.0 || q_height < 0.0 {
+          let t = p_height / (p_height - q_height);
+          let crossing = Point3::new(p.x + (q.x - p.x) * t, p.y + (q.y - p.y) * t, z);
+          if p_height < 0.0 {
+            p = crossing;
+            u0 = start + run * t;
+          } else {
+            q = crossing;
+            u1 = start + run * t;
+          }
+        }
+        wall_area += (u1 - u0) * (wall_height(&p) + wall_height(&q)) / 2.0;
+        let (p_bottom, p_top) = (p.z.min(z), p.z.max(z));
+        let (q_bottom, q_top) = (q.z.min(z), q.z.max(z));
+        let quad = [
+          Point3::new(p.x, p.y, p_bottom), Point3::new(q.x, q.y, q_bottom),
+          Point3::new(q.x, q.y, q_top), Point3::new(p.x, p.y, p_top)
+        ];
+        let uvs = [(u0, p_bottom), (u1, q_bottom), (u1, q_top), (u0, p_top)];
+        // The walls of a built foundation face out, and those of a pit face in.
+        let (nx, ny) = outward[side];
+        match kind {
+          FoundationKind::Built => walls.quad(&quad, &uvs, (nx, ny, 0.0), false),
+          FoundationKind::Dug => walls.quad(&quad, &uvs, (-nx, -ny, 0.0), true)
+        }
+      }
+    }
+
+    let mut cap = FoundationMesh::new();
+    let quad = [
+      Point3::new(min.x, min.y, z), Point3::new(max.x, min.y, z),
+      Point3::new(max.x, max.y, z), Point3::new(min.x, max.y, z)
+    ];
+    let uvs = [(min.x, min.y), (max.x, min.y), (max.x, max.y), (min.x, max.y)];
+    cap.quad(&quad, &uvs, (0.0, 0.0, 1.0), false);
+
+    Foundation {
+      kind: kind, footprint: *footprint, z: z, walls: walls, cap: cap, wall_area: wall_area
+    }
+  }
+
+  // How far a built foundation's cap is above the lowest ground, or how far a dug
+  // foundation's floor is below the highest, given the lowest and highest ground under
+  // it. This is what Config::foundation_max_height limits.
+  pub fn height(&self, ground: (f32, f32)) -> f32 {
+    match self.kind {
+      FoundationKind::Built => self.z - ground.0,
+      FoundationKind::Dug => ground.1 - self.z
+    }
+  }
+
+  // Names of the textures for the walls and the cap or floor, from the config.
+  pub fn textures<'a>(&self, config: &'a Config) -> (Option<&'a str>, Option<&'a str>) {
+    let (wall, cap) = match self.kind {
+      FoundationKind::Built =>
+        (&config.built_foundation_wall_texture, &config.built_foundation_cap_texture),
+      FoundationKind::Dug =>
+        (&config.dug_foundation_wall_texture, &config.dug_foundation_floor_texture)
+    };
+    (wall.as_ref().map(|s| s.as_ref()), cap.as_ref().map(|s| s.as_ref()))
+  }
+}
+
+impl FoundationMesh {
+  fn new() -> FoundationMesh {
+    FoundationMesh {
+      positions: Vec::new(), normals: Vec::new(), uvs: Vec::new(), indices: Vec::new()
+    }
+  }
+
+  // Adds a quad whose corners are counter-clockwise seen from the front, or clockwise if
+  // reversed.
+  fn quad(
+    &mut self, corners: &[Point3<f32>; 4], uvs: &[(f32, f32); 4],
+    normal: (f32, f32, f32), reversed: bool
+  ) {
+    let first = (self.positions.len() / 3) as u16;
+    for i in 0..4 {
+      self.positions.extend_from_slice(&[corners[i].x, corners[i].y, corners[i].z]);
+      self.normals.extend_from_slice(&[normal.0, normal.1, normal.2]);
+      self.uvs.extend_from_slice(&[uvs[i].0, uvs[i].1]);
+    }
+    let order: [u16; 6] = if reversed { [0, 2, 1, 0, 3, 2] } else { [0, 1, 2, 0, 2, 3] };
+    self.indices.extend(order.iter().map(|&i| first + i));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use terrain::{Footprint, Terrain};
+  use thing::Config;
+  use super::*;
+
+  // 3 x 2 vertices, rising by 1 per unit along X.
+  fn ramp() -> Terrain {
+    Terrain::new(3, 2, vec![0.0, 1.0, 2.0, 0.0, 1.0, 2.0])
+  }
+
+  #[test]
+  fn built() {
+    let foundation = Foundation::new(
+      FoundationKind::Built, &ramp(), &Footprint::new(0.0, 0.0, 2.0, 1.0)
+    );
+    assert_eq!(2.0, foundation.z);
+    // The low end is 2 high, and the long sides are triangles 2 long and 2 high.
+    assert_eq!(2.0 + 2.0 + 2.0, foundation.wall_area);
+    assert_eq!(vec![0.0, 0.0, 1.0], foundation.cap.normals[0..3].to_vec());
+    assert!(foundation.cap.positions.chunks(3).all(|p| p[2] == 2.0));
+    // Each wall's bottom edge is on the ground.
+    for p in foundation.walls.positions.chunks(3) {
+      assert!(p[2] == 2.0 || p[2] == p[0]);
+    }
+  }
+
+  #[test]
+  fn dug() {
+    let foundation = Foundation::new(
+      FoundationKind::Dug, &ramp(), &Footprint::new(0.5, 0.0, 2.0, 1.0)
+    );
+    assert_eq!(0.5, foundation.z);
+    // The high end is 1.5 deep, and the long sides are triangles 1.5 long and 1.5 deep.
+    assert_eq!(1.5 + 1.125 + 1.125, foundation.wall_area);
+    // The far wall faces back into the pit.
+    let far = foundation.walls.normals.chunks(3).find(|n| n[0] != 0.0).unwrap();
+    assert_eq!(-1.0, far[0]);
+  }
+
+  #[test]
+  fn partial() {
+    // Built at Z = 1 on the ramp, so only the low half has walls: a 1 x 1 end and two
+    // triangles 1 long and 1 high.
+    let foundation = Foundation::at(
+      FoundationKind::Built, &ramp(), &Footprint::new(0.0, 0.0, 2.0, 1.0), 1.0
+    );
+    assert_eq!(1.0 + 0.5 + 0.5, foundation.wall_area);
+    assert!(foundation.walls.positions.chunks(3).all(|p| p[0] <= 1.0));
+  }
+
+  #[test]
+  fn choice() {
+    let footprint = Footprint::new(0.0, 0.0, 2.0, 1.0);
+    let both = Config {builds_foundation: true, digs_foundation: true, ..Config::default()};
+
+    // Mostly low ground with a rise at the far end, where digging shows less wall.
+    let low = Terrain::new(3, 2, vec![0.0, 0.0, 2.0, 0.0, 0.0, 2.0]);
+    assert_eq!(FoundationKind::Dug, Foundation::for_site(&both, &low, &footprint).unwrap().kind);
+
+    // Mostly high ground, where building shows less wall.
+    let high = Terrain::new(3, 2, vec![0.0, 2.0, 2.0, 0.0, 2.0, 2.0]);
+    assert_eq!(FoundationKind::Built, Foundation::for_site(&both, &high, &footprint).unwrap().kind);
+
+    assert!(Foundation::for_site(&both, &Terrain::new(2, 2, vec![1.0; 4]), &footprint).is_none());
+    assert!(Foundation::for_site(&Config::default(), &high, &footprint).is_none());
+    let gentle = Config {foundation_min_slope: 3.0, ..both};
+    assert!(Foundation::for_site(&gentle, &high, &footprint).is_none());
+  }
+}
\ No newline at end of file
@@ -0,0 +1,52 @@
+// Generated by py/thing_configs.py. Do not edit.
+
+pub const THING_MAIN_MODEL: u8 = 0;
+pub const THING_BASE_X_SIZE: u8 = 1;
+pub const THING_BASE_Y_SIZE: u8 = 2;
+pub const THING_BASE_X_OFFSET: u8 = 3;
+pub const THING_BASE_Y_OFFSET: u8 = 4;
+pub const THING_BUILDS_FOUNDATION: u8 = 5;
+pub const THING_DIGS_FOUNDATION: u8 = 6;
+pub const THING_FOUNDATION_MIN_SLOPE: u8 = 7;
+pub const THING_BUILT_FOUNDATION_WALL_TEXTURE: u8 = 8;
+pub const THING_BUILT_FOUNDATION_CAP_TEXTURE: u8 = 9;
+pub const THING_DUG_FOUNDATION_WALL_TEXTURE: u8 = 10;
+pub const THING_DUG_FOUNDATION_FLOOR_TEXTURE: u8 = 11;
+pub const THING_HAS_FOOTER: u8 = 12;
+pub const THING_FOOTER_MODEL: u8 = 13;
+pub const THING_HAS_PYLON: u8 = 14;
+pub const THING_PYLON_MODEL: u8 = 15;
+pub const THING_PYLON_ALIGN: u8 = 16;
+pub const THING_PYLON_REPEAT: u8 = 17;
+pub const THING_ALIGN_FRONT: u8 = 18;
+pub const THING_ALIGN_BACK: u8 = 19;
+pub const THING_ALIGN_LEFT: u8 = 20;
+pub const THING_ALIGN_RIGHT: u8 = 21;
+pub const THING_FOUNDATION_MAX_HEIGHT: u8 = 22;
+
+// Names used for each key in .thing.json files, indexed by key.
+pub const NAMES: [&'static str; 23] = [
+  "thing_main_model",
+  "thing_base_x_size",
+  "thing_base_y_size",
+  "thing_base_x_offset",
+  "thing_base_y_offset",
+  "thing_builds_foundation",
+  "thing_digs_foundation",
+  "thing_foundation_min_slope",
+  "thing_built_foundation_wall_texture",
+  "thing_built_foundation_cap_texture",
+  "thing_dug_foundation_wall_texture",
+  "thing_dug_foundation_floor_texture",
+  "thing_has_footer",
+  "thing_footer_model",
+  "thing_has_pylon",
+  "thing_pylon_model",
+  "thing_pylon_align",
+  "thing_pylon_repeat",
+  "thing_align_front",
+  "thing_align_back",
+  "thing_align_left",
+  "thing_align_right",
+  "thing_foundation_max_height",
+];
@@ -0,0 +1,179 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use cgmath::Vector3;
+
+use binary::{Reader, Writer};
+use errors::GameError;
+use migrate;
+use migrate::Migration;
+use super::config::Config;
+
+// The latest version of the .thing format. See doc/thing-file-format.md.
+pub const FORMAT_VERSION: u16 = 0;
+
+// Upgrades from each older version of the format. See migrate.rs.
+const MIGRATIONS: &'static [Migration] = &[];
+
+// A packet of configuration data describing one kind of Thing, as loaded from a .thing
+// file. Any number of Things in the world may share one MetaThing.
+pub struct MetaThing {
+  pub author_name: String,
+  pub thing_name: String,
+  pub models: Vec<ModelRef>,
+  pub config: Config
+}
+
+// A reference from a MetaThing to one of the MetaModels it's drawn with.
+pub struct ModelRef {
+  pub author_name: String,
+  pub model_name: String,
+  pub direction: u8,
+
+  // Position of the Model's origin relative to the Thing's origin.
+  pub offset: Vector3<f32>
+}
+
+impl MetaThing {
+  pub fn from_file(path: &Path) -> Result<MetaThing, GameError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut file = try!(File::open(path));
+    try!(file.read_to_end(&mut bytes));
+    MetaThing::from_bytes(&bytes)
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<MetaThing, GameError> {
+    let bytes = try!(migrate::upgrade(bytes, FORMAT_VERSION, MIGRATIONS));
+    let mut file = Reader::new(&bytes);
+
+    let mut header = try!(file.section_u16("header"));
+    try!(header.u16()); // Version. Already checked by migrate::upgrade.
+    let author_name = try!(header.ascii());
+    let thing_name = try!(header.ascii());
+    let key_size = try!(header.u8());
+    if key_size != 1 {
+      return Err(GameError::Invalid(format!("Unsupported config key size: {}", key_size)));
+    }
+    try!(header.finish());
+
+    let mut models_section = try!(file.section_u32("models"));
+    let count = try!(models_section.u16());
+    let mut models: Vec<ModelRef> = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+      models.push(try!(ModelRef::read(&mut models_section)));
+    }
+    try!(models_section.finish());
+
+    let mut configs_section = try!(file.section_u32("configs"));
+    let config = try!(Config::read(&mut configs_section));
+    try!(configs_section.finish());
+
+    try!(file.finish());
+
+    Ok(MetaThing {
+      author_name: author_name, thing_name: thing_name, models: models, config: config
+    })
+  }
+
+  pub fn to_bytes(&self) -> Result<Vec<u8>, GameError> {
+    let mut w = Writer::new();
+
+    let header = w.begin_section_u16();
+    w.u16(FORMAT_VERSION);
+    try!(w.string(&self.author_name));
+    try!(w.string(&self.thing_name));
+    w.u8(1); // Key size.
+    try!(w.end_section_u16(header));
+
+    let models_section = w.begin_section_u32();
+    if self.models.len() > 0xffff {
+      return Err(GameError::Invalid(format!("Too many models: {}", self.models.len())));
+    }
+    w.u16(self.models.len() as u16);
+    for model in self.models.iter() {
+      try!(model.write(&mut w));
+    }
+    try!(w.end_section_u32(models_section));
+
+    let configs_section = w.begin_section_u32();
+    try!(self.config.write(&mut w));
+    try!(w.end_section_u32(configs_section));
+
+    Ok(w.into_bytes())
+  }
+
+  // The name by which save files refer to this MetaThing, e.g. "jarrett-test".
+  pub fn qualified_name(&self) -> String {
+    format!("{}-{}", self.author_name, self.thing_name)
+  }
+}
+
+impl ModelRef {
+  fn read(section: &mut Reader) -> Result<ModelRef, GameError> {
+    let author_name = try!(section.ascii());
+    let model_name = try!(section.ascii());
+    let direction = try!(section.u8());
+    let x = try!(section.f32());
+    let y = try!(section.f32());
+    let z = try!(section.f32());
+    Ok(ModelRef {
+      author_name: author_name, model_name: model_name, direction: direction,
+      offset: Vector3::new(x, y, z)
+    })
+  }
+
+  fn write(&self, w: &mut Writer) -> Result<(), GameError> {
+    try!(w.string(&self.author_name));
+    try!(w.string(&self.model_name));
+    w.u8(self.direction);
+    w.f32(self.offset.x);
+    w.f32(self.offset.y);
+    w.f32(self.offset.z);
+    Ok(())
+  }
+
+  // The qualified name of the referenced MetaModel, e.g. "jarrett-test".
+  pub fn qualified_name(&self) -> String {
+    format!("{}-{}", self.author_name, self.model_name)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+  use thing::keys::*;
+  use thing::PylonAlign;
+  use super::*;
+
+  #[test]
+  fn from_file() {
+    let meta_thing = MetaThing::from_file(&Path::new("assets/things/jarrett-test.thing")).unwrap();
+    assert_eq!("jarrett-test", meta_thing.qualified_name());
+    assert_eq!(1, meta_thing.models.len());
+    assert_eq!("jarrett-test", meta_thing.models[0].qualified_name());
+    assert_eq!(0, meta_thing.models[0].direction);
+    assert!(!meta_thing.config.builds_foundation);
+    assert_eq!(PylonAlign::WaterTable, meta_thing.config.pylon_align);
+  }
+
+  #[test]
+  fn configs() {
+    let bytes: Vec<u8> = vec![
+      // Header.
+      0, 11, 0, 0, 0, 1, b'a', 0, 1, b'b', 1,
+      // Models.
+      0, 0, 0, 6, 0, 0,
+      // Configs: builds_foundation = 1, foundation_min_slope = 0.5, unknown key 200.
+      0, 0, 0, 22, 0, 3,
+      THING_BUILDS_FOUNDATION, 0, 1, 1,
+      THING_FOUNDATION_MIN_SLOPE, 0, 4, 0x3f, 0, 0, 0,
+      200, 0, 2, 7, 8
+    ];
+    let meta_thing = MetaThing::from_bytes(&bytes).unwrap();
+    assert_eq!(bytes, meta_thing.to_bytes().unwrap());
+    assert!(meta_thing.config.builds_foundation);
+    assert!(!meta_thing.config.digs_foundation);
+    assert_eq!(0.5, meta_thing.config.foundation_min_slope);
+    assert_eq!(vec![(200u8, vec![7u8, 8u8])], meta_thing.config.unknown);
+  }
+}
\ No newline at end of file
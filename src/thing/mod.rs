@@ -0,0 +1,14 @@
+pub mod keys;
+mod align;
+mod config;
+mod expand;
+mod foundation;
+mod meta_thing;
+mod placement;
+
+pub use self::align::{Conflict, Site, align, align_world};
+pub use self::config::{Config, PylonAlign, PylonRepeat};
+pub use self::expand::{ModelList, ModelInstance, Part, expand, pylon_stack};
+pub use self::foundation::{Foundation, FoundationKind, FoundationMesh};
+pub use self::meta_thing::{MetaThing, ModelRef};
+pub use self::placement::{Placer, Placement, Unplaceable, footprint, rotate, MAX_SLOPE};
\ No newline at end of file
@@ -0,0 +1,307 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+use cgmath::{Point2, Point3};
+
+use assets::AssetRegistry;
+use terrain::{Footprint, Hydrology, Terrain};
+use world::{Extensions, Thing, World};
+use super::config::Config;
+use super::foundation::Foundation;
+use super::meta_thing::MetaThing;
+
+// Works out where a new Thing would sit, or why it can't go there. See "Base Alignment"
+// in doc/thing-file-format.md.
+pub struct Placer<'a> {
+  terrain: &'a Terrain,
+  hydrology: &'a Hydrology,
+
+  // Steepest ground a Thing can be placed on.
+  pub max_slope: f32,
+
+  // Footprints of the Things already placed, with their indices in World::things.
+  occupied: Vec<(usize, Footprint)>
+}
+
+// A Thing that fits where it was asked to go.
+pub struct Placement {
+  // Ready to push onto World::things.
+  pub thing: Thing,
+
+  // The ground the Thing's base covers.
+  pub footprint: Footprint,
+
+  // Lowest and highest points of the ground within the footprint.
+  pub ground: (f32, f32),
+
+  // Set if the ground is sloped enough for the Thing to need a foundation.
+  pub foundation: Option<Foundation>,
+
+  // Z coord of the Thing's base in the world: on top of its foundation if it has one,
+  // otherwise sunk to the lowest point of the ground.
+  pub base_z: f32
+}
+
+// Why a Thing can't go where it was asked to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Unplaceable {
+  // Part of the base is off the edge of the map.
+  OffMap,
+
+  // The base is over water this deep. Things with pylons can stand in water.
+  Water {depth: f32},
+
+  // The ground under the base is steeper than the Placer's max_slope, and the Thing
+  // can't level it with a foundation.
+  TooSteep {slope: f32},
+
+  // Levelling the ground would take a foundation this high, more than the Thing's
+  // foundation_max_height.
+  FoundationTooHigh {height: f32},
+
+  // The base overlaps the base of World::things[thing].
+  Overlaps {thing: usize}
+}
+
+// Slope of a 45 degree incline.
+pub const MAX_SLOPE: f32 = 1.0;
+
+// Unit vector each direction points in. Direction 0 faces along the X axis, and each
+// direction after is another 45 degrees counter-clockwise seen from above. Written out,
+// rather than computed with sin and cos, so that the four axis-aligned directions give
+// footprints exactly on the grid.
+const DIRECTION_VECTORS: [(f32, f32); 8] = [
+  (1.0, 0.0), (FRAC_1_SQRT_2, FRAC_1_SQRT_2), (0.0, 1.0), (-FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+  (-1.0, 0.0), (-FRAC_1_SQRT_2, -FRAC_1_SQRT_2), (0.0, -1.0), (FRAC_1_SQRT_2, -FRAC_1_SQRT_2)
+];
+
+// The ground covered by the base of a Thing with its origin at the given XY, facing the
+// given direction. Facing direction 0, the base's lower left corner is at
+// (base_x_offset, base_y_offset) from the origin. Other directions rotate the base about
+// the origin. Footprints are axis-aligned, so a base facing one of the diagonal
+// directions covers the whole box around it.
+pub fn footprint(config: &Config, at: Point2<f32>, direction: u8) -> Footprint {
+  let x0 = config.base_x_offset;
+  let y0 = config.base_y_offset;
+  let x1 = x0 + config.base_x_size;
+  let y1 = y0 + config.base_y_size;
+  let mut min = Point2::new(::std::f32::INFINITY, ::std::f32::INFINITY);
+  let mut max = Point2::new(::std::f32::NEG_INFINITY, ::std::f32::NEG_INFINITY);
+  for &(x, y) in [(x0, y0), (x1, y0), (x1, y1), (x0, y1)].iter() {
+    let (x, y) = rotate(x, y, direction);
+    min = Point2::new(min.x.min(at.x + x), min.y.min(at.y + y));
+    max = Point2::new(max.x.max(at.x + x), max.y.max(at.y + y));
+  }
+  Footprint {min: min, max: max}
+}
+
+// Rotates the vector (x, y) about the Z axis by the given direction.
+pub fn rotate(x: f32, y: f32, direction: u8) -> (f32, f32) {
+  let (cos, sin) = DIRECTION_VECTORS[(direction % 8) as usize];
+  (x * cos - y * sin, x * sin + y * cos)
+}
+
+impl<'a> Placer<'a> {
+  // A Placer for an empty world.
+  pub fn new(terrain: &'a Terrain, hydrology: &'a Hydrology) -> Placer<'a> {
+    Placer {terrain: terrain, hydrology: hydrology, max_slope: MAX_SLOPE, occupied: Vec::new()}
+  }
+
+  // A Placer that keeps new Things off the Things already in the world. Things whose
+  // MetaThing isn't in the registry don't block anything.
+  pub fn for_world(
+    world: &'a World, hydrology: &'a Hydrology, assets: &AssetRegistry
+  ) -> Placer<'a> {
+    let mut placer = Placer::new(&world.terrain, hydrology);
+    for (i, thing) in world.things.iter().enumerate() {
+      if let Some(meta_thing) = assets.thing(&thing.meta_thing) {
+        let at = Point2::new(thing.origin.x, thing.origin.y);
+        placer.occupy(i, footprint(&meta_thing.config, at, thing.direction));
+      }
+    }
+    placer
+  }
+
+  // Marks the ground under World::things[thing] as taken, e.g. after pushing a
+  // Placement's Thing.
+  pub fn occupy(&mut self, thing: usize, footprint: Footprint) {
+    self.occupied.push((thing, footprint));
+  }
+
+  // Places a Thing of the given MetaThing with its origin at the given XY, facing the
+  // given direction from 0 to 7. Returns every reason it can't go there, if any.
+  pub fn place(
+    &self, meta_thing: &MetaThing, at: Point2<f32>, direction: u8
+  ) -> Result<Placement, Vec<Unplaceable>> {
+    let config = &meta_thing.config;
+    let footprint = footprint(config, at, direction);
+    let mut problems: Vec<Unplaceable> = Vec::new();
+
+    let on_map = footprint.min.x >= 0.0 && footprint.min.y >= 0.0 &&
+      footprint.max.x <= self.terrain.x_size() && footprint.max.y <= self.terrain.y_size();
+    if !on_map {
+      problems.push(Unplaceable::OffMap);
+    }
+    let depth = self.hydrology.max_depth_under(&footprint);
+    if depth > 0.0 && !config.has_pylon {
+      problems.push(Unplaceable::Water {depth: depth});
+    }
+    let ground = self.terrain.surface_range_under(&footprint);
+    let foundation = Foundation::for_site(config, self.terrain, &footprint);
+    let slope = self.terrain.max_slope(&footprint);
+    match foundation {
+      Some(ref foundation) => {
+        let height = foundation.height(ground);
+        if height > config.foundation_max_height {
+          problems.push(Unplaceable::FoundationTooHigh {height: height});
+        }
+      },
+      None => if slope > self.max_slope {
+        problems.push(Unplaceable::TooSteep {slope: slope});
+      }
+    }
+    for &(thing, ref other) in self.occupied.iter() {
+      if overlaps(&footprint, other) {
+        problems.push(Unplaceable::Overlaps {thing: thing});
+      }
+    }
+    if !problems.is_empty() {
+      return Err(problems);
+    }
+
+    // Without a foundation, sink the base until none of it floats above the ground.
+    // Things store their Z relative to the ground at their origin.
+    let base_z = foundation.as_ref().map_or(ground.0, |foundation| foundation.z);
+    let origin_z = base_z - self.terrain.surface_height(at.x, at.y);
+    Ok(Placement {
+      thing: Thing {
+        meta_thing: meta_thing.qualified_name(),
+        direction: direction % 8,
+        origin: Point3::new(at.x, at.y, origin_z),
+        extensions: Extensions::default()
+      },
+      footprint: footprint,
+      ground: ground,
+      foundation: foundation,
+      base_z: base_z
+    })
+  }
+}
+
+// Footprints that only share an edge don't overlap, so Things can be packed side by side.
+fn overlaps(a: &Footprint, b: &Footprint) -> bool {
+  a.min.x < b.max.x && b.min.x < a.max.x && a.min.y < b.max.y && b.min.y < a.max.y
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::Point2;
+  use terrain::{Footprint, Hydrology, Terrain};
+  use thing::{Config, FoundationKind, MetaThing};
+  use super::*;
+
+  // A 2 x 1 base.
+  fn meta_thing() -> MetaThing {
+    MetaThing {
+      author_name: "a".to_string(), thing_name: "b".to_string(), models: Vec::new(),
+      config: Config {base_x_size: 2.0, ..Config::default()}
+    }
+  }
+
+  // 6 x 6 vertices. Flat at 2, with a hill at (4, 4) and a pond at (1, 4).
+  fn terrain() -> Terrain {
+    let mut heights = vec![2.0; 36];
+    heights[4 * 6 + 4] = 6.0;
+    heights[4 * 6 + 1] = 0.0;
+    Terrain::new(6, 6, heights)
+  }
+
+  #[test]
+  fn footprints() {
+    let config = Config {base_x_size: 2.0, base_y_offset: -0.5, ..Config::default()};
+    let at = Point2::new(3.0, 3.0);
+    assert_eq!(Footprint::new(3.0, 2.5, 5.0, 3.5), footprint(&config, at, 0));
+    assert_eq!(Footprint::new(2.5, 3.0, 3.5, 5.0), footprint(&config, at, 2));
+    assert_eq!(Footprint::new(1.0, 2.5, 3.0, 3.5), footprint(&config, at, 4));
+    let diagonal = footprint(&config, at, 1);
+    assert!((diagonal.max.x - (3.0 + 2.5 * FRAC_1_SQRT_2)).abs() < 0.0001);
+  }
+
+  #[test]
+  fn place() {
+    let terrain = terrain();
+    let hydrology = Hydrology::new(&terrain, 0.0);
+    let mut placer = Placer::new(&terrain, &hydrology);
+
+    let placement = placer.place(&meta_thing(), Point2::new(1.0, 1.0), 0).unwrap();
+    assert_eq!(Footprint::new(1.0, 1.0, 3.0, 2.0), placement.footprint);
+    assert_eq!((2.0, 2.0), placement.ground);
+    assert_eq!(0.0, placement.thing.origin.z);
+    assert_eq!("a-b", placement.thing.meta_thing);
+    placer.occupy(0, placement.footprint);
+
+    // Side by side is fine, but not on top.
+    assert!(placer.place(&meta_thing(), Point2::new(1.0, 2.0), 0).is_ok());
+    assert_eq!(
+      Some(vec![Unplaceable::Overlaps {thing: 0}]),
+      placer.place(&meta_thing(), Point2::new(2.0, 1.5), 0).err()
+    );
+    assert_eq!(
+      Some(vec![Unplaceable::OffMap]),
+      placer.place(&meta_thing(), Point2::new(4.0, 0.0), 0).err()
+    );
+  }
+
+  #[test]
+  fn ground() {
+    let terrain = terrain();
+    let hydrology = Hydrology::new(&terrain, 0.0);
+    let mut placer = Placer::new(&terrain, &hydrology);
+
+    // The pond is in a hollow, so it's steep too.
+    let problems = placer.place(&meta_thing(), Point2::new(1.0, 3.0), 0).err().unwrap();
+    match problems[0] {
+      Unplaceable::Water {depth} => assert!(depth > 0.0),
+      _ => panic!("{:?}", problems)
+    }
+    assert_eq!(Unplaceable::TooSteep {slope: 2.0}, problems[1]);
+    assert_eq!(
+      Some(vec![Unplaceable::TooSteep {slope: 4.0}]),
+      placer.place(&meta_thing(), Point2::new(3.0, 3.0), 0).err()
+    );
+
+    // With the origin on top of the hill, facing away from it, the base sinks to the
+    // lowest ground under it, well below the ground at the origin.
+    placer.max_slope = 4.0;
+    let placement = placer.place(&meta_thing(), Point2::new(4.0, 4.0), 4).unwrap();
+    assert_eq!(Footprint::new(2.0, 3.0, 4.0, 4.0), placement.footprint);
+    assert_eq!((2.0, 6.0), placement.ground);
+    assert_eq!(2.0, placement.base_z);
+    assert_eq!(-4.0, placement.thing.origin.z);
+    assert!(placement.foundation.is_none());
+  }
+
+  #[test]
+  fn foundation() {
+    let terrain = terrain();
+    let hydrology = Hydrology::new(&terrain, 0.0);
+    let placer = Placer::new(&terrain, &hydrology);
+    let mut meta_thing = meta_thing();
+    meta_thing.config.builds_foundation = true;
+
+    // Too steep to stand on the hill, but a foundation levels it. The base sits on top.
+    let placement = placer.place(&meta_thing, Point2::new(4.0, 4.0), 4).unwrap();
+    assert_eq!(FoundationKind::Built, placement.foundation.as_ref().unwrap().kind);
+    assert_eq!(6.0, placement.base_z);
+    assert_eq!(0.0, placement.thing.origin.z);
+
+    // Flat ground doesn't need one.
+    let placement = placer.place(&meta_thing, Point2::new(1.0, 1.0), 0).unwrap();
+    assert!(placement.foundation.is_none());
+
+    // The hill is 4 high, so a lower limit rules the foundation out.
+    meta_thing.config.foundation_max_height = 2.0;
+    assert_eq!(
+      Some(vec![Unplaceable::FoundationTooHigh {height: 4.0}]),
+      placer.place(&meta_thing, Point2::new(4.0, 4.0), 4).err()
+    );
+  }
+}
\ No newline at end of file
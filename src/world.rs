@@ -0,0 +1,63 @@
+use cgmath::Point3;
+
+use terrain::Terrain;
+
+// Everything that's saved in a .city file.
+pub struct World {
+  pub name: String,
+  pub terrain: Terrain,
+
+  // Where the terrain came from. When we write the World back out, we store the terrain
+  // the same way.
+  pub terrain_storage: TerrainStorage,
+
+  // Z coord of the water surface. Everything below it is under water.
+  pub water_height: f32,
+
+  pub things: Vec<Thing>
+}
+
+pub enum TerrainStorage {
+  // The heights are stored in the save file itself.
+  Inline,
+
+  // The heights come from an external heightmap image. path is relative to the working
+  // directory.
+  Image {path: String, vertical_scale: f32}
+}
+
+// A single placed instance of a MetaThing.
+pub struct Thing {
+  // Qualified name of the MetaThing, e.g. "jarrett-test".
+  pub meta_thing: String,
+
+  // In the range 0-7.
+  pub direction: u8,
+
+  // X and Y are relative to the world origin. Z is relative to the ground.
+  pub origin: Point3<f32>,
+
+  pub extensions: Extensions
+}
+
+// Optional per-Thing state that gameplay systems can attach without changing the save
+// format. Stored as tagged records in each Thing's reserved section. See
+// doc/save-file-format.md.
+#[derive(Default)]
+pub struct Extensions {
+  // A name the player gave this Thing, e.g. "Town Hall".
+  pub custom_name: Option<String>,
+
+  // ID of the district that owns this Thing.
+  pub district: Option<u32>,
+
+  // How far along construction is, from 0.0 (just started) to 1.0 (complete).
+  pub construction_progress: Option<f32>,
+
+  // Opaque data keyed by plugin name.
+  pub plugin_data: Vec<(String, Vec<u8>)>,
+
+  // Records whose tags this version of the game doesn't recognize. We keep them so they
+  // survive a round trip.
+  pub unknown: Vec<(u16, Vec<u8>)>
+}
\ No newline at end of file